@@ -0,0 +1,13 @@
+use std::{env, fs, path::PathBuf};
+
+// Adds settings.x to the linker scripts of the firmware, the host tests do not link against it
+fn main() {
+    println!("cargo:rerun-if-changed=settings.x");
+    if !env::var("TARGET").unwrap().starts_with("thumb") {
+        return;
+    }
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy("settings.x", out.join("settings.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rustc-link-arg-bins=-Tsettings.x");
+}
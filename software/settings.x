@@ -0,0 +1,6 @@
+/* The last two pages of the flash hold the settings store, see src/flash.rs. They are not part
+   of the firmware image, so the settings survive flashing new firmware. */
+_settings_start = ORIGIN(FLASH) + LENGTH(FLASH) - 8K;
+
+ASSERT(__veneer_limit <= _settings_start, "
+ERROR(seabreeze): the firmware overlaps the settings pages at the end of the flash");
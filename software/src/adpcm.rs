@@ -0,0 +1,152 @@
+// IMA ADPCM decoder for WAV files in the Microsoft block layout, 4 bits per sample.
+// Every block starts with a header per channel holding the first sample and the step
+// index, followed by groups of 4 bytes (8 samples) per channel, low nibble first.
+
+const STEPS: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97,
+    107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871,
+    5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623,
+    27086, 29794, 32767,
+];
+const INDEX_ADJUST: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+pub const HEADER_SIZE: usize = 4; // Per channel
+const GROUP_SIZE: usize = 4; // Bytes of 8 samples of one channel
+
+// Samples per channel in a block of `block_align` bytes, the header sample included
+pub const fn samples_per_block(block_align: usize, channels: usize) -> usize {
+    (block_align - HEADER_SIZE * channels) * 2 / channels + 1
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    predictor: i32,
+    index: i32,
+}
+
+impl Channel {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEPS[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        self.predictor = match nibble & 8 {
+            0 => self.predictor + diff,
+            _ => self.predictor - diff,
+        }
+        .clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + INDEX_ADJUST[(nibble & 7) as usize]).clamp(0, STEPS.len() as i32 - 1);
+        self.predictor as i16
+    }
+}
+
+// Decodes one block, possibly the shorter last one, to interleaved little endian
+// 16 bit samples. Returns the number of bytes written to `pcm`.
+pub fn decode_block(block: &[u8], channels: usize, pcm: &mut [u8]) -> usize {
+    if block.len() < HEADER_SIZE * channels {
+        return 0;
+    }
+    let mut states = [Channel { predictor: 0, index: 0 }; 2];
+    for (channel, state) in states.iter_mut().enumerate().take(channels) {
+        let header = &block[channel * HEADER_SIZE..];
+        state.predictor = i16::from_le_bytes([header[0], header[1]]) as i32;
+        state.index = (header[2] as i32).min(STEPS.len() as i32 - 1);
+    }
+    let frame_size = 2 * channels;
+    let mut written = 0;
+    let mut write = |channel: usize, frame: usize, sample: i16| {
+        let offset = frame * frame_size + channel * 2;
+        if let Some(bytes) = pcm.get_mut(offset..offset + 2) {
+            bytes.copy_from_slice(&sample.to_le_bytes());
+            written = written.max(offset + 2);
+        }
+    };
+    for (channel, state) in states.iter().enumerate().take(channels) {
+        write(channel, 0, state.predictor as i16);
+    }
+    // Each round holds 8 samples of every channel
+    let body = &block[HEADER_SIZE * channels..];
+    for (round, groups) in body.chunks_exact(GROUP_SIZE * channels).enumerate() {
+        for (channel, group) in groups.chunks_exact(GROUP_SIZE).enumerate() {
+            for (i, byte) in group.iter().enumerate() {
+                let frame = 1 + round * 8 + i * 2;
+                write(channel, frame, states[channel].decode(byte & 0x0F));
+                write(channel, frame + 1, states[channel].decode(byte >> 4));
+            }
+        }
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wav::{self, Encoding};
+
+    // Decodes a whole file, block by block
+    fn decode(bytes: &[u8]) -> (usize, Vec<i16>) {
+        let wav = wav::parse(bytes).unwrap();
+        let Encoding::ImaAdpcm { block_align } = wav.encoding else {
+            panic!("{:?}", wav.encoding);
+        };
+        let channels = wav.format.channels as usize;
+        let mut samples = Vec::new();
+        let mut pcm = vec![0; samples_per_block(block_align as usize, channels) * channels * 2];
+        for block in wav.data.chunks(block_align as usize) {
+            let len = decode_block(block, channels, &mut pcm);
+            samples.extend(pcm[..len].chunks_exact(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])));
+        }
+        (channels, samples)
+    }
+
+    fn fnv1a(samples: &[i16]) -> u32 {
+        samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .fold(0x811C_9DC5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+
+    // The references are decoded with Python's audioop.adpcm2lin from the same files
+    #[test]
+    fn mono_clip_matches_reference() {
+        let (channels, samples) = decode(include_bytes!("../assets/SeaBreeze3.adpcm.wav"));
+        assert_eq!(channels, 1);
+        assert_eq!(samples.len(), 88_207);
+        assert_eq!(samples[..8], [-5077, -5066, -5036, -5099, -4963, -4982, -4716, -4142]);
+        // Across the first block boundary, sample 1017 is the header of the second block
+        assert_eq!(samples[1015..1019], [-1159, -2144, -1670, -1008]);
+        assert_eq!(fnv1a(&samples), 0x4E24_2B44);
+    }
+
+    #[test]
+    fn stereo_clip_matches_reference() {
+        let (channels, samples) = decode(include_bytes!("../assets/SeaBreeze2.adpcm.wav"));
+        assert_eq!(channels, 2);
+        assert_eq!(samples.len(), 193_760);
+        assert_eq!(fnv1a(&samples), 0x0E5E_DCC7);
+    }
+
+    #[test]
+    fn nibbles_follow_the_step_table() {
+        // At step 7 the nibble 0x7 adds 0 + 7 + 3 + 1 and moves 8 steps up the table,
+        // at step 16 the nibble 0x1 then adds 2 + 4
+        let block = [0, 0, 0, 0, 0x17, 0, 0, 0];
+        let mut pcm = [0u8; 18];
+        assert_eq!(decode_block(&block, 1, &mut pcm), 18);
+        let samples: Vec<i16> = pcm.chunks_exact(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])).collect();
+        assert_eq!(samples[..3], [0, 11, 17]);
+    }
+
+    #[test]
+    fn short_block_is_ignored() {
+        assert_eq!(decode_block(&[0, 0, 0], 1, &mut [0; 4]), 0);
+    }
+}
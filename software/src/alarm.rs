@@ -0,0 +1,256 @@
+use crate::{haptic::DEFAULT_PATTERN, sound::DEFAULT_SOUND, time::TICKS_PER_DAY};
+
+pub const ALARM_SLOTS: usize = 8;
+pub const DEFAULT_SNOOZE_MINUTES: u32 = 9;
+pub const DEFAULT_SNOOZE_LIMIT: u32 = 3;
+pub const MAX_SNOOZE_MINUTES: u32 = 60;
+pub const DEFAULT_SUNRISE_MINUTES: u32 = 20; // 0 disables the sunrise
+pub const MAX_SUNRISE_MINUTES: u32 = 60;
+
+// Weekday mask, bit 0 is Monday and bit 6 is Sunday
+pub const EVERY_DAY: u8 = 0b111_1111;
+pub const WEEKDAYS: u8 = 0b001_1111;
+pub const WEEKENDS: u8 = 0b110_0000;
+
+// Presets selectable with the rotary encoder, the last entry disables the alarm
+pub const WEEKDAY_PRESETS: [u8; 4] = [EVERY_DAY, WEEKDAYS, WEEKENDS, 0];
+
+const WEEKDAY_LETTERS: [u8; 7] = *b"MTWTFSS";
+
+pub const ALARM_STAGES: usize = 3;
+pub const MAX_STAGE_MINUTES: u32 = 60;
+
+// Actuators driven in an alarm stage. The amplifier, fan and humidifier are separate loads,
+// a board that gangs them switches on all three for any of SOUND, FAN and HUMIDIFIER.
+pub const HAPTIC: u8 = 1 << 0;
+pub const SOUND: u8 = 1 << 1;
+pub const FAN: u8 = 1 << 2;
+pub const HUMIDIFIER: u8 = 1 << 3;
+pub const ACTUATOR_NAMES: [(u8, &[u8]); 4] = [
+    (HAPTIC, b"haptic"),
+    (SOUND, b"sound"),
+    (FAN, b"fan"),
+    (HUMIDIFIER, b"humidifier"),
+];
+
+// Haptic first, then the sea sound, then the fan and humidifier
+pub const DEFAULT_STAGES: [Stage; ALARM_STAGES] = [
+    Stage { actuators: HAPTIC, intensity: 30, minutes: 1 },
+    Stage { actuators: HAPTIC | SOUND, intensity: 60, minutes: 2 },
+    Stage { actuators: HAPTIC | SOUND | FAN | HUMIDIFIER, intensity: 100, minutes: 0 },
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Alarm {
+    pub ticks: u32, // Alarm offset in ticks from 00:00
+    pub enabled: bool,
+    pub weekdays: u8,
+    pub sound: u8,  // Index into sound::SOUNDS
+    pub haptic: u8, // Index into haptic::PATTERNS
+}
+
+impl Alarm {
+    pub const DISABLED: Alarm = Alarm {
+        ticks: 0,
+        enabled: false,
+        weekdays: EVERY_DAY,
+        sound: DEFAULT_SOUND,
+        haptic: DEFAULT_PATTERN,
+    };
+
+    pub const fn new(ticks: u32, weekdays: u8) -> Alarm {
+        Alarm {
+            ticks,
+            enabled: true,
+            weekdays,
+            sound: DEFAULT_SOUND,
+            haptic: DEFAULT_PATTERN,
+        }
+    }
+
+    fn rings_on(&self, weekday: u32) -> bool {
+        self.weekdays & (1 << weekday) != 0
+    }
+
+    // Ticks from `now` (ticks since Monday 00:00) until this alarm rings next,
+    // always strictly in the future so a ringing alarm is not re-armed for the same minute
+    pub fn ticks_until(&self, now: u32) -> Option<u32> {
+        if !self.enabled || self.weekdays & EVERY_DAY == 0 {
+            return None;
+        }
+        let today = now / TICKS_PER_DAY;
+        let time_of_day = now % TICKS_PER_DAY;
+
+        // Day 7 is the same weekday next week
+        (0..=7u32)
+            .filter(|day| self.rings_on((today + day) % 7))
+            .map(|day| day * TICKS_PER_DAY + self.ticks)
+            .find(|ticks| *ticks > time_of_day)
+            .map(|ticks| ticks - time_of_day)
+    }
+
+    // Index into WEEKDAY_PRESETS matching this alarm, used as starting point when rotating
+    pub fn preset_index(&self) -> usize {
+        if !self.enabled {
+            return WEEKDAY_PRESETS.len() - 1;
+        }
+        WEEKDAY_PRESETS
+            .iter()
+            .position(|preset| *preset == self.weekdays)
+            .unwrap_or(0)
+    }
+
+    pub fn with_preset(&self, index: usize) -> Alarm {
+        match WEEKDAY_PRESETS[index] {
+            0 => Alarm {
+                enabled: false,
+                ..*self
+            },
+            weekdays => Alarm {
+                enabled: true,
+                weekdays,
+                ..*self
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stage {
+    pub actuators: u8,
+    pub intensity: u8, // Percent, scales the haptic duty cycle and the sound
+    pub minutes: u32,  // Time before escalating to the next stage, the last stage is held
+}
+
+impl Stage {
+    pub fn has(&self, actuators: u8) -> bool {
+        self.actuators & actuators != 0
+    }
+}
+
+// Stage the alarm is in after ringing for `minutes`, stages of 0 minutes are skipped
+pub fn current_stage(stages: &[Stage; ALARM_STAGES], minutes: u32) -> usize {
+    let mut elapsed = 0;
+    for (index, stage) in stages.iter().enumerate().take(ALARM_STAGES - 1) {
+        elapsed = stage.minutes.saturating_add(elapsed);
+        if minutes < elapsed {
+            return index;
+        }
+    }
+    ALARM_STAGES - 1
+}
+
+// Parses a comma separated list of actuator names, e.g. "haptic,sound"
+pub fn actuators_from_bytes(bytes: &[u8]) -> Option<u8> {
+    if bytes == b"none" {
+        return Some(0);
+    }
+    bytes.split(|c| *c == b',').try_fold(0, |actuators, name| {
+        ACTUATOR_NAMES
+            .iter()
+            .find(|(_, actuator_name)| *actuator_name == name)
+            .map(|(actuator, _)| actuators | actuator)
+    })
+}
+
+// Returns the slot and the number of ticks until the next alarm of all enabled slots
+pub fn next_alarm(alarms: &[Alarm; ALARM_SLOTS], now: u32) -> Option<(usize, u32)> {
+    alarms
+        .iter()
+        .enumerate()
+        .filter_map(|(slot, alarm)| alarm.ticks_until(now).map(|ticks| (slot, ticks)))
+        .min_by_key(|(_, ticks)| *ticks)
+}
+
+// Formats the weekday mask as e.g. "MTWTF--"
+pub fn weekdays_to_bytes(weekdays: u8) -> [u8; 7] {
+    let mut buffer = [b'-'; 7];
+    for (day, letter) in WEEKDAY_LETTERS.iter().enumerate() {
+        if weekdays & (1 << day) != 0 {
+            buffer[day] = *letter;
+        }
+    }
+    buffer
+}
+
+// Parses a weekday mask in the format written by weekdays_to_bytes, '-' marks a day off
+pub fn weekdays_from_bytes(bytes: &[u8]) -> Option<u8> {
+    match bytes {
+        b"daily" => Some(EVERY_DAY),
+        b"weekdays" => Some(WEEKDAYS),
+        b"weekends" => Some(WEEKENDS),
+        _ if bytes.len() == 7 => Some(
+            bytes
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c != b'-')
+                .fold(0, |mask, (day, _)| mask | (1 << day)),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::time_to_ticks;
+
+    const MONDAY: u32 = 0;
+    const FRIDAY: u32 = 4 * TICKS_PER_DAY;
+
+    #[test]
+    fn alarm_later_today() {
+        let alarm = Alarm::new(time_to_ticks(6, 30), EVERY_DAY);
+        let now = MONDAY + time_to_ticks(6, 0);
+        assert_eq!(alarm.ticks_until(now), Some(time_to_ticks(0, 30)));
+    }
+
+    #[test]
+    fn ringing_alarm_is_armed_for_next_day() {
+        let alarm = Alarm::new(time_to_ticks(6, 30), EVERY_DAY);
+        let now = MONDAY + time_to_ticks(6, 30);
+        assert_eq!(alarm.ticks_until(now), Some(TICKS_PER_DAY));
+    }
+
+    #[test]
+    fn weekday_alarm_skips_weekend() {
+        let alarm = Alarm::new(time_to_ticks(6, 30), WEEKDAYS);
+        let now = FRIDAY + time_to_ticks(7, 0);
+        assert_eq!(alarm.ticks_until(now), Some(3 * TICKS_PER_DAY - time_to_ticks(0, 30)));
+    }
+
+    #[test]
+    fn next_alarm_picks_earliest_slot() {
+        let mut alarms = [Alarm::DISABLED; ALARM_SLOTS];
+        alarms[2] = Alarm::new(time_to_ticks(8, 0), EVERY_DAY);
+        alarms[5] = Alarm::new(time_to_ticks(7, 0), WEEKENDS);
+        alarms[6] = Alarm::new(time_to_ticks(6, 0), 0);
+        assert_eq!(next_alarm(&alarms, MONDAY), Some((2, time_to_ticks(8, 0))));
+        assert_eq!(next_alarm(&[Alarm::DISABLED; ALARM_SLOTS], MONDAY), None);
+    }
+
+    #[test]
+    fn stages_escalate_and_hold() {
+        assert_eq!(current_stage(&DEFAULT_STAGES, 0), 0);
+        assert_eq!(current_stage(&DEFAULT_STAGES, 1), 1);
+        assert_eq!(current_stage(&DEFAULT_STAGES, 3), 2);
+        assert_eq!(current_stage(&DEFAULT_STAGES, 100), 2);
+    }
+
+    #[test]
+    fn huge_stage_minutes_do_not_overflow() {
+        let mut stages = DEFAULT_STAGES;
+        stages[0].minutes = 5;
+        stages[1].minutes = u32::MAX;
+        assert_eq!(current_stage(&stages, 5), 1);
+        assert_eq!(current_stage(&stages, u32::MAX), 2);
+    }
+
+    #[test]
+    fn weekday_mask_round_trip() {
+        assert_eq!(&weekdays_to_bytes(WEEKDAYS), b"MTWTF--");
+        assert_eq!(weekdays_from_bytes(b"MTWTF--"), Some(WEEKDAYS));
+        assert_eq!(weekdays_from_bytes(b"weekends"), Some(WEEKENDS));
+        assert_eq!(weekdays_from_bytes(b"mon"), None);
+    }
+}
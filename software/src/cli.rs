@@ -1,21 +1,80 @@
 use {
-    crate::{app::*, rtc, display}, core::sync::atomic::Ordering,panic_rtt_target as _
+    crate::{alarm::{self, Alarm, Stage, ALARM_SLOTS, ALARM_STAGES}, app::*, format::{self, Format}, haptic::{self, PATTERNS}, night_light::{self, Schedule}, outputs::{self, Load, LOAD_NAMES, MAX_DUTY}, rtc::{self, Date, DateTime}, rtttl, sound::{self, SOUNDS}, speaker, state_machine::{Event, Section}, thermostat::{self, QuietHours}, time::{self, Window}, volume},
+    core::sync::atomic::Ordering,
+    heapless::Vec,
+    panic_rtt_target as _,
+    rtic::Mutex,
 };
 
 #[cfg(feature = "52833-debug")]
-use {
-    core::fmt::Write,
-    rtic::Mutex,
-};
+use core::fmt::Write;
 
 pub const DATA_OUT_BUFFER_SIZE: usize = 64;
-pub const DATA_IN_BUFFER_SIZE: usize = 64;
+pub const DATA_IN_BUFFER_SIZE: usize = 16 + rtttl::MAX_LEN; // Fits a ringtone after `play rtttl`
+pub const DATA_IN_PACKET_SIZE: usize = 64; // Full speed USB packet
 
+// Most commands are a few bytes, a ringtone is queued with the command that plays it
+#[allow(clippy::large_enum_variant)]
 pub(crate) enum CliCommand {
     SetTime(u8, u8),
-    SetAlarm(u8, u8),
+    SetDate(Date),
+    SetAlarm(usize, u8, u8, Option<u8>), // Slot, hour, minute, weekdays
+    EnableAlarm(usize, bool),
     GetTime,
-    GetAlarm,
+    GetDate,
+    GetAlarm(Option<usize>), // None gets the next alarm to ring
+    SetSnoozeMinutes(u32),
+    SetSnoozeLimit(u32),
+    GetSnooze,
+    SetSunriseMinutes(u32),
+    GetSunrise,
+    SetStage(usize, Stage),
+    GetStage(usize),
+    SetSettingsTimeout(u32),
+    SetAlarmTimeout(u32),
+    GetTimeout,
+    SetVolume(u8),
+    GetVolume,
+    ListSounds,
+    SetSound(usize, u8), // Slot, sound
+    GetSound(usize),
+    PlaySound(u8),
+    PlayRtttl(rtttl::Text), // Copied when the line is parsed, the next line reuses the buffer
+    StopSound,
+    ListHaptics,
+    SetHaptic(usize, u8), // Slot, pattern
+    GetHaptic(usize),
+    PlayHaptic(u8),
+    StopHaptic,
+    SetOutput(Load, u8), // Duty cycle in percent
+    GetOutputs,
+    SetThermostat(ThermostatSetting),
+    SetQuietHours(QuietHours),
+    GetThermostat,
+    NightLight(Option<u8>), // Brightness, None switches it on at the stored one
+    SetNightLightBrightness(u8),
+    SetNightLightTimer(u32), // Auto-off minutes, 0 keeps it on
+    SetNightLightSchedule(Schedule),
+    GetNightLight,
+    SetFormat(FormatSetting),
+    GetFormat,
+}
+
+pub(crate) enum FormatSetting {
+    Clock(format::Clock),
+    LeadingZero(bool),
+    Unit(format::Unit),
+}
+
+// Temperatures in tenths of a degree Celsius
+pub(crate) enum ThermostatSetting {
+    Enabled(bool),
+    Threshold(i16),
+    Span(u8),
+    Hysteresis(u8),
+    MinSpeed(u8),
+    MinRunMinutes(u8),
+    MinOffMinutes(u8),
 }
 
 #[allow(unused_mut)]
@@ -36,30 +95,46 @@ pub(crate) fn cli_commands(mut cx: cli_commands::Context, command: CliCommand) {
             data[12..17].copy_from_slice(&time);
             write_to_serial(&data);
             
+            // Keep the current date
             let ticks = rtc::time_to_ticks(hour, minute);
             set_time::spawn(ticks).ok();
-            update_display::spawn(ticks, display::Section::Display, false).ok();
+            update_display::spawn(DateTime::new(now(&mut cx).days, ticks), Section::Display, false).ok();
+        }
+        CliCommand::SetDate(date) => {
+            let days = date.to_days();
+            let now = DateTime::new(days, now(&mut cx).ticks);
+            write_date(b"Date set to ", &now);
+
+            set_date::spawn(days).ok();
+            update_display::spawn(now, Section::Display, false).ok();
         }
-        CliCommand::SetAlarm(hour, minute) => {
+        CliCommand::SetAlarm(slot, hour, minute, weekdays) => {
             #[cfg(feature = "52833-debug")]
             cx.shared.rtt_serial.lock(|rtt_serial| {
-                writeln!(rtt_serial, "Set alarm: {:02}:{:02}", hour, minute).ok();
+                writeln!(rtt_serial, "Set alarm {}: {:02}:{:02}", slot + 1, hour, minute).ok();
             });
-            
-            let mut time = [0u8; 5];
-            time_formatter(hour, minute, &mut time);
-            let msg = b"Alarm set to ";
-            let mut data = [0u8; 18];
-            data[0..13].copy_from_slice(msg);
-            data[13..18].copy_from_slice(&time);
-            write_to_serial(&data);
 
-            let ticks = rtc::time_to_ticks(hour, minute);
-            set_alarm::spawn(ticks).ok();
+            let current = cx.shared.alarms.lock(|alarms| alarms[slot]);
+            let alarm = Alarm {
+                sound: current.sound,
+                haptic: current.haptic,
+                ..Alarm::new(rtc::time_to_ticks(hour, minute), weekdays.unwrap_or(current.weekdays))
+            };
+            write_alarm(b"Alarm ", slot, &alarm);
+            set_alarm::spawn(slot, alarm).ok();
+        }
+        CliCommand::EnableAlarm(slot, enabled) => {
+            let alarm = Alarm {
+                enabled,
+                ..cx.shared.alarms.lock(|alarms| alarms[slot])
+            };
+            write_alarm(b"Alarm ", slot, &alarm);
+            set_alarm::spawn(slot, alarm).ok();
         }
         CliCommand::GetTime => {
-            let curr_time_ticks = cx.shared.time_offset_ticks.load(Ordering::Relaxed);
-            let (hour, minute) = rtc::ticks_to_time(curr_time_ticks);
+            let now = now(&mut cx);
+            let (hour, minute) = rtc::ticks_to_time(now.ticks);
+            let weekday = now.weekday();
 
             #[cfg(feature = "52833-debug")]
             cx.shared.rtt_serial.lock(|rtt_serial| {
@@ -68,30 +143,569 @@ pub(crate) fn cli_commands(mut cx: cli_commands::Context, command: CliCommand) {
 
             let mut time = [0u8; 5];
             time_formatter(hour, minute, &mut time);
-            let msg = b"Current time: ";
-            let mut data = [0u8; 19];
-            data[0..14].copy_from_slice(msg);
-            data[14..19].copy_from_slice(&time);
+            let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+            data.extend_from_slice(b"Current time: ").ok();
+            data.extend_from_slice(rtc::WEEKDAY_NAMES[weekday as usize]).ok();
+            data.extend_from_slice(b" ").ok();
+            data.extend_from_slice(&time).ok();
             write_to_serial(&data);
         }
-        CliCommand::GetAlarm => {
-            let curr_alarm_ticks = cx.shared.alarm_offset_ticks.load(Ordering::Relaxed);
-            let (hour, minute) = rtc::ticks_to_time(curr_alarm_ticks);
+        CliCommand::GetDate => write_date(b"Current date: ", &now(&mut cx)),
+        CliCommand::GetAlarm(Some(slot)) => {
+            let alarm = cx.shared.alarms.lock(|alarms| alarms[slot]);
 
             #[cfg(feature = "52833-debug")]
             cx.shared.rtt_serial.lock(|rtt_serial| {
-                writeln!(rtt_serial, "Get alarm: {:02}:{:02}", hour, minute).ok();
+                writeln!(rtt_serial, "Get alarm {}: {:?}", slot + 1, alarm).ok();
             });
 
-            let mut time = [0u8; 5];
-            time_formatter(hour, minute, &mut time);
-            let msg = b"Current alarm: ";
-            let mut data = [0u8; 20];
-            data[0..15].copy_from_slice(msg);
-            data[15..20].copy_from_slice(&time);
-            write_to_serial(&data);
+            write_alarm(b"Alarm ", slot, &alarm);
+        }
+        CliCommand::GetAlarm(None) => {
+            let alarms = cx.shared.alarms.lock(|alarms| *alarms);
+            match alarm::next_alarm(&alarms, now(&mut cx).week_ticks()) {
+                Some((slot, _)) => write_alarm(b"Next alarm ", slot, &alarms[slot]),
+                None => write_to_serial(b"No alarm set"),
+            }
+        }
+        CliCommand::SetSnoozeMinutes(minutes) => {
+            cx.shared.snooze_minutes.store(minutes, Ordering::Relaxed);
+            write_snooze(&mut cx);
+            save_settings::spawn().ok();
+        }
+        CliCommand::SetSnoozeLimit(limit) => {
+            cx.shared.snooze_limit.store(limit, Ordering::Relaxed);
+            write_snooze(&mut cx);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetSnooze => write_snooze(&mut cx),
+        CliCommand::SetSunriseMinutes(minutes) => {
+            cx.shared.sunrise_minutes.store(minutes, Ordering::Relaxed);
+            write_sunrise(&mut cx);
+            // The sunrise deadline depends on the window
+            arm_next_alarm::spawn().ok();
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetSunrise => write_sunrise(&mut cx),
+        CliCommand::SetStage(index, stage) => {
+            cx.shared.alarm_stages.lock(|stages| {
+                stages[index] = stage;
+            });
+            write_stage(index, &stage);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetStage(index) => {
+            let stage = cx.shared.alarm_stages.lock(|stages| stages[index]);
+            write_stage(index, &stage);
+        }
+        CliCommand::SetSettingsTimeout(minutes) => {
+            cx.shared.settings_timeout_minutes.store(minutes, Ordering::Relaxed);
+            write_timeout(&mut cx);
+            save_settings::spawn().ok();
+        }
+        CliCommand::SetAlarmTimeout(minutes) => {
+            cx.shared.alarm_timeout_minutes.store(minutes, Ordering::Relaxed);
+            write_timeout(&mut cx);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetTimeout => write_timeout(&mut cx),
+        CliCommand::SetVolume(volume) => {
+            // A playing sound picks it up with the next buffer
+            cx.shared.volume.store(volume, Ordering::Relaxed);
+            write_volume(&mut cx);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetVolume => write_volume(&mut cx),
+        CliCommand::ListSounds => {
+            for sound in 0..SOUNDS.len() as u8 {
+                write_sound(b"Sound ", sound);
+            }
+        }
+        CliCommand::SetSound(slot, sound) => {
+            let alarm = Alarm {
+                sound,
+                ..cx.shared.alarms.lock(|alarms| alarms[slot])
+            };
+            write_alarm_sound(slot, &alarm);
+            set_alarm::spawn(slot, alarm).ok();
+        }
+        CliCommand::GetSound(slot) => write_alarm_sound(slot, &cx.shared.alarms.lock(|alarms| alarms[slot])),
+        CliCommand::PlaySound(sound) => {
+            // Played once, the I2S interrupt switches the amp off at the end
+            cx.shared.audio_gain.store(sound::PREVIEW_GAIN, Ordering::Relaxed);
+            cx.shared.amp_off_pending.store(true, Ordering::Relaxed);
+            set_output::spawn(Load::Amp, MAX_DUTY).ok();
+            start_audio::spawn(sound, false).ok();
+            write_sound(b"Playing sound ", sound);
+        }
+        CliCommand::PlayRtttl(text) => {
+            // Mixed over the alarm sound if one is playing
+            cx.shared.amp_off_pending.store(true, Ordering::Relaxed);
+            set_output::spawn(Load::Amp, MAX_DUTY).ok();
+            play_tune::spawn(text).ok();
+            write_to_serial(b"Playing tune");
+        }
+        CliCommand::StopSound => {
+            stop_audio::spawn().ok();
+            speaker::turn_off_amp_after_audio(cx.shared.audio_playing, cx.shared.amp_off_pending);
+            write_to_serial(b"Sound stopped");
+        }
+        CliCommand::ListHaptics => {
+            for pattern in 0..PATTERNS.len() as u8 {
+                write_haptic(b"Haptic ", pattern);
+            }
+        }
+        CliCommand::SetHaptic(slot, haptic) => {
+            let alarm = Alarm {
+                haptic,
+                ..cx.shared.alarms.lock(|alarms| alarms[slot])
+            };
+            write_alarm_haptic(slot, &alarm);
+            set_alarm::spawn(slot, alarm).ok();
+        }
+        CliCommand::GetHaptic(slot) => write_alarm_haptic(slot, &cx.shared.alarms.lock(|alarms| alarms[slot])),
+        CliCommand::PlayHaptic(pattern) => {
+            play_haptic::spawn(pattern).ok();
+            write_haptic(b"Playing haptic ", pattern);
+        }
+        CliCommand::StopHaptic => {
+            stop_haptic::spawn().ok();
+            write_to_serial(b"Haptic stopped");
+        }
+        CliCommand::SetOutput(load, percent) => {
+            match (load, percent) {
+                (Load::Amp, 0) => speaker::turn_off_amp_after_audio(cx.shared.audio_playing, cx.shared.amp_off_pending),
+                (Load::Amp, _) => {
+                    cx.shared.amp_off_pending.store(false, Ordering::Relaxed);
+                    set_output::spawn(load, percent).ok()
+                }
+                _ => set_output::spawn(load, percent).ok(),
+            };
+            write_output(load, percent);
+        }
+        CliCommand::GetOutputs => {
+            let outputs = cx.shared.loads.lock(|loads| *loads.outputs());
+            for (load, _) in LOAD_NAMES {
+                write_output(load, outputs.duty(load));
+            }
+        }
+        CliCommand::SetThermostat(setting) => {
+            // Applied with the next temperature reading
+            let settings = cx.shared.thermostat.lock(|settings| {
+                match setting {
+                    ThermostatSetting::Enabled(enabled) => settings.enabled = enabled,
+                    ThermostatSetting::Threshold(threshold) => settings.threshold = threshold,
+                    ThermostatSetting::Span(span) => settings.span = span,
+                    ThermostatSetting::Hysteresis(hysteresis) => settings.hysteresis = hysteresis,
+                    ThermostatSetting::MinSpeed(speed) => settings.min_speed = speed,
+                    ThermostatSetting::MinRunMinutes(minutes) => settings.min_run_minutes = minutes,
+                    ThermostatSetting::MinOffMinutes(minutes) => settings.min_off_minutes = minutes,
+                }
+                *settings
+            });
+            write_thermostat(&settings);
+            save_settings::spawn().ok();
+        }
+        CliCommand::SetQuietHours(quiet) => {
+            cx.shared.thermostat.lock(|settings| {
+                settings.quiet = quiet;
+            });
+            write_quiet_hours(&quiet);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetThermostat => {
+            let settings = cx.shared.thermostat.lock(|settings| *settings);
+            write_thermostat(&settings);
+            write_quiet_hours(&settings.quiet);
+        }
+        CliCommand::NightLight(brightness) => {
+            // Only switched when idle, like holding the knob
+            let brightness = brightness.unwrap_or_else(|| cx.shared.night_light.lock(|settings| settings.brightness));
+            state_machine::spawn(Event::NightLight(brightness)).ok();
+            write_night_light(brightness);
+        }
+        CliCommand::SetNightLightBrightness(brightness) => {
+            let settings = cx.shared.night_light.lock(|settings| {
+                settings.brightness = brightness;
+                *settings
+            });
+            write_night_light_settings(&settings);
+            save_settings::spawn().ok();
+        }
+        CliCommand::SetNightLightTimer(minutes) => {
+            let settings = cx.shared.night_light.lock(|settings| {
+                settings.auto_off_minutes = minutes;
+                *settings
+            });
+            write_night_light_settings(&settings);
+            save_settings::spawn().ok();
+        }
+        CliCommand::SetNightLightSchedule(schedule) => {
+            // Followed from the next minute on
+            cx.shared.night_light.lock(|settings| {
+                settings.schedule = schedule;
+            });
+            write_night_light_schedule(&schedule);
+            save_settings::spawn().ok();
+        }
+        CliCommand::GetNightLight => {
+            let settings = cx.shared.night_light.lock(|settings| *settings);
+            write_night_light_settings(&settings);
+            write_night_light_schedule(&settings.schedule);
+        }
+        CliCommand::SetFormat(setting) => {
+            let format = cx.shared.format.lock(|format| {
+                match setting {
+                    FormatSetting::Clock(clock) => format.clock = clock,
+                    FormatSetting::LeadingZero(leading_zero) => format.leading_zero = leading_zero,
+                    FormatSetting::Unit(unit) => format.unit = unit,
+                }
+                *format
+            });
+            write_format(&format);
+            save_settings::spawn().ok();
+            update_display::spawn(now(&mut cx), Section::Display, false).ok();
+        }
+        CliCommand::GetFormat => {
+            let format = cx.shared.format.lock(|format| *format);
+            write_format(&format);
+        }
+    }
+}
+
+fn now(cx: &mut cli_commands::Context) -> DateTime {
+    let overflows = cx.shared.rtc_overflows;
+    let now = cx.shared.rtc.lock(|rtc| rtc::now(rtc, overflows));
+    cx.shared.time_offset.lock(|time_offset| rtc::date_time(*time_offset, now))
+}
+
+// Writes e.g. "<prefix>Mon 2024-01-15"
+fn write_date(prefix: &[u8], now: &DateTime) {
+    let date = now.date();
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(prefix).ok();
+    data.extend_from_slice(rtc::WEEKDAY_NAMES[now.weekday() as usize]).ok();
+    data.push(b' ').ok();
+    number_formatter(date.year as u32, &mut data);
+    data.push(b'-').ok();
+    zero_padded_formatter(date.month, &mut data);
+    data.push(b'-').ok();
+    zero_padded_formatter(date.day, &mut data);
+    write_to_serial(&data);
+}
+
+// Writes e.g. "<prefix>1: 06:30 MTWTF-- on"
+fn write_alarm(prefix: &[u8], slot: usize, alarm: &Alarm) {
+    let (hour, minute) = rtc::ticks_to_time(alarm.ticks);
+    let mut time = [0u8; 5];
+    time_formatter(hour, minute, &mut time);
+
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(prefix).ok();
+    data.push(b'1' + slot as u8).ok();
+    data.extend_from_slice(b": ").ok();
+    data.extend_from_slice(&time).ok();
+    data.push(b' ').ok();
+    data.extend_from_slice(&alarm::weekdays_to_bytes(alarm.weekdays)).ok();
+    data.extend_from_slice(if alarm.enabled { b" on" } else { b" off" }).ok();
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Snooze: 9 min, limit 3"
+fn write_snooze(cx: &mut cli_commands::Context) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Snooze: ").ok();
+    number_formatter(cx.shared.snooze_minutes.load(Ordering::Relaxed), &mut data);
+    data.extend_from_slice(b" min, limit ").ok();
+    number_formatter(cx.shared.snooze_limit.load(Ordering::Relaxed), &mut data);
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Stage 2: 2 min haptic,sound 60%"
+fn write_stage(index: usize, stage: &Stage) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Stage ").ok();
+    data.push(b'1' + index as u8).ok();
+    data.extend_from_slice(b": ").ok();
+    match index {
+        _ if index == ALARM_STAGES - 1 => data.extend_from_slice(b"held").ok(),
+        _ => {
+            number_formatter(stage.minutes, &mut data);
+            data.extend_from_slice(b" min").ok()
+        }
+    };
+    data.push(b' ').ok();
+    let mut first = true;
+    for (actuator, name) in alarm::ACTUATOR_NAMES {
+        if stage.has(actuator) {
+            if !first {
+                data.push(b',').ok();
+            }
+            data.extend_from_slice(name).ok();
+            first = false;
+        }
+    }
+    if first {
+        data.extend_from_slice(b"none").ok();
+    }
+    data.push(b' ').ok();
+    number_formatter(stage.intensity as u32, &mut data);
+    data.push(b'%').ok();
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Sunrise: 20 min"
+fn write_sunrise(cx: &mut cli_commands::Context) {
+    let minutes = cx.shared.sunrise_minutes.load(Ordering::Relaxed);
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Sunrise: ").ok();
+    match minutes {
+        0 => data.extend_from_slice(b"off").ok(),
+        _ => {
+            number_formatter(minutes, &mut data);
+            data.extend_from_slice(b" min").ok()
+        }
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Timeout: settings 5 min, alarm 30 min"
+fn write_timeout(cx: &mut cli_commands::Context) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Timeout: settings ").ok();
+    number_formatter(cx.shared.settings_timeout_minutes.load(Ordering::Relaxed), &mut data);
+    data.extend_from_slice(b" min, alarm ").ok();
+    number_formatter(cx.shared.alarm_timeout_minutes.load(Ordering::Relaxed), &mut data);
+    data.extend_from_slice(b" min").ok();
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Volume: 14/20"
+fn write_volume(cx: &mut cli_commands::Context) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Volume: ").ok();
+    number_formatter(cx.shared.volume.load(Ordering::Relaxed) as u32, &mut data);
+    data.push(b'/').ok();
+    number_formatter(volume::MAX_VOLUME as u32, &mut data);
+    write_to_serial(&data);
+}
+
+// Writes e.g. "<prefix>1: sea breeze"
+fn write_sound(prefix: &[u8], sound: u8) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(prefix).ok();
+    sound_formatter(sound, &mut data);
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Alarm 1 sound 1: sea breeze"
+fn write_alarm_sound(slot: usize, alarm: &Alarm) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Alarm ").ok();
+    data.push(b'1' + slot as u8).ok();
+    data.extend_from_slice(b" sound ").ok();
+    sound_formatter(alarm.sound, &mut data);
+    write_to_serial(&data);
+}
+
+// Sounds are numbered from 1 on the CLI
+fn sound_formatter(sound: u8, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    number_formatter(sound as u32 + 1, buffer);
+    buffer.extend_from_slice(b": ").ok();
+    buffer.extend_from_slice(SOUNDS[sound as usize].name).ok();
+}
+
+// Writes e.g. "<prefix>2: pulse"
+fn write_haptic(prefix: &[u8], pattern: u8) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(prefix).ok();
+    haptic_formatter(pattern, &mut data);
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Alarm 1 haptic 2: pulse"
+fn write_alarm_haptic(slot: usize, alarm: &Alarm) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Alarm ").ok();
+    data.push(b'1' + slot as u8).ok();
+    data.extend_from_slice(b" haptic ").ok();
+    haptic_formatter(alarm.haptic, &mut data);
+    write_to_serial(&data);
+}
+
+// Patterns are numbered from 1 on the CLI, like the sounds
+fn haptic_formatter(pattern: u8, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    number_formatter(pattern as u32 + 1, buffer);
+    buffer.extend_from_slice(b": ").ok();
+    buffer.extend_from_slice(PATTERNS[pattern as usize].name).ok();
+}
+
+// Writes e.g. "Output fan: 40%"
+fn write_output(load: Load, percent: u8) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Output ").ok();
+    data.extend_from_slice(LOAD_NAMES[load as usize].1).ok();
+    data.extend_from_slice(b": ").ok();
+    match percent {
+        0 => data.extend_from_slice(b"off").ok(),
+        _ => {
+            number_formatter(percent as u32, &mut data);
+            data.push(b'%').ok()
+        }
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Thermostat: on, 26.0-30.0 C, off below 25.0 C"
+// and "Thermostat fan: 30% min, run 5 min, rest 3 min"
+fn write_thermostat(settings: &thermostat::Settings) {
+    let threshold = settings.threshold as u32;
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Thermostat: ").ok();
+    data.extend_from_slice(if settings.enabled { b"on, " } else { b"off, " }).ok();
+    tenths_formatter(threshold, &mut data);
+    data.push(b'-').ok();
+    tenths_formatter(threshold + settings.span as u32, &mut data);
+    data.extend_from_slice(b" C, off below ").ok();
+    tenths_formatter(threshold.saturating_sub(settings.hysteresis as u32), &mut data);
+    data.extend_from_slice(b" C").ok();
+    write_to_serial(&data);
+
+    data.clear();
+    data.extend_from_slice(b"Thermostat fan: ").ok();
+    number_formatter(settings.min_speed as u32, &mut data);
+    data.extend_from_slice(b"% min, run ").ok();
+    number_formatter(settings.min_run_minutes as u32, &mut data);
+    data.extend_from_slice(b" min, rest ").ok();
+    number_formatter(settings.min_off_minutes as u32, &mut data);
+    data.extend_from_slice(b" min").ok();
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Quiet hours: 22:00-07:00, fan 20%"
+fn write_quiet_hours(quiet: &QuietHours) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Quiet hours: ").ok();
+    if !quiet.window.enabled() {
+        data.extend_from_slice(b"off").ok();
+        write_to_serial(&data);
+        return;
+    }
+    window_formatter(&quiet.window, &mut data);
+    data.extend_from_slice(b", fan ").ok();
+    match quiet.speed {
+        0 => data.extend_from_slice(b"off").ok(),
+        _ => {
+            number_formatter(quiet.speed as u32, &mut data);
+            data.push(b'%').ok()
+        }
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Night light: 20%"
+fn write_night_light(brightness: u8) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Night light: ").ok();
+    match brightness {
+        0 => data.extend_from_slice(b"off").ok(),
+        _ => {
+            number_formatter(brightness as u32, &mut data);
+            data.push(b'%').ok()
+        }
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Night light brightness: 20%, off after 30 min"
+fn write_night_light_settings(settings: &night_light::Settings) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Night light brightness: ").ok();
+    number_formatter(settings.brightness as u32, &mut data);
+    match settings.auto_off_minutes {
+        0 => data.extend_from_slice(b"%, stays on").ok(),
+        minutes => {
+            data.extend_from_slice(b"%, off after ").ok();
+            number_formatter(minutes, &mut data);
+            data.extend_from_slice(b" min").ok()
+        }
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Night light schedule: 22:00-06:00 at 5%"
+fn write_night_light_schedule(schedule: &Schedule) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Night light schedule: ").ok();
+    match schedule.window.enabled() {
+        true => {
+            window_formatter(&schedule.window, &mut data);
+            data.extend_from_slice(b" at ").ok();
+            number_formatter(schedule.brightness as u32, &mut data);
+            data.push(b'%').ok()
+        }
+        false => data.extend_from_slice(b"off").ok(),
+    };
+    write_to_serial(&data);
+}
+
+// Writes e.g. "Format: 24h, leading zero, C"
+fn write_format(format: &Format) {
+    let mut data: Vec<u8, DATA_OUT_BUFFER_SIZE> = Vec::new();
+    data.extend_from_slice(b"Format: ").ok();
+    data.extend_from_slice(match format.clock {
+        format::Clock::H24 => b"24h, ",
+        format::Clock::H12 => b"12h, ",
+    })
+    .ok();
+    data.extend_from_slice(if format.leading_zero { b"leading zero, " } else { b"no leading zero, " }).ok();
+    data.push(match format.unit {
+        format::Unit::Celsius => b'C',
+        format::Unit::Fahrenheit => b'F',
+    })
+    .ok();
+    write_to_serial(&data);
+}
+
+// Times of day as e.g. "22:00-06:00"
+fn window_formatter(window: &Window, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    let mut time = [0u8; 5];
+    let (hour, minute) = rtc::ticks_to_time(window.start);
+    time_formatter(hour, minute, &mut time);
+    buffer.extend_from_slice(&time).ok();
+    buffer.push(b'-').ok();
+    let (hour, minute) = rtc::ticks_to_time(window.end);
+    time_formatter(hour, minute, &mut time);
+    buffer.extend_from_slice(&time).ok();
+}
+
+// Tenths as e.g. "24.5"
+fn tenths_formatter(value: u32, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    number_formatter(value / 10, buffer);
+    buffer.push(b'.').ok();
+    buffer.push(b'0' + (value % 10) as u8).ok();
+}
+
+fn number_formatter(value: u32, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    let mut digits = [0u8; 10];
+    let mut len = 0;
+    let mut value = value;
+    loop {
+        digits[len] = b'0' + (value % 10) as u8;
+        len += 1;
+        value /= 10;
+        if value == 0 {
+            break;
         }
     }
+    for digit in digits[..len].iter().rev() {
+        buffer.push(*digit).ok();
+    }
+}
+
+fn zero_padded_formatter(value: u8, buffer: &mut Vec<u8, DATA_OUT_BUFFER_SIZE>) {
+    buffer.push((value / 10) + b'0').ok();
+    buffer.push((value % 10) + b'0').ok();
 }
 
 fn time_formatter(hour: u8, minute: u8, buffer: &mut [u8; 5]){
@@ -121,23 +735,212 @@ pub(crate) fn parse_serial_cmd(bytes: &[u8]) -> Option<CliCommand> {
                     let next = split.next()?;
                     let str = core::str::from_utf8(next).ok()?;
                     let minute: u8 = str.parse().ok()?;
+                    if hour > 23 || minute > 59 {
+                        return None;
+                    }
 
                     Some(CliCommand::SetTime(hour, minute))
                 }
                 b"alarm" => {
                     let next = split.next()?;
+                    // "set alarm hh:mm" without a slot sets the first slot
+                    let (slot, next) = match next.contains(&b':') {
+                        true => (0, next),
+                        false => (parse_slot(next)?, split.next()?),
+                    };
+                    match next {
+                        b"on" => return Some(CliCommand::EnableAlarm(slot, true)),
+                        b"off" => return Some(CliCommand::EnableAlarm(slot, false)),
+                        _ => {}
+                    }
                     let hour_minute = next.splitn(next.len(), |c| *c == b':');
                     
-                    let mut split = hour_minute;
-                    let next = split.next()?;
+                    let mut split_time = hour_minute;
+                    let next = split_time.next()?;
                     let str = core::str::from_utf8(next).ok()?;
                     let hour: u8 = str.parse().ok()?;
                     
-                    let next = split.next()?;
+                    let next = split_time.next()?;
                     let str = core::str::from_utf8(next).ok()?;
                     let minute: u8 = str.parse().ok()?;
+                    if hour > 23 || minute > 59 {
+                        return None;
+                    }
+
+                    let weekdays = match split.next() {
+                        Some(days) => Some(alarm::weekdays_from_bytes(days)?),
+                        None => None,
+                    };
 
-                    Some(CliCommand::SetAlarm(hour, minute))
+                    Some(CliCommand::SetAlarm(slot, hour, minute, weekdays))
+                }
+                b"snooze" => {
+                    let minutes = parse_number(split.next()?)?;
+                    match minutes {
+                        1..=alarm::MAX_SNOOZE_MINUTES => Some(CliCommand::SetSnoozeMinutes(minutes)),
+                        _ => None,
+                    }
+                }
+                b"snooze_limit" => Some(CliCommand::SetSnoozeLimit(parse_number(split.next()?)?)),
+                b"sunrise" => {
+                    let next = split.next()?;
+                    let minutes = match next {
+                        b"off" => 0,
+                        _ => parse_number(next)?,
+                    };
+                    match minutes {
+                        0..=alarm::MAX_SUNRISE_MINUTES => Some(CliCommand::SetSunriseMinutes(minutes)),
+                        _ => None,
+                    }
+                }
+                b"stage" => {
+                    // set stage <n> <minutes> <actuators> <intensity>
+                    let index = parse_number(split.next()?)? as usize;
+                    let minutes = parse_number(split.next()?)?;
+                    let actuators = alarm::actuators_from_bytes(split.next()?)?;
+                    let intensity = parse_number(split.next()?)?;
+                    match (index, intensity, minutes) {
+                        (1..=ALARM_STAGES, 0..=100, 0..=alarm::MAX_STAGE_MINUTES) => Some(CliCommand::SetStage(
+                            index - 1,
+                            Stage { actuators, intensity: intensity as u8, minutes },
+                        )),
+                        _ => None,
+                    }
+                }
+                b"timeout" => {
+                    // set timeout settings|alarm <minutes>
+                    let which = split.next()?;
+                    let minutes = parse_number(split.next()?)?;
+                    match (which, minutes) {
+                        (b"settings", 1..=time::MAX_TIMEOUT_MINUTES) => Some(CliCommand::SetSettingsTimeout(minutes)),
+                        (b"alarm", 1..=time::MAX_TIMEOUT_MINUTES) => Some(CliCommand::SetAlarmTimeout(minutes)),
+                        _ => None,
+                    }
+                }
+                b"sound" => {
+                    // set sound [<slot>] <n>, without a slot it sets the first slot
+                    let first = split.next()?;
+                    match split.next() {
+                        Some(sound) => Some(CliCommand::SetSound(parse_slot(first)?, parse_sound(sound)?)),
+                        None => Some(CliCommand::SetSound(0, parse_sound(first)?)),
+                    }
+                }
+                b"output" => {
+                    // set output <load> on|off|<percent>
+                    let load = outputs::find(split.next()?)?;
+                    let percent = match split.next()? {
+                        b"on" => MAX_DUTY,
+                        b"off" => 0,
+                        percent => u8::try_from(parse_number(percent)?).ok().filter(|percent| *percent <= MAX_DUTY)?,
+                    };
+                    Some(CliCommand::SetOutput(load, percent))
+                }
+                b"thermostat" => {
+                    // set thermostat on|off, or a setting and its value, temperatures in C
+                    let next = split.next()?;
+                    let setting = match next {
+                        b"on" => ThermostatSetting::Enabled(true),
+                        b"off" => ThermostatSetting::Enabled(false),
+                        _ => {
+                            let value = split.next()?;
+                            match next {
+                                b"threshold" => ThermostatSetting::Threshold(
+                                    i16::try_from(parse_tenths(value)?).ok().filter(|t| *t <= thermostat::MAX_TEMPERATURE)?,
+                                ),
+                                b"span" => ThermostatSetting::Span(u8::try_from(parse_tenths(value)?).ok().filter(|span| *span > 0)?),
+                                b"hysteresis" => ThermostatSetting::Hysteresis(u8::try_from(parse_tenths(value)?).ok()?),
+                                b"speed" => ThermostatSetting::MinSpeed(
+                                    u8::try_from(parse_number(value)?).ok().filter(|speed| *speed <= MAX_DUTY)?,
+                                ),
+                                b"run" => ThermostatSetting::MinRunMinutes(parse_minutes(value)?),
+                                b"rest" => ThermostatSetting::MinOffMinutes(parse_minutes(value)?),
+                                _ => return None,
+                            }
+                        }
+                    };
+                    Some(CliCommand::SetThermostat(setting))
+                }
+                b"quiet" => {
+                    // set quiet <hh:mm> <hh:mm> [<fan percent>], or off
+                    let start = split.next()?;
+                    if start == b"off" {
+                        return Some(CliCommand::SetQuietHours(QuietHours::DISABLED));
+                    }
+                    let window = Window { start: parse_time(start)?, end: parse_time(split.next()?)? };
+                    let speed = match split.next() {
+                        Some(speed) => u8::try_from(parse_number(speed)?).ok().filter(|speed| *speed <= MAX_DUTY)?,
+                        None => 0,
+                    };
+                    Some(CliCommand::SetQuietHours(QuietHours { window, speed }))
+                }
+                b"light" => {
+                    // set light <percent>, set light timer <minutes>|off,
+                    // set light schedule <hh:mm> <hh:mm> <percent>|off
+                    let next = split.next()?;
+                    match next {
+                        b"timer" => {
+                            let minutes = match split.next()? {
+                                b"off" => 0,
+                                minutes => parse_number(minutes)?,
+                            };
+                            match minutes {
+                                0..=night_light::MAX_AUTO_OFF_MINUTES => Some(CliCommand::SetNightLightTimer(minutes)),
+                                _ => None,
+                            }
+                        }
+                        b"schedule" => {
+                            let start = split.next()?;
+                            if start == b"off" {
+                                return Some(CliCommand::SetNightLightSchedule(Schedule::DISABLED));
+                            }
+                            let window = Window { start: parse_time(start)?, end: parse_time(split.next()?)? };
+                            let brightness = parse_brightness(split.next()?)?;
+                            Some(CliCommand::SetNightLightSchedule(Schedule { window, brightness }))
+                        }
+                        _ => Some(CliCommand::SetNightLightBrightness(parse_brightness(next)?)),
+                    }
+                }
+                b"format" => {
+                    // set format 12h|24h|c|f, or set format zero on|off
+                    let setting = match split.next()? {
+                        b"24h" => FormatSetting::Clock(format::Clock::H24),
+                        b"12h" => FormatSetting::Clock(format::Clock::H12),
+                        b"c" => FormatSetting::Unit(format::Unit::Celsius),
+                        b"f" => FormatSetting::Unit(format::Unit::Fahrenheit),
+                        b"zero" => match split.next()? {
+                            b"on" => FormatSetting::LeadingZero(true),
+                            b"off" => FormatSetting::LeadingZero(false),
+                            _ => return None,
+                        },
+                        _ => return None,
+                    };
+                    Some(CliCommand::SetFormat(setting))
+                }
+                b"haptic" => {
+                    // set haptic [<slot>] <pattern>, without a slot it sets the first slot
+                    let first = split.next()?;
+                    match split.next() {
+                        Some(pattern) => Some(CliCommand::SetHaptic(parse_slot(first)?, parse_haptic(pattern)?)),
+                        None => Some(CliCommand::SetHaptic(0, parse_haptic(first)?)),
+                    }
+                }
+                b"volume" => {
+                    let volume = u8::try_from(parse_number(split.next()?)?).ok()?;
+                    match volume {
+                        0..=volume::MAX_VOLUME => Some(CliCommand::SetVolume(volume)),
+                        _ => None,
+                    }
+                }
+                b"date" => {
+                    // set date yyyy-mm-dd, the weekday follows from the date
+                    let mut split_date = split.next()?.split(|c| *c == b'-');
+                    let year = parse_number(split_date.next()?)?;
+                    let month = parse_number(split_date.next()?)?;
+                    let day = parse_number(split_date.next()?)?;
+                    if split_date.next().is_some() || year > u16::MAX as u32 || month > 12 || day > 31 {
+                        return None;
+                    }
+                    Some(CliCommand::SetDate(Date::new(year as u16, month as u8, day as u8)?))
                 }
                 _ => None,
             }
@@ -146,14 +949,143 @@ pub(crate) fn parse_serial_cmd(bytes: &[u8]) -> Option<CliCommand> {
             let next = split.next()?;
             match next {
                 b"time" => Some(CliCommand::GetTime),
-                b"alarm" => Some(CliCommand::GetAlarm),
+                b"date" => Some(CliCommand::GetDate),
+                b"alarm" => match split.next() {
+                    Some(slot) => Some(CliCommand::GetAlarm(Some(parse_slot(slot)?))),
+                    None => Some(CliCommand::GetAlarm(None)),
+                },
+                b"snooze" => Some(CliCommand::GetSnooze),
+                b"sunrise" => Some(CliCommand::GetSunrise),
+                b"timeout" => Some(CliCommand::GetTimeout),
+                b"volume" => Some(CliCommand::GetVolume),
+                b"sound" => match split.next() {
+                    Some(slot) => Some(CliCommand::GetSound(parse_slot(slot)?)),
+                    None => Some(CliCommand::GetSound(0)),
+                },
+                b"outputs" => Some(CliCommand::GetOutputs),
+                b"thermostat" => Some(CliCommand::GetThermostat),
+                b"light" => Some(CliCommand::GetNightLight),
+                b"format" => Some(CliCommand::GetFormat),
+                b"haptic" => match split.next() {
+                    Some(slot) => Some(CliCommand::GetHaptic(parse_slot(slot)?)),
+                    None => Some(CliCommand::GetHaptic(0)),
+                },
+                b"stage" => {
+                    let index = parse_number(split.next()?)? as usize;
+                    match index {
+                        1..=ALARM_STAGES => Some(CliCommand::GetStage(index - 1)),
+                        _ => None,
+                    }
+                }
                 _ => None,
             }
         }
+        b"list" => match split.next()? {
+            b"sounds" => Some(CliCommand::ListSounds),
+            b"haptics" => Some(CliCommand::ListHaptics),
+            _ => None,
+        },
+        b"play" => match split.next()? {
+            b"sound" => Some(CliCommand::PlaySound(parse_sound(split.next()?)?)),
+            b"rtttl" => {
+                let text = rtttl_text(bytes)?;
+                rtttl::check(text).ok()?;
+                Some(CliCommand::PlayRtttl(rtttl::Text::from_slice(text).ok()?))
+            }
+            _ => None,
+        },
+        b"stop" => match split.next()? {
+            b"sound" => Some(CliCommand::StopSound),
+            _ => None,
+        },
+        b"light" => match split.next()? {
+            b"on" => match split.next() {
+                Some(brightness) => Some(CliCommand::NightLight(Some(parse_brightness(brightness)?))),
+                None => Some(CliCommand::NightLight(None)),
+            },
+            b"off" => Some(CliCommand::NightLight(Some(0))),
+            _ => None,
+        },
+        b"haptic" => match split.next()? {
+            b"play" => Some(CliCommand::PlayHaptic(parse_haptic(split.next()?)?)),
+            b"stop" => Some(CliCommand::StopHaptic),
+            _ => None,
+        },
         _ => None,
     }
 }
 
+fn parse_number(bytes: &[u8]) -> Option<u32> {
+    core::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+// A decimal with at most one digit after the point, e.g. "24.5", in tenths
+fn parse_tenths(bytes: &[u8]) -> Option<u32> {
+    let mut split = bytes.splitn(2, |c| *c == b'.');
+    let whole = parse_number(split.next()?)?;
+    let tenths = match split.next() {
+        None => 0,
+        Some([digit @ b'0'..=b'9']) => (digit - b'0') as u32,
+        Some(_) => return None,
+    };
+    whole.checked_mul(10)?.checked_add(tenths)
+}
+
+// Time of day as hh:mm, in ticks from 00:00
+fn parse_time(bytes: &[u8]) -> Option<u32> {
+    let mut split = bytes.splitn(2, |c| *c == b':');
+    let hour = parse_number(split.next()?)?;
+    let minute = parse_number(split.next()?)?;
+    match (hour, minute) {
+        (0..=23, 0..=59) => Some(time::time_to_ticks(hour as u8, minute as u8)),
+        _ => None,
+    }
+}
+
+// Night light brightness in percent, 0 would be off
+fn parse_brightness(bytes: &[u8]) -> Option<u8> {
+    u8::try_from(parse_number(bytes)?).ok().filter(|brightness| (1..=night_light::MAX_BRIGHTNESS).contains(brightness))
+}
+
+fn parse_minutes(bytes: &[u8]) -> Option<u8> {
+    u8::try_from(parse_number(bytes)?).ok().filter(|minutes| *minutes <= thermostat::MAX_MINUTES)
+}
+
+// Slots are numbered from 1 on the CLI
+fn parse_slot(bytes: &[u8]) -> Option<usize> {
+    let slot = parse_number(bytes)? as usize;
+    match slot {
+        1..=ALARM_SLOTS => Some(slot - 1),
+        _ => None,
+    }
+}
+
+// Sounds are numbered from 1 on the CLI
+fn parse_sound(bytes: &[u8]) -> Option<u8> {
+    let sound = parse_number(bytes)? as usize;
+    match (1..=SOUNDS.len()).contains(&sound) {
+        true => Some(sound as u8 - 1),
+        false => None,
+    }
+}
+
+// A pattern by its name or its number from 1
+fn parse_haptic(bytes: &[u8]) -> Option<u8> {
+    if let Some(pattern) = haptic::find(bytes) {
+        return Some(pattern);
+    }
+    let pattern = parse_number(bytes)? as usize;
+    match (1..=PATTERNS.len()).contains(&pattern) {
+        true => Some(pattern as u8 - 1),
+        false => None,
+    }
+}
+
+// The rest of a `play rtttl` line, names and notes may contain spaces
+fn rtttl_text(bytes: &[u8]) -> Option<&[u8]> {
+    bytes.get(b"play rtttl ".len()..)
+}
+
 // Should NOT be RTIC task
 // Just makes it easier to create an array of the correct size
 pub(crate) fn write_to_serial(data: &[u8]) {
@@ -191,41 +1123,43 @@ pub(crate) fn data_out(mut cx: data_out::Context, data: [u8; DATA_OUT_BUFFER_SIZ
 }
 
 #[allow(unused_mut)]
-pub(crate) fn data_in(mut cx: data_in::Context, data: u8) {
+pub(crate) fn data_in(mut cx: data_in::Context, packet: [u8; DATA_IN_PACKET_SIZE], count: usize) {
     let len = cx.local.len;
     let data_arr = cx.local.data_arr;
 
-    match data {
-        13 => {
-            let slice = &data_arr[0..*len];
-            #[cfg(feature = "52833-debug")]
-            cx.shared.rtt_serial.lock(|rtt_serial| {
-                writeln!(rtt_serial, "Received: {:?}", core::str::from_utf8(slice)).ok();
-            });
-
-            if let Some(command) = parse_serial_cmd(slice) {
-                cli_commands::spawn(command).ok();
-            } else {
+    for &data in &packet[..count] {
+        match data {
+            13 => {
+                let slice = &data_arr[0..*len];
                 #[cfg(feature = "52833-debug")]
                 cx.shared.rtt_serial.lock(|rtt_serial| {
-                    writeln!(rtt_serial, "Invalid command").ok();
+                    writeln!(rtt_serial, "Received: {:?}", core::str::from_utf8(slice)).ok();
                 });
 
-                write_to_serial(b"Invalid command or argument");
-            }
-            *len = 0;
-        }
-        _ => {
-            data_arr[*len] = data;
-            if *len < data_arr.len() - 1 {
-                *len += 1;
-            } else {
-                #[cfg(feature = "52833-debug")]
-                cx.shared.rtt_serial.lock(|rtt_serial| {
-                    writeln!(rtt_serial, "Buffer full, discarding data").ok();
-                });
+                if let Some(command) = parse_serial_cmd(slice) {
+                    cli_commands::spawn(command).ok();
+                } else {
+                    #[cfg(feature = "52833-debug")]
+                    cx.shared.rtt_serial.lock(|rtt_serial| {
+                        writeln!(rtt_serial, "Invalid command").ok();
+                    });
+
+                    write_to_serial(b"Invalid command or argument");
+                }
                 *len = 0;
             }
+            _ => {
+                data_arr[*len] = data;
+                if *len < data_arr.len() - 1 {
+                    *len += 1;
+                } else {
+                    #[cfg(feature = "52833-debug")]
+                    cx.shared.rtt_serial.lock(|rtt_serial| {
+                        writeln!(rtt_serial, "Buffer full, discarding data").ok();
+                    });
+                    *len = 0;
+                }
+            }
         }
     }
 }
@@ -239,13 +1173,19 @@ pub(crate) fn usb_fs(mut cx: usb_fs::Context) {
     let usb_dev = cx.shared.usb_dev;
     let serial = cx.shared.serial;
     
-    let mut buf = [0u8; 64];
+    let mut buf = [0u8; DATA_IN_PACKET_SIZE];
     usb_dev.poll(&mut [serial]);
 
+    // A whole packet per message, a pasted ringtone arrives at once
     match serial.read(&mut buf) {
         Ok(count) if count > 0 => {
-            for i in 0..count {
-                data_in::spawn(buf[i]).unwrap();
+            // Dropped while the queue is full
+            let _spawned = data_in::spawn(buf, count);
+            #[cfg(feature = "52833-debug")]
+            if _spawned.is_err() {
+                cx.shared.rtt_hw.lock(|rtt_hw| {
+                    writeln!(rtt_hw, "Input queue full, discarding data").ok();
+                });
             }
         }
         _ => {}
@@ -0,0 +1,111 @@
+// Extends the 24 bit RTC counter to a 64 bit monotonic tick count that never wraps.
+// All deadlines are kept as monotonic ticks and only reduced to a compare value when armed.
+
+pub const COUNTER_BITS: u32 = 24;
+pub const MAX_TICKS: u32 = 1 << COUNTER_BITS; // 24 bit max value for RTC counter
+const MIN_COMPARE_TICKS: u64 = 2; // The RTC can miss a compare less than 2 ticks ahead of the counter
+
+// Monotonic ticks from the number of handled overflows and the counter.
+// `overflow_pending` is an overflow event not handled yet. The counter has to be read before
+// the event, then a small counter means it wrapped after the overflows were counted.
+pub fn extend(overflows: u32, counter: u32, overflow_pending: bool) -> u64 {
+    let overflows = match overflow_pending && counter < MAX_TICKS / 2 {
+        true => overflows as u64 + 1,
+        false => overflows as u64,
+    };
+    (overflows << COUNTER_BITS) + counter as u64
+}
+
+// Compare register value firing at `deadline`, a deadline in the past fires as soon as possible.
+// A deadline further out than half a counter period fires early, nothing has expired then and
+// it is armed again.
+pub fn compare_value(now: u64, deadline: u64) -> u32 {
+    let deadline = deadline.clamp(now + MIN_COMPARE_TICKS, now + MAX_TICKS as u64 / 2);
+    (deadline % MAX_TICKS as u64) as u32
+}
+
+// Next deadline after `now` on a multiple of `interval_ticks`, counted from monotonic tick
+// `-offset`. With the wall clock offset this aligns to e.g. full minutes.
+pub fn next_aligned(now: u64, offset: i64, interval_ticks: u32) -> u64 {
+    let interval_ticks = interval_ticks as u64;
+    let aligned = now.wrapping_add_signed(offset);
+    now + interval_ticks - aligned % interval_ticks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAST: u32 = MAX_TICKS - 1;
+
+    #[test]
+    fn extend_counts_overflows() {
+        assert_eq!(extend(0, 0, false), 0);
+        assert_eq!(extend(0, LAST, false), LAST as u64);
+        assert_eq!(extend(1, 0, false), MAX_TICKS as u64);
+        assert_eq!(extend(300, 5, false), 300 * MAX_TICKS as u64 + 5);
+    }
+
+    #[test]
+    fn extend_with_pending_overflow() {
+        // Counter wrapped, the interrupt has not run yet
+        assert_eq!(extend(0, 0, true), MAX_TICKS as u64);
+        assert_eq!(extend(0, 3, true), MAX_TICKS as u64 + 3);
+        // Counter read just before it wrapped
+        assert_eq!(extend(0, LAST, true), LAST as u64);
+    }
+
+    #[test]
+    fn extend_is_monotonic_across_wrap() {
+        let mut previous = 0;
+        for overflows in 0..3 {
+            for (counter, pending) in [(LAST - 1, false), (LAST, false), (LAST, true), (0, true), (1, true)] {
+                let now = extend(overflows, counter, pending);
+                assert!(now >= previous, "{} < {}", now, previous);
+                previous = now;
+            }
+        }
+    }
+
+    #[test]
+    fn compare_value_wraps_with_counter() {
+        let now = extend(2, LAST - 10, false);
+        assert_eq!(compare_value(now, now + 5), LAST - 5);
+        assert_eq!(compare_value(now, now + 11), 0);
+        assert_eq!(compare_value(now, now + 20), 9);
+    }
+
+    #[test]
+    fn compare_value_never_in_the_past() {
+        let now = extend(1, LAST, false);
+        assert_eq!(compare_value(now, now - 100), 1);
+        assert_eq!(compare_value(now, now), 1);
+        assert_eq!(compare_value(now, now + 1), 1);
+        assert_eq!(compare_value(now, now + 2), 1);
+        assert_eq!(compare_value(now, now + 3), 2);
+    }
+
+    #[test]
+    fn compare_value_for_far_deadline() {
+        let now = extend(1, 100, false);
+        let far = now + 3 * MAX_TICKS as u64;
+        assert_eq!(compare_value(now, far), 100 + MAX_TICKS / 2);
+    }
+
+    #[test]
+    fn next_aligned_across_wrap() {
+        let now = MAX_TICKS as u64 - 3;
+        assert_eq!(next_aligned(now, 0, 4), MAX_TICKS as u64);
+        assert_eq!(next_aligned(MAX_TICKS as u64, 0, 4), MAX_TICKS as u64 + 4);
+    }
+
+    #[test]
+    fn next_aligned_to_wall_clock() {
+        let minute = 480;
+        // Wall clock is 100 ticks past a full minute at monotonic tick 1000
+        let offset = 10 * minute as i64 + 100 - 1000;
+        assert_eq!(next_aligned(1000, offset, minute), 1000 + minute as u64 - 100);
+        // Wall clock behind the monotonic ticks
+        assert_eq!(next_aligned(1000, -1000, minute), 1000 + minute as u64);
+    }
+}
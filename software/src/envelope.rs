@@ -0,0 +1,185 @@
+// Levels of a PWM sequence generated from a few segments instead of a table, e.g. a ramp
+// of the LED duty cycle followed by a hold. A repeat segment plays the segments before it
+// again, so a pulsing pattern takes a handful of segments.
+
+use libm::{cosf, exp2f, powf};
+
+pub const GAMMA: f32 = 2.2; // Perceived brightness is roughly linear with gamma correction
+const EXPONENTIAL_OCTAVES: f32 = 10.0; // Range of an exponential ramp, about 60 dB
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Curve {
+    Linear,
+    Exponential, // Doubles at the same pace, slow at the bottom
+    Sine,        // Eases in and out
+    Gamma,       // Linear in perceived brightness, see GAMMA
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segment {
+    // From one level to another, reaching `to` at the last step
+    Ramp { from: u16, to: u16, steps: usize, curve: Curve },
+    Hold { level: u16, steps: usize },
+    // Plays the `segments` before it `times` more times, repeats do not nest
+    Repeat { segments: usize, times: usize },
+}
+
+impl Curve {
+    // Progress along the ramp from 0.0 to 1.0 shaped by the curve
+    fn shape(&self, progress: f32) -> f32 {
+        match self {
+            Curve::Linear => progress,
+            Curve::Exponential => (exp2f(EXPONENTIAL_OCTAVES * progress) - 1.0) / (exp2f(EXPONENTIAL_OCTAVES) - 1.0),
+            Curve::Sine => (1.0 - cosf(core::f32::consts::PI * progress)) / 2.0,
+            Curve::Gamma => powf(progress, GAMMA),
+        }
+    }
+}
+
+// Iterates over the levels of the segments, step by step
+pub struct Levels<'a> {
+    segments: &'a [Segment],
+    segment: usize,
+    step: usize,
+    repeats: Option<usize>, // Left of the repeat being played
+}
+
+pub fn levels(segments: &[Segment]) -> Levels<'_> {
+    Levels { segments, segment: 0, step: 0, repeats: None }
+}
+
+impl Iterator for Levels<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        loop {
+            let level = match *self.segments.get(self.segment)? {
+                Segment::Ramp { from, to, steps, curve } if self.step < steps => {
+                    let progress = match steps {
+                        1 => 1.0,
+                        _ => self.step as f32 / (steps - 1) as f32,
+                    };
+                    let level = from as f32 + (to as f32 - from as f32) * curve.shape(progress);
+                    Some((level + 0.5) as u16)
+                }
+                Segment::Hold { level, steps } if self.step < steps => Some(level),
+                Segment::Repeat { segments, times } => {
+                    let repeats = self.repeats.unwrap_or(times);
+                    match repeats {
+                        0 => {
+                            self.repeats = None;
+                            self.segment += 1;
+                        }
+                        _ => {
+                            self.repeats = Some(repeats - 1);
+                            self.segment = self.segment.saturating_sub(segments);
+                        }
+                    }
+                    self.step = 0;
+                    None
+                }
+                _ => {
+                    self.segment += 1;
+                    self.step = 0;
+                    None
+                }
+            };
+            if let Some(level) = level {
+                self.step += 1;
+                return Some(level);
+            }
+        }
+    }
+}
+
+// Fills every `stride`th word of `buffer` from `offset` on, the channel of an interleaved
+// PWM sequence. Levels are scaled by `percent` and the last one is held to the end.
+pub fn fill(segments: &[Segment], percent: u32, buffer: &mut [u16], offset: usize, stride: usize) {
+    let mut last = 0;
+    let mut levels = levels(segments);
+    for word in buffer.iter_mut().skip(offset).step_by(stride) {
+        last = levels.next().unwrap_or(last);
+        *word = (last as u32 * percent.min(100) / 100) as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(segments: &[Segment]) -> Vec<u16> {
+        levels(segments).collect()
+    }
+
+    #[test]
+    fn linear_ramp_ends_at_the_target() {
+        let ramp = [Segment::Ramp { from: 0, to: 100, steps: 5, curve: Curve::Linear }];
+        assert_eq!(collect(&ramp), [0, 25, 50, 75, 100]);
+        let down = [Segment::Ramp { from: 10_000, to: 5_000, steps: 1000, curve: Curve::Linear }];
+        let levels = collect(&down);
+        assert_eq!((levels.len(), levels[0], levels[999]), (1000, 10_000, 5_000));
+    }
+
+    #[test]
+    fn curves_share_the_ends() {
+        for curve in [Curve::Linear, Curve::Exponential, Curve::Sine, Curve::Gamma] {
+            let levels = collect(&[Segment::Ramp { from: 0, to: 10_000, steps: 101, curve }]);
+            assert_eq!((levels[0], levels[100]), (0, 10_000), "{:?}", curve);
+            assert!(levels.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", curve);
+        }
+    }
+
+    #[test]
+    fn curves_differ_in_the_middle() {
+        let middle = |curve| collect(&[Segment::Ramp { from: 0, to: 10_000, steps: 101, curve }])[50];
+        assert_eq!(middle(Curve::Linear), 5_000);
+        assert_eq!(middle(Curve::Sine), 5_000);
+        assert_eq!(middle(Curve::Gamma), 2_176); // 0.5^2.2
+        assert_eq!(middle(Curve::Exponential), 303); // (2^5 - 1) / (2^10 - 1)
+        let sine = |step| collect(&[Segment::Ramp { from: 0, to: 10_000, steps: 101, curve: Curve::Sine }])[step];
+        assert!(sine(10) < 500 && sine(90) > 9_500); // Slow at both ends
+    }
+
+    #[test]
+    fn single_step_ramp_is_the_target() {
+        assert_eq!(collect(&[Segment::Ramp { from: 0, to: 7, steps: 1, curve: Curve::Gamma }]), [7]);
+        assert_eq!(collect(&[Segment::Ramp { from: 0, to: 7, steps: 0, curve: Curve::Gamma }]), []);
+    }
+
+    #[test]
+    fn segments_follow_each_other() {
+        let segments = [
+            Segment::Hold { level: 3, steps: 2 },
+            Segment::Ramp { from: 0, to: 2, steps: 3, curve: Curve::Linear },
+            Segment::Hold { level: 9, steps: 1 },
+        ];
+        assert_eq!(collect(&segments), [3, 3, 0, 1, 2, 9]);
+    }
+
+    #[test]
+    fn repeat_plays_the_segments_before_it_again() {
+        let pulse = [
+            Segment::Hold { level: 5, steps: 1 },
+            Segment::Hold { level: 1, steps: 2 },
+            Segment::Hold { level: 0, steps: 1 },
+            Segment::Repeat { segments: 2, times: 2 },
+            Segment::Hold { level: 7, steps: 1 },
+        ];
+        assert_eq!(collect(&pulse), [5, 1, 1, 0, 1, 1, 0, 1, 1, 0, 7]);
+    }
+
+    #[test]
+    fn fill_writes_one_channel_and_holds_the_last_level() {
+        let segments = [Segment::Ramp { from: 100, to: 300, steps: 3, curve: Curve::Linear }];
+        let mut buffer = [1u16; 10];
+        fill(&segments, 50, &mut buffer, 1, 2);
+        assert_eq!(buffer, [1, 50, 1, 100, 1, 150, 1, 150, 1, 150]);
+    }
+
+    #[test]
+    fn empty_envelope_is_off() {
+        let mut buffer = [1u16; 4];
+        fill(&[], 100, &mut buffer, 0, 1);
+        assert_eq!(buffer, [0; 4]);
+    }
+}
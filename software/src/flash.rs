@@ -0,0 +1,51 @@
+use {
+    crate::{app::*, persist, state_machine::Config, store::{self, Store}},
+    core::sync::atomic::Ordering,
+    hal::{nvmc::Nvmc, pac::NVMC},
+    nrf52833_hal as hal,
+    rtic::Mutex,
+};
+
+#[cfg(feature = "52833-debug")]
+use rtt_target::rprintln;
+
+const STORE_SIZE: usize = (store::PAGE_SIZE * store::PAGES) as usize;
+
+extern "C" {
+    // Start of the settings pages at the end of the flash, defined in settings.x
+    static mut _settings_start: u8;
+}
+
+pub(crate) type SettingsStore = Store<Nvmc<NVMC>>;
+
+// Mounts the settings store and restores the saved settings
+pub(crate) fn init(nvmc: NVMC) -> (SettingsStore, Config) {
+    // Only called once from init, the store owns the pages from here on
+    let pages = unsafe {
+        core::slice::from_raw_parts_mut(core::ptr::addr_of_mut!(_settings_start), STORE_SIZE)
+    };
+    let mut store = Store::new(Nvmc::new(nvmc, pages), persist::SCHEMA_VERSION).unwrap();
+    let config = persist::load(&mut store);
+    (store, config)
+}
+
+pub(crate) fn save_settings(mut cx: save_settings::Context) {
+    let config = Config {
+        alarms: cx.shared.alarms.lock(|alarms| *alarms),
+        stages: cx.shared.alarm_stages.lock(|stages| *stages),
+        snooze_minutes: cx.shared.snooze_minutes.load(Ordering::Relaxed),
+        snooze_limit: cx.shared.snooze_limit.load(Ordering::Relaxed),
+        sunrise_minutes: cx.shared.sunrise_minutes.load(Ordering::Relaxed),
+        settings_timeout_minutes: cx.shared.settings_timeout_minutes.load(Ordering::Relaxed),
+        alarm_timeout_minutes: cx.shared.alarm_timeout_minutes.load(Ordering::Relaxed),
+        volume: cx.shared.volume.load(Ordering::Relaxed),
+        thermostat: cx.shared.thermostat.lock(|settings| *settings),
+        night_light: cx.shared.night_light.lock(|settings| *settings),
+        format: cx.shared.format.lock(|format| *format),
+    };
+    // The CPU stalls while a page is erased, this runs at the lowest priority
+    if let Err(_error) = persist::save(cx.local.store, &config) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("Saving settings failed: {:?}", _error);
+    }
+}
@@ -0,0 +1,147 @@
+// Text shown on the screen, the same in every build. The hour is padded to two characters
+// so it keeps its place in the monospaced font with or without the leading zero.
+
+use {
+    crate::time::{DateTime, WEEKDAY_NAMES},
+    core::fmt::Write,
+    heapless::String,
+};
+
+pub const DEFAULT_FORMAT: Format = Format { clock: Clock::H24, leading_zero: true, unit: Unit::Celsius };
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Clock {
+    H24,
+    H12,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub clock: Clock,
+    pub leading_zero: bool, // Of the hour, the minutes always have two digits
+    pub unit: Unit,
+}
+
+// e.g. "07", " 7" or "12" for 00 in 12 hour mode
+pub fn hour(hour: u8, format: &Format) -> String<2> {
+    let hour = match format.clock {
+        Clock::H24 => hour,
+        Clock::H12 => (hour + 11) % 12 + 1,
+    };
+    let mut hour_str = String::new();
+    match format.leading_zero {
+        true => write!(hour_str, "{:02}", hour),
+        false => write!(hour_str, "{:2}", hour),
+    }
+    .ok();
+    hour_str
+}
+
+pub fn minute(minute: u8) -> String<2> {
+    let mut minute_str = String::new();
+    write!(minute_str, "{:02}", minute).ok();
+    minute_str
+}
+
+// "AM" or "PM" in 12 hour mode, empty otherwise
+pub fn meridiem(hour: u8, format: &Format) -> &'static str {
+    match (format.clock, hour) {
+        (Clock::H24, _) => "",
+        (Clock::H12, 0..=11) => "AM",
+        (Clock::H12, _) => "PM",
+    }
+}
+
+// e.g. "21.5°C" or "70.7°F" from degrees Celsius
+pub fn temperature(celsius: f32, format: &Format) -> String<10> {
+    let mut temp_str = String::new();
+    match format.unit {
+        Unit::Celsius => write!(temp_str, "{:.1}°C", celsius),
+        Unit::Fahrenheit => write!(temp_str, "{:.1}°F", celsius * 1.8 + 32.0),
+    }
+    .ok();
+    temp_str
+}
+
+// e.g. "Mon 2024-01-15"
+pub fn date(now: &DateTime) -> String<16> {
+    let date = now.date();
+    let weekday = core::str::from_utf8(WEEKDAY_NAMES[now.weekday() as usize]).unwrap_or("");
+    let mut date_str = String::new();
+    write!(date_str, "{} {:04}-{:02}-{:02}", weekday, date.year, date.month, date.day).ok();
+    date_str
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::Date;
+
+    const H12: Format = Format { clock: Clock::H12, ..DEFAULT_FORMAT };
+    const NO_ZERO: Format = Format { leading_zero: false, ..DEFAULT_FORMAT };
+    const FAHRENHEIT: Format = Format { unit: Unit::Fahrenheit, ..DEFAULT_FORMAT };
+
+    #[test]
+    fn hour_in_24_hour_mode() {
+        assert_eq!(hour(0, &DEFAULT_FORMAT), "00");
+        assert_eq!(hour(7, &DEFAULT_FORMAT), "07");
+        assert_eq!(hour(23, &DEFAULT_FORMAT), "23");
+        assert_eq!(hour(7, &NO_ZERO), " 7");
+        assert_eq!(hour(0, &NO_ZERO), " 0");
+        assert_eq!(hour(19, &NO_ZERO), "19");
+    }
+
+    #[test]
+    fn hour_in_12_hour_mode() {
+        assert_eq!(hour(0, &H12), "12");
+        assert_eq!(hour(7, &H12), "07");
+        assert_eq!(hour(12, &H12), "12");
+        assert_eq!(hour(13, &H12), "01");
+        assert_eq!(hour(23, &H12), "11");
+        let no_zero = Format { leading_zero: false, ..H12 };
+        assert_eq!(hour(13, &no_zero), " 1");
+        assert_eq!(hour(22, &no_zero), "10");
+    }
+
+    #[test]
+    fn meridiem_only_in_12_hour_mode() {
+        assert_eq!(meridiem(0, &H12), "AM");
+        assert_eq!(meridiem(11, &H12), "AM");
+        assert_eq!(meridiem(12, &H12), "PM");
+        assert_eq!(meridiem(23, &H12), "PM");
+        assert_eq!(meridiem(23, &DEFAULT_FORMAT), "");
+    }
+
+    #[test]
+    fn minute_keeps_two_digits() {
+        assert_eq!(minute(0), "00");
+        assert_eq!(minute(5), "05");
+        assert_eq!(minute(59), "59");
+    }
+
+    #[test]
+    fn temperature_in_celsius() {
+        assert_eq!(temperature(21.54, &DEFAULT_FORMAT), "21.5°C");
+        assert_eq!(temperature(-3.0, &DEFAULT_FORMAT), "-3.0°C");
+        assert_eq!(temperature(0.0, &DEFAULT_FORMAT), "0.0°C");
+    }
+
+    #[test]
+    fn temperature_in_fahrenheit() {
+        assert_eq!(temperature(21.5, &FAHRENHEIT), "70.7°F");
+        assert_eq!(temperature(-40.0, &FAHRENHEIT), "-40.0°F");
+        assert_eq!(temperature(40.0, &FAHRENHEIT), "104.0°F");
+    }
+
+    #[test]
+    fn date_with_weekday() {
+        let now = DateTime::new(Date::new(2024, 1, 15).unwrap().to_days(), 0);
+        assert_eq!(date(&now), "Mon 2024-01-15");
+    }
+}
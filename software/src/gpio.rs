@@ -1,12 +1,12 @@
 use {
-    crate::app::*, embedded_hal::digital::v2::OutputPin, hal::{gpio::{
+    hal::{gpio::{
         p0::{Parts as P0Parts, P0_02, P0_03}, p1::Parts as P1Parts, Disconnected, Floating, Input, Level, Output, Pin, PullUp, PushPull
-    }, pac::{P0, P1}}, nrf52833_hal::{self as hal}, rtic::Mutex
+    }, pac::{P0, P1}}, nrf52833_hal::{self as hal}
 };
 
 pub(crate) struct Pins {
     pub(crate) led: Pin<Output<PushPull>>,
-    pub(crate) amp_fan_hum: Pin<Output<PushPull>>,
+    pub(crate) amp_fan_hum: Pin<Output<PushPull>>, // The loads share it, see loads::BOARD
     pub(crate) haptic: Pin<Output<PushPull>>,
     pub(crate) rotary_encoder: hal::qdec::Pins,
     pub(crate) rotary_switch: Pin<Input<PullUp>>,
@@ -64,15 +64,3 @@ pub(crate) fn init(p0: P0, p1: P1) -> Pins {
         speaker,
     }
 }
-
-pub(crate) fn turn_on_amp_fan_hum(mut cx: turn_on_amp_fan_hum::Context) {
-    cx.shared.amp_fan_hum_pin.lock(|amp_fan_hum_pin| {
-        amp_fan_hum_pin.set_high().ok();
-    });
-}
-
-pub(crate) fn turn_off_amp_fan_hum(mut cx: turn_off_amp_fan_hum::Context) {
-    cx.shared.amp_fan_hum_pin.lock(|amp_fan_hum_pin| {
-        amp_fan_hum_pin.set_low().ok();
-    });
-}
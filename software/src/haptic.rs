@@ -0,0 +1,143 @@
+// Vibration patterns of the haptic actuator on PWM channel C1, selected per alarm by index,
+// so new patterns go at the end. A pattern is an envelope of the duty cycle in steps of the
+// PWM sequence, 110 ms each, and repeats until the sequence is full.
+
+use crate::envelope::{Curve, Segment};
+
+pub const DEFAULT_PATTERN: u8 = 0;
+pub const FULL: u16 = 10_000; // Duty cycle at full strength, the PWM counts to 10000
+
+const FOREVER: usize = usize::MAX; // Repeats until the sequence is full
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Pattern {
+    pub name: &'static [u8],
+    pub segments: &'static [Segment],
+}
+
+pub const PATTERNS: [Pattern; 6] = [
+    // One long fade to half strength over the sequence
+    Pattern { name: b"fade", segments: &[Segment::Ramp { from: FULL, to: FULL / 2, steps: 1000, curve: Curve::Linear }] },
+    Pattern {
+        name: b"pulse",
+        segments: &[Segment::Hold { level: FULL, steps: 3 }, Segment::Hold { level: 0, steps: 6 }, Segment::Repeat { segments: 2, times: FOREVER }],
+    },
+    // Lub-dub at about 55 beats per minute
+    Pattern {
+        name: b"heartbeat",
+        segments: &[
+            Segment::Hold { level: FULL, steps: 1 },
+            Segment::Hold { level: 0, steps: 1 },
+            Segment::Hold { level: FULL * 6 / 10, steps: 1 },
+            Segment::Hold { level: 0, steps: 8 },
+            Segment::Repeat { segments: 4, times: FOREVER },
+        ],
+    },
+    Pattern {
+        name: b"wave",
+        segments: &[
+            Segment::Ramp { from: 0, to: FULL, steps: 10, curve: Curve::Sine },
+            Segment::Ramp { from: FULL, to: 0, steps: 10, curve: Curve::Sine },
+            Segment::Repeat { segments: 2, times: FOREVER },
+        ],
+    },
+    Pattern {
+        name: b"double-tap",
+        segments: &[
+            Segment::Hold { level: FULL, steps: 1 },
+            Segment::Hold { level: 0, steps: 1 },
+            Segment::Hold { level: FULL, steps: 1 },
+            Segment::Hold { level: 0, steps: 12 },
+            Segment::Repeat { segments: 4, times: FOREVER },
+        ],
+    },
+    // Buzzes getting stronger, then held at full strength
+    Pattern {
+        name: b"escalating",
+        segments: &[
+            Segment::Hold { level: FULL / 4, steps: 3 },
+            Segment::Hold { level: 0, steps: 3 },
+            Segment::Hold { level: FULL / 2, steps: 3 },
+            Segment::Hold { level: 0, steps: 3 },
+            Segment::Hold { level: FULL * 3 / 4, steps: 3 },
+            Segment::Hold { level: 0, steps: 3 },
+            Segment::Hold { level: FULL, steps: 3 },
+            Segment::Hold { level: 0, steps: 3 },
+            Segment::Repeat { segments: 2, times: FOREVER },
+        ],
+    },
+];
+
+// Pattern by its name, for the CLI
+pub fn find(name: &[u8]) -> Option<u8> {
+    PATTERNS.iter().position(|pattern| pattern.name == name).map(|index| index as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope;
+
+    const SEQUENCE_LENGTH: usize = 1000;
+
+    fn levels(name: &[u8], steps: usize) -> Vec<u16> {
+        envelope::levels(PATTERNS[find(name).unwrap() as usize].segments).take(steps).collect()
+    }
+
+    #[test]
+    fn every_pattern_fills_the_sequence() {
+        for pattern in PATTERNS {
+            let levels: Vec<u16> = envelope::levels(pattern.segments).take(SEQUENCE_LENGTH).collect();
+            assert_eq!(levels.len(), SEQUENCE_LENGTH, "{:?}", pattern.name);
+            assert!(levels.iter().all(|level| *level <= FULL), "{:?}", pattern.name);
+            assert!(levels.iter().any(|level| *level > 0), "{:?}", pattern.name);
+        }
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (index, pattern) in PATTERNS.iter().enumerate() {
+            assert_eq!(find(pattern.name), Some(index as u8));
+        }
+        assert_eq!(find(b"purr"), None);
+    }
+
+    #[test]
+    fn default_fades_to_half_strength() {
+        let levels = levels(b"fade", SEQUENCE_LENGTH);
+        assert_eq!((levels[0], levels[SEQUENCE_LENGTH - 1]), (FULL, FULL / 2));
+        assert_eq!(DEFAULT_PATTERN, find(b"fade").unwrap());
+    }
+
+    #[test]
+    fn pulse_repeats() {
+        let on = [FULL; 3];
+        let off = [0; 6];
+        assert_eq!(levels(b"pulse", 18), [on, on].map(|on| [&on[..], &off[..]].concat()).concat());
+    }
+
+    #[test]
+    fn heartbeat_beats_twice_then_rests() {
+        let beat = [FULL, 0, FULL * 6 / 10, 0, 0, 0, 0, 0, 0, 0, 0];
+        assert_eq!(levels(b"heartbeat", 22), [beat, beat].concat());
+    }
+
+    #[test]
+    fn double_tap_taps_twice() {
+        assert_eq!(levels(b"double-tap", 16), [FULL, 0, FULL, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, FULL]);
+    }
+
+    #[test]
+    fn wave_swells_and_ebbs() {
+        let levels = levels(b"wave", 21);
+        assert_eq!((levels[0], levels[9], levels[10], levels[19], levels[20]), (0, FULL, FULL, 0, 0));
+    }
+
+    #[test]
+    fn escalating_buzz_gets_stronger() {
+        let levels = levels(b"escalating", 36);
+        let buzzes: Vec<u16> = levels.iter().step_by(6).cloned().collect();
+        assert_eq!(buzzes, [FULL / 4, FULL / 2, FULL * 3 / 4, FULL, FULL, FULL]);
+        assert!(levels.chunks(6).all(|buzz| buzz[3..] == [0; 3]));
+    }
+}
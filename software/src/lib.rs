@@ -0,0 +1,26 @@
+// Hardware independent clock logic, shared by the firmware and the host tests
+#![cfg_attr(not(test), no_std)]
+#![deny(warnings)]
+
+pub mod adpcm;
+pub mod alarm;
+pub mod counter;
+pub mod envelope;
+pub mod format;
+pub mod haptic;
+pub mod mixer;
+pub mod night_light;
+pub mod outputs;
+pub mod pcm;
+pub mod persist;
+pub mod rtttl;
+pub mod screen;
+pub mod sound;
+pub mod state_machine;
+pub mod store;
+pub mod surf;
+pub mod thermostat;
+pub mod time;
+pub mod timer;
+pub mod volume;
+pub mod wav;
@@ -0,0 +1,104 @@
+use {
+    crate::{
+        app::*,
+        outputs::{Board, Driver, Load, Outputs, LOADS},
+        rtc,
+        thermostat::Thermostat,
+    },
+    embedded_hal::digital::v2::OutputPin,
+    hal::{
+        gpio::{Output, Pin, PushPull},
+        pac::PWM1,
+        pwm::{Channel, Prescaler, Pwm},
+    },
+    nrf52833_hal as hal,
+    rtic::Mutex,
+};
+
+#[cfg(feature = "52833-debug")]
+use rtt_target::rprintln;
+
+// On this board the amp, fan and humidifier share one pin. A board with a pin per load
+// passes them to init and maps the loads to Driver::Gpio(0), (1) and (2). A Driver::Pwm
+// is a channel of PWM1, PWM0 plays the LED and haptic sequences and stops with them.
+pub(crate) const BOARD: Board = [Driver::Gpio(0); LOADS];
+pub(crate) const PINS: usize = 1;
+
+const MAX_DUTY: u16 = 640; // 25 kHz from 16 MHz, above hearing and what PC fans expect
+const CHANNELS: [Channel; 4] = [Channel::C0, Channel::C1, Channel::C2, Channel::C3];
+
+pub struct Loads {
+    outputs: Outputs,
+    pins: [Pin<Output<PushPull>>; PINS],
+    pwm: Pwm<PWM1>,
+}
+
+// `pwm_pins` are connected to the PWM1 channels in order
+pub(crate) fn init(pwm: PWM1, pins: [Pin<Output<PushPull>>; PINS], pwm_pins: [Option<Pin<Output<PushPull>>>; 4]) -> Loads {
+    let pwm = Pwm::new(pwm);
+    pwm.set_prescaler(Prescaler::Div1).set_max_duty(MAX_DUTY);
+    for (channel, pin) in CHANNELS.into_iter().zip(pwm_pins) {
+        if let Some(pin) = pin {
+            pwm.set_output_pin(channel, pin);
+        }
+    }
+    Loads { outputs: Outputs::new(BOARD), pins, pwm }
+}
+
+impl Loads {
+    pub(crate) fn outputs(&self) -> &Outputs {
+        &self.outputs
+    }
+
+    fn drive(&mut self, driver: Driver, duty: u8) {
+        match driver {
+            Driver::Gpio(index) => {
+                if let Some(pin) = self.pins.get_mut(index) {
+                    match duty {
+                        0 => pin.set_low().ok(),
+                        _ => pin.set_high().ok(),
+                    };
+                }
+            }
+            Driver::Pwm(channel) => {
+                if let Some(channel) = CHANNELS.get(channel) {
+                    self.pwm.set_duty_on(*channel, (MAX_DUTY as u32 * duty as u32 / 100) as u16);
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn set_output(mut cx: set_output::Context, load: Load, percent: u8) {
+    cx.shared.loads.lock(|loads| {
+        let (driver, duty) = loads.outputs.set(load, percent);
+        #[cfg(feature = "52833-debug")]
+        rprintln!("{:?} at {}%, {:?} at {}%", load, percent, driver, duty);
+        loads.drive(driver, duty);
+    });
+}
+
+// The thermostat speed is a floor of the fan, an alarm stage may run it faster. Stopping it
+// clears the floor and starts the thermostat over, as if the fan was never on.
+pub(crate) fn update_thermostat(mut cx: update_thermostat::Context, running: bool) {
+    let speed = match running {
+        true => {
+            let temperature = cx.shared.temperature.lock(|temperature| *temperature);
+            let settings = cx.shared.thermostat.lock(|settings| *settings);
+            let overflows = cx.shared.rtc_overflows;
+            let now = cx.shared.rtc.lock(|rtc| rtc::now(rtc, overflows));
+            let now = cx.shared.time_offset.lock(|time_offset| rtc::date_time(*time_offset, now));
+            cx.local.thermostat.update(&settings, temperature, now.ticks)
+        }
+        false => {
+            *cx.local.thermostat = Thermostat::new();
+            0
+        }
+    };
+    cx.shared.loads.lock(|loads| {
+        let (driver, duty) = loads.outputs.set_floor(Load::Fan, speed);
+        #[cfg(feature = "52833-debug")]
+        rprintln!("Thermostat at {}%, {:?} at {}%", speed, driver, duty);
+        loads.drive(driver, duty);
+    });
+}
@@ -1,6 +1,8 @@
 #![no_main]
 #![no_std]
 #![allow(dead_code)]
+// A failed spawn hands the message back, a ringtone from the CLI makes that a large error
+#![allow(clippy::result_large_err)]
 //#![deny(unsafe_code)]
 #![deny(warnings)]
 
@@ -10,17 +12,21 @@ mod gpio;
 mod pwm;
 mod rotary_encoder;
 mod rtc;
-mod state_machine;
 mod thermistor;
 mod uicr;
 mod backup_mode;
 mod cli;
 mod speaker;
+mod loads;
+mod monotonic;
+mod flash;
+
+use seabreeze::{alarm, counter, envelope, format, haptic, mixer, night_light, outputs::{self, Load}, pcm, persist, rtttl, screen, sound, state_machine, store, surf, thermostat, volume, wav, time::{self, DateTime}, timer::{self, Timer, TimerQueue}};
 
 use {
     cli::*,
-    crate::{display::Display, pwm::Pwm0, state_machine::*},
-    core::sync::atomic::{AtomicU32, AtomicBool, Ordering},
+    crate::{alarm::{Alarm, Stage, ALARM_SLOTS, ALARM_STAGES}, display::Display, pwm::Pwm0, state_machine::*},
+    core::sync::atomic::{AtomicU8, AtomicU32, AtomicBool, AtomicUsize, Ordering},
     cortex_m::asm,
     hal::{
         gpio::*,
@@ -42,6 +48,7 @@ use {
     },
     nrf52833_hal as hal, 
     panic_rtt_target as _,
+    rtic::Mutex,
     rtt_target::UpChannel,
     usb_device::device::UsbDevice,
     usbd_serial::{SerialPort, USB_CLASS_CDC},
@@ -62,25 +69,47 @@ use {
 mod app {
     use super::*;
 
+    #[monotonic(binds = RTC2, default = true)]
+    type Mono = monotonic::MonoRtc;
+
     #[shared]
     struct Shared {
         rtt_hw: UpChannel,
         rtt_serial: UpChannel,
         rtc: Rtc<hal::pac::RTC1>,
-        time_offset_ticks: AtomicU32,  // Time offset in ticks from 00:00
-        alarm_offset_ticks: AtomicU32, // Alarm offset in ticks from 00:00
-        amp_on: AtomicBool,
+        timers: TimerQueue,            // Deadlines multiplexed onto the RTC1 compare registers
+        rtc_overflows: AtomicU32,      // Extends the RTC counter to monotonic ticks
+        time_offset: i64,              // Ticks since 2000-01-01 00:00 at monotonic tick 0
+        alarms: [Alarm; ALARM_SLOTS],
+        next_alarm_slot: AtomicUsize,  // Slot the alarm timer rings
+        snooze_minutes: AtomicU32,
+        snooze_limit: AtomicU32,       // Number of snoozes before a press dismisses the alarm
+        sunrise_minutes: AtomicU32,    // LED ramp before the alarm, 0 disables it
+        sunrise_slot: AtomicUsize,     // Slot of the alarm the sunrise timer leads up to
+        settings_timeout_minutes: AtomicU32,
+        alarm_timeout_minutes: AtomicU32,
+        audio_playing: AtomicBool,     // The I2S runs until the mixer has faded out every voice
+        amp_off_pending: AtomicBool,   // Switch the amp off once the audio has stopped
+        audio_gain: AtomicU32,         // Percent, set by the alarm stage
+        volume: AtomicU8,              // Steps of 2 dB on top of the stage gain, see volume::gain
+        alarm_stages: [Stage; ALARM_STAGES],
         temperature: f32,
+        thermostat: thermostat::Settings,
+        night_light: night_light::Settings,
+        format: format::Format,        // Of the time and temperature on the screen
         #[lock_free]
         pwm: Pwm0,
+        #[lock_free]
+        led_level: u16,                // Duty cycle of the night light, kept by the haptic
         display: Display,
-        amp_fan_hum_pin: Pin<Output<PushPull>>,
+        loads: loads::Loads,
         #[lock_free]
         usb_dev: UsbDevice<'static, Usbd<UsbPeripheral<'static>>>,
         #[lock_free]
         serial: SerialPort<'static, Usbd<UsbPeripheral<'static>>>, 
         gpiote: Gpiote,
         qdec: Qdec,
+        speaker: speaker::Speaker,
     }
 
     #[local]
@@ -88,19 +117,18 @@ mod app {
         rtt_display: UpChannel,
         rtt_state: UpChannel,
         rtt_speaker: UpChannel,
-        state_machine: State,
         saadc: Saadc,
         saadc_pin: p0::P0_03<Disconnected>,
         comp: LpComp,
-        dma_buf: [u32; speaker::BUFFER_LEN],
-        i2s: Option<hal::i2s::I2S>,
+        store: flash::SettingsStore,
     }
 
     #[init(local = [
-        SEQBUF0: [u16; pwm::SEQUENCE_LENGTH*4] = [0u16; pwm::SEQUENCE_LENGTH*4],
-        SEQBUF1: [u16; pwm::SEQUENCE_LENGTH*4] = [0u16; pwm::SEQUENCE_LENGTH*4],
+        SEQBUF0: [u16; pwm::SEQUENCE_LENGTH*pwm::CHANNELS] = [0u16; pwm::SEQUENCE_LENGTH*pwm::CHANNELS],
+        SEQBUF1: [u16; pwm::SEQUENCE_LENGTH*pwm::CHANNELS] = [0u16; pwm::SEQUENCE_LENGTH*pwm::CHANNELS],
         clocks: Option<Clocks<ExternalOscillator, Internal, LfOscStarted>> = None,
         usb_bus: Option<UsbBusAllocator<Usbd<UsbPeripheral<'static>>>> = None, 
+        AUDIO_BUFFERS: [[u32; speaker::BUFFER_LEN]; 2] = [[0u32; speaker::BUFFER_LEN]; 2],
     ])]
     fn init(mut cx: init::Context) -> (Shared, Local, init::Monotonics) {
         let (rtt_display, rtt_hw, rtt_state, rtt_serial, rtt_speaker) = rtt::init();
@@ -148,15 +176,21 @@ mod app {
         let pins = gpio::init(cx.device.P0, cx.device.P1);
 
         // Initialize UICR
-        uicr::init(cx.device.UICR, cx.device.NVMC);
+        let nvmc = uicr::init(cx.device.UICR, cx.device.NVMC);
+
+        // Restore the settings saved in flash
+        let (store, config) = flash::init(nvmc);
 
         // Initialize PWM
         let pwm = pwm::init(cx.device.PWM0, pins.led, pins.haptic);
         let pwm = pwm.load(Some(SEQBUF0), Some(SEQBUF1), false).ok();
-        load_pwm_sequence::spawn().ok();
+        load_pwm_sequence::spawn(haptic::DEFAULT_PATTERN, 100).ok();
 
         // Initialize the RTC peripheral
-        let rtc = rtc::init(cx.device.RTC1);
+        let (rtc, timers) = rtc::init(cx.device.RTC1);
+
+        // Initialize the monotonic for spawn_after, it runs from the LFCLK as well
+        let mono = monotonic::MonoRtc::new(cx.device.RTC2);
 
         // Initialize the rotary encoder and switch
         let (qdec, gpiote) = rotary_encoder::init(
@@ -173,47 +207,58 @@ mod app {
         let saadc = thermistor::init(cx.device.SAADC);
         read_temperature::spawn().ok();
 
-        // Simulate user setting the time
-        let time_ticks = rtc::time_to_ticks(06, 20);
-        set_time::spawn(time_ticks).ok();
-
-        // Simulate user setting the alarm,
-        let alarm_ticks = rtc::time_to_ticks(06, 21);
-        set_alarm::spawn(alarm_ticks).ok();
+        // Arm the restored alarms, the clock starts at 2000-01-01 00:00 until the time is set
+        arm_next_alarm::spawn().ok();
 
         let comp = backup_mode::init(cx.device.LPCOMP, pins.vdetect);
 
-        let i2s = speaker::init(cx.device.I2S, pins.speaker);
+        let speaker = speaker::init(cx.device.I2S, pins.speaker, cx.local.AUDIO_BUFFERS);
         enable_display::spawn().ok();
         (
             Shared {
                 rtt_serial,
                 rtt_hw,
                 rtc,
-                time_offset_ticks: AtomicU32::new(time_ticks),
-                alarm_offset_ticks: AtomicU32::new(alarm_ticks),
-                amp_on: AtomicBool::new(false),
+                timers,
+                rtc_overflows: AtomicU32::new(0),
+                time_offset: 0,
+                alarms: config.alarms,
+                next_alarm_slot: AtomicUsize::new(0),
+                snooze_minutes: AtomicU32::new(config.snooze_minutes),
+                snooze_limit: AtomicU32::new(config.snooze_limit),
+                sunrise_minutes: AtomicU32::new(config.sunrise_minutes),
+                sunrise_slot: AtomicUsize::new(0),
+                settings_timeout_minutes: AtomicU32::new(config.settings_timeout_minutes),
+                alarm_timeout_minutes: AtomicU32::new(config.alarm_timeout_minutes),
+                audio_playing: AtomicBool::new(false),
+                amp_off_pending: AtomicBool::new(false),
+                audio_gain: AtomicU32::new(100),
+                volume: AtomicU8::new(config.volume),
+                alarm_stages: config.stages,
                 temperature: 0.0,
+                thermostat: config.thermostat,
+                night_light: config.night_light,
+                format: config.format,
                 pwm,
+                led_level: 0,
                 display,
-                amp_fan_hum_pin: pins.amp_fan_hum,
+                loads: loads::init(cx.device.PWM1, [pins.amp_fan_hum], [None, None, None, None]),
                 usb_dev,
                 serial,
                 gpiote,
                 qdec,
+                speaker,
             },
             Local {
                 rtt_display,
                 rtt_state,
                 rtt_speaker,
-                state_machine: State::Idle,
                 saadc,
                 saadc_pin: pins.saadc,
                 comp,
-                dma_buf: [0u32; speaker::BUFFER_LEN],
-                i2s: Some(i2s),
+                store,
             },
-            init::Monotonics(),
+            init::Monotonics(mono),
         )
     }
 
@@ -229,202 +274,43 @@ mod app {
     #[task(
         priority = 4, 
         capacity = 10, 
-        local = [state_machine, current_ticks: u32 = 0, temp_ticks: u32 = 0, rtt_state], 
-        shared = [&time_offset_ticks, &alarm_offset_ticks, &amp_on])]
-    fn state_machine(cx: state_machine::Context, event: Event) {
-        let state = *cx.local.state_machine;
-        let next_state = state.next(event);
-        *cx.local.state_machine = next_state;
+        local = [
+            clock: Clock = Clock::new(),
+            timeout: Option<state_machine::SpawnHandle> = None,
+            blink: Option<state_machine::SpawnHandle> = None,
+            save: Option<save_settings::SpawnHandle> = None,
+            rtt_state,
+        ], 
+        shared = [alarms, alarm_stages, &audio_playing, &amp_off_pending, &audio_gain, &volume, &snooze_minutes, &snooze_limit, &sunrise_minutes, &settings_timeout_minutes, &alarm_timeout_minutes, thermostat, night_light, format])]
+    fn state_machine(mut cx: state_machine::Context, event: Event) {
+        let config = Config {
+            alarms: cx.shared.alarms.lock(|alarms| *alarms),
+            stages: cx.shared.alarm_stages.lock(|stages| *stages),
+            snooze_minutes: cx.shared.snooze_minutes.load(Ordering::Relaxed),
+            snooze_limit: cx.shared.snooze_limit.load(Ordering::Relaxed),
+            sunrise_minutes: cx.shared.sunrise_minutes.load(Ordering::Relaxed),
+            settings_timeout_minutes: cx.shared.settings_timeout_minutes.load(Ordering::Relaxed),
+            alarm_timeout_minutes: cx.shared.alarm_timeout_minutes.load(Ordering::Relaxed),
+            volume: cx.shared.volume.load(Ordering::Relaxed),
+            thermostat: cx.shared.thermostat.lock(|settings| *settings),
+            night_light: cx.shared.night_light.lock(|settings| *settings),
+            format: cx.shared.format.lock(|format| *format),
+        };
+        #[cfg(feature = "52833-debug")]
+        let state = cx.local.clock.state;
+        let (_next_state, actions) = cx.local.clock.next(event, &config);
         #[cfg(feature = "52833-debug")]
         writeln!(
             cx.local.rtt_state, 
-            "State: {:?}, Event: {:?} -> State: {:?}", state, event, next_state
+            "State: {:?}, Event: {:?} -> State: {:?}", state, event, _next_state
         ).ok();
 
-        match event {
-            Event::Timer(TimerEvent::PeriodicUpdate(counter)) => {
-                let new_time = cx.shared.time_offset_ticks.load(Ordering::Relaxed)
-                    + counter % rtc::TICKS_PER_DAY;
-                *cx.local.current_ticks = new_time;
-                read_temperature::spawn().ok();
-                set_periodic_update::spawn(rtc::TICKS_PER_MINUTE).ok();
-
-                match state {
-                    State::Idle => {
-                        update_display::spawn(new_time, display::Section::Display, false).ok();
-                    }
-                    _ => {}
-                }
-            }
-            Event::Timer(TimerEvent::AlarmTriggered) => {
-                match state {
-                    State::Idle => {
-                        cx.shared.amp_on.store(true, Ordering::Relaxed); 
-                        turn_on_amp_fan_hum::spawn().ok();
-                        disable_alarm::spawn().ok();
-                        start_pwm::spawn().ok();
-                        update_display::spawn(*cx.local.current_ticks, display::Section::AlarmIcon, false).ok();
-                        play_next_audio_segment::spawn().ok();
-                    }
-                    _ => {}
-                }
-            }
-            Event::Timer(TimerEvent::Timeout) => {
-                match state {
-                    State::Settings(_) => {
-                        disable_blinking::spawn().ok();
-                        update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                    }
-                    State::Alarm => {
-                        disable_alarm_components(&cx);
-                        update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                    }
-                    _ => {}
-                }
-            }
-            Event::Timer(TimerEvent::Blink) => {
-                match state {
-                    State::Alarm => {
-                        update_display::spawn(*cx.local.current_ticks, display::Section::AlarmIcon, true).ok();
-                        set_blinking::spawn(rtc::BLINK_TICKS).ok();
-
-                    }
-                    State::Settings(settings) => match settings {
-                        Settings::ClockHours => {
-                            update_display::spawn(*cx.local.temp_ticks, display::Section::Hour, true).ok();
-                            set_blinking::spawn(rtc::BLINK_TICKS).ok();
-
-                        }
-                        Settings::ClockMinutes => {
-                            update_display::spawn(*cx.local.temp_ticks, display::Section::Minute, true).ok();
-                            set_blinking::spawn(rtc::BLINK_TICKS).ok();
-
-                        }
-                        Settings::AlarmHours => {
-                            update_display::spawn(*cx.local.temp_ticks, display::Section::Hour, true).ok();
-                            set_blinking::spawn(rtc::BLINK_TICKS).ok();
-
-                        }
-                        Settings::AlarmMinutes => {
-                            update_display::spawn(*cx.local.temp_ticks, display::Section::Minute, true).ok();
-                            set_blinking::spawn(rtc::BLINK_TICKS).ok();
-                        }
-                    },
-                    _ => {}
-                }
-            }
-            Event::Encoder(EncoderEvent::ShortPressed) => match state {
-                State::Idle => {
-                    let alarm_time = cx.shared.alarm_offset_ticks.load(Ordering::Relaxed);
-                    *cx.local.temp_ticks = alarm_time;
-
-                    disable_alarm::spawn().ok();
-                    set_timeout::spawn(rtc::TIMEOUT_SETTINGS_TICKS).ok();
-                    set_blinking::spawn(rtc::BLINK_TICKS).ok();
-                    update_display::spawn(alarm_time, display::Section::Display, false).ok();
-                }
-                State::Alarm => {
-                    disable_alarm_components(&cx);
-                    update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                }
-                State::Settings(settings) => match settings {
-                    Settings::ClockMinutes => {
-                        *cx.local.current_ticks = *cx.local.temp_ticks;
-                        disable_blinking::spawn().ok();
-                        set_time::spawn(*cx.local.temp_ticks).ok();
-                        set_alarm::spawn(cx.shared.alarm_offset_ticks.load(Ordering::Relaxed)).ok();
-                        update_display::spawn(*cx.local.temp_ticks, display::Section::Display, false).ok();
-                        set_periodic_update::spawn(rtc::TICKS_PER_MINUTE).ok();
-                    }
-                    Settings::AlarmMinutes => {
-                        set_alarm::spawn(*cx.local.temp_ticks).ok();
-                        disable_blinking::spawn().ok();
-                        update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                    }
-                    _ => {}
-                },
-                _ => {}
-            },
-            Event::Encoder(EncoderEvent::LongPressed) => match state {
-                State::Idle => {
-                    let temp = *cx.local.current_ticks;
-                    *cx.local.temp_ticks = temp;
-
-                    disable_periodic_update::spawn().ok();
-                    disable_alarm::spawn().ok();
-                    set_blinking::spawn(rtc::BLINK_TICKS).ok();
-                    set_timeout::spawn(rtc::TIMEOUT_SETTINGS_TICKS).ok();
-                }
-                State::Alarm => {
-                    disable_alarm_components(&cx);
-                    update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                }
-                _ => {}
-            },
-            Event::Encoder(EncoderEvent::Rotated(direction)) => {
-                match state {
-                    State::Settings(settings) => {
-                        let mut diff = direction;
-                        match settings {
-                            Settings::ClockHours => {
-                                diff = diff * rtc::TICKS_PER_HOUR as isize;
-                                let temp = *cx.local.temp_ticks;
-                                let new_time = (temp as isize + diff).rem_euclid(rtc::TICKS_PER_DAY as isize) as u32;
-                                *cx.local.temp_ticks = new_time;
-                            }
-                            Settings::ClockMinutes => {
-                                diff = diff * rtc::TICKS_PER_MINUTE as isize;
-                                let temp = *cx.local.temp_ticks;
-                                let new_time = (temp as isize + diff).rem_euclid(rtc::TICKS_PER_DAY as isize) as u32;
-                                *cx.local.temp_ticks = new_time;
-                            }
-                            Settings::AlarmHours => {
-                                diff = diff * rtc::TICKS_PER_HOUR as isize;
-                                let temp = *cx.local.temp_ticks;
-                                let new_time = (temp as isize + diff).rem_euclid(rtc::TICKS_PER_DAY as isize) as u32;
-                                *cx.local.temp_ticks = new_time;
-                            }
-                            Settings::AlarmMinutes => {
-                                diff = diff * rtc::TICKS_PER_MINUTE as isize;
-                                let temp = *cx.local.temp_ticks;
-                                let new_time = (temp as isize + diff).rem_euclid(rtc::TICKS_PER_DAY as isize) as u32;
-                                *cx.local.temp_ticks = new_time;
-                            }
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            Event::VBUSConnected => {
-                match state {
-                    State::BackupBattery => { // Just in case
-                        set_alarm::spawn(*cx.local.current_ticks).ok();
-                        set_periodic_update::spawn(rtc::TICKS_PER_MINUTE).ok();
-                        rotary_encoder_enable_interrupts::spawn().ok();
-                        enable_display::spawn().ok();
-                        update_display::spawn(*cx.local.current_ticks, display::Section::Display, false).ok();
-                    }
-                    _ => {}
-                }
-            }
-            Event::VBUSDisconnected => {
-                match state {
-                    State::BackupBattery => {} // Just in case
-                    _ => {
-                        rotary_disable_interrupts::spawn().ok();
-                        disable_alarm::spawn().ok();
-                        disable_periodic_update::spawn().ok();
-                        disable_timeout::spawn().ok();
-                        disable_display::spawn().ok();
-                        disable_alarm_components(&cx);
-                    }
-                }
-            }
-            _ => {}
+        for action in actions {
+            execute_action(&mut cx, action);
         }
     }
 
-    #[task(binds = RTC1, priority = 4, shared = [rtc, &time_offset_ticks, rtt_hw])]
+    #[task(binds = RTC1, priority = 4, shared = [rtc, &rtc_overflows, timers, time_offset, &next_alarm_slot, &sunrise_slot, rtt_hw])]
     fn rtc_interrupt(cx: rtc_interrupt::Context) {
         rtc::handle_interrupt(cx);
     }
@@ -459,81 +345,102 @@ mod app {
         rotary_encoder::enable_interrupts(cx);
     }
 
-    #[task(priority = 3, shared = [rtc, &time_offset_ticks])]
+    #[task(priority = 3, shared = [rtc, &rtc_overflows, time_offset])]
     fn set_time(cx: set_time::Context, ticks: u32) {
         #[cfg(feature = "52833-debug")]
         rprintln!("Setting time, ticks: {}", ticks);
         rtc::set_time(cx, ticks);
     }
 
-    #[task(priority = 3, shared = [rtc, &alarm_offset_ticks, &time_offset_ticks])]
-    fn set_alarm(cx: set_alarm::Context, ticks: u32) {
+    #[task(priority = 3, shared = [rtc, &rtc_overflows, time_offset])]
+    fn set_date(cx: set_date::Context, days: u32) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("Setting date, days: {}", days);
+        rtc::set_date(cx, days);
+    }
+
+    #[task(priority = 3, shared = [alarms])]
+    fn set_alarm(cx: set_alarm::Context, slot: usize, alarm: Alarm) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Setting alarm, ticks: {}", ticks);
-        rtc::set_alarm(cx, ticks);
+        rprintln!("Setting alarm, slot: {}, alarm: {:?}", slot, alarm);
+        rtc::set_alarm(cx, slot, alarm);
     }
 
-    #[task(priority = 5, shared = [rtc])]
-    fn disable_alarm(cx: disable_alarm::Context) {
+    #[task(priority = 3, shared = [rtc, &rtc_overflows, timers, alarms, time_offset, &next_alarm_slot, &sunrise_minutes, &sunrise_slot])]
+    fn arm_next_alarm(cx: arm_next_alarm::Context) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Disabling alarm");
-        rtc::disable_alarm(cx);
+        rprintln!("Arming next alarm");
+        rtc::arm_next_alarm(cx);
     }
 
-    #[task(priority = 1, shared = [rtc])]
+    #[task(priority = 3, shared = [rtc, &rtc_overflows, timers])]
+    fn set_snooze(cx: set_snooze::Context, ticks: u32) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("Setting snooze, ticks: {}", ticks);
+        rtc::set_snooze(cx, ticks);
+    }
+
+    #[task(priority = 1, shared = [rtc, &rtc_overflows, timers, time_offset])]
     fn set_periodic_update(cx: set_periodic_update::Context, interval_minutes: u32) {
         #[cfg(feature = "52833-debug")]
         rprintln!("Setting periodic update, ticks: {}", interval_minutes);
         rtc::set_periodic_update(cx, interval_minutes);
     }
 
-    #[task(priority = 5, shared = [rtc])]
-    fn disable_periodic_update(cx: disable_periodic_update::Context) {
+    #[task(priority = 3, capacity = 4, shared = [rtc, &rtc_overflows, timers])]
+    fn stop_timer(cx: stop_timer::Context, timer: Timer) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("Stopping timer {:?}", timer);
+        rtc::stop_timer(cx, timer);
+    }
+
+    #[task(priority = 3, local = [saadc, saadc_pin], shared = [temperature])]
+    fn read_temperature(cx: read_temperature::Context) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Disabling periodic update");
-        rtc::disable_periodic_update(cx);
+        rprintln!("read_temperature");
+        thermistor::read(cx);
     }
 
-    #[task(priority = 3, shared = [rtc, &time_offset_ticks])]
-    fn set_timeout(cx: set_timeout::Context, ticks: u32) {
+    #[task(priority = 3, local = [thermostat: thermostat::Thermostat = thermostat::Thermostat::new()], shared = [temperature, thermostat, rtc, &rtc_overflows, time_offset, loads])]
+    fn update_thermostat(cx: update_thermostat::Context, running: bool) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Setting timeout, ticks: {}", ticks);
-        rtc::set_timeout(cx, ticks);
+        rprintln!("update_thermostat, running: {}", running);
+        loads::update_thermostat(cx, running);
     }
 
-    #[task(priority = 3, shared = [rtc])]
-    fn disable_timeout(cx: disable_timeout::Context) {
+    #[task(priority = 3, shared = [pwm, led_level])]
+    fn load_pwm_sequence(cx: load_pwm_sequence::Context, haptic_pattern: u8, haptic_intensity: u8) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Disabling timeout");
-        rtc::disable_timeout(cx);
+        rprintln!("load_pwm_sequence, haptic_pattern: {}, haptic_intensity: {}", haptic_pattern, haptic_intensity);
+        pwm::load_pwm_sequence(cx, haptic_pattern, haptic_intensity);
     }
 
-    #[task(priority = 3, shared = [rtc])]
-    fn set_blinking(cx: set_blinking::Context, interval_ticks: u32) {
+    #[task(priority = 3, shared = [pwm, led_level])]
+    fn play_haptic(cx: play_haptic::Context, pattern: u8) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("Setting blinking, interval_ticks: {}", interval_ticks);
-        rtc::set_blinking(cx, interval_ticks);
+        rprintln!("play_haptic, pattern: {}", pattern);
+        pwm::play_haptic(cx, pattern);
     }
 
-    #[task(priority = 5, shared = [rtc])]
-    fn disable_blinking(cx: disable_blinking::Context) {
+    #[task(priority = 3, shared = [pwm, led_level])]
+    fn stop_haptic(cx: stop_haptic::Context) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("disable_blinking");
-        rtc::disable_blinking(cx);
+        rprintln!("stop_haptic");
+        pwm::stop_haptic(cx);
     }
 
-    #[task(priority = 3, local = [saadc, saadc_pin], shared = [temperature])]
-    fn read_temperature(cx: read_temperature::Context) {
+    #[task(priority = 3, shared = [pwm, led_level])]
+    fn set_night_light(cx: set_night_light::Context, brightness: u8) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("read_temperature");
-        thermistor::read(cx);
+        rprintln!("set_night_light, brightness: {}", brightness);
+        pwm::set_night_light(cx, brightness);
     }
 
-    #[task(priority = 3, shared = [pwm])]
-    fn load_pwm_sequence(cx: load_pwm_sequence::Context) {
+    #[task(priority = 3, shared = [pwm, led_level])]
+    fn start_sunrise(cx: start_sunrise::Context, minutes: u32) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("load_pwm_sequence");
-        pwm::load_pwm_sequence(cx);
+        rprintln!("start_sunrise, minutes: {}", minutes);
+        pwm::start_sunrise(cx, minutes);
     }
 
     #[task(priority = 3, shared = [pwm])]
@@ -550,17 +457,17 @@ mod app {
         pwm::stop(cx);
     }
 
-    #[task(priority = 5, shared = [display, temperature], local = [on: bool = true, rtt_display])]
+    #[task(priority = 5, shared = [display, temperature, format], local = [on: bool = true, rtt_display])]
     fn update_display(
         cx: update_display::Context,
-        ticks: u32,
-        section: display::Section,
+        now: DateTime,
+        section: Section,
         blink: bool,
     ) {
         #[cfg(feature = "52833-debug")]
         rprintln!("update_display");
-        //display::update_display_rtt(cx, ticks, section, blink);
-        display::update_display(cx, ticks, section, blink);
+        //display::update_display_rtt(cx, now, section, blink);
+        display::update_display(cx, now, section, blink);
     }
 
     #[task(priority = 3, shared = [display])]
@@ -577,18 +484,11 @@ mod app {
         display::disable_display(cx);
     }
 
-    #[task(priority = 3, shared = [amp_fan_hum_pin])]
-    fn turn_on_amp_fan_hum(cx: turn_on_amp_fan_hum::Context) {
-        #[cfg(feature = "52833-debug")]
-        rprintln!("turn_on_amp_fan_hum");
-        gpio::turn_on_amp_fan_hum(cx);
-    }
-
-    #[task(priority = 5, shared = [amp_fan_hum_pin])]
-    fn turn_off_amp_fan_hum(cx: turn_off_amp_fan_hum::Context) {
+    #[task(priority = 5, capacity = 4, shared = [loads])]
+    fn set_output(cx: set_output::Context, load: Load, percent: u8) {
         #[cfg(feature = "52833-debug")]
-        rprintln!("turn_off_amp_fan_hum");
-        gpio::turn_off_amp_fan_hum(cx);
+        rprintln!("set_output");
+        loads::set_output(cx, load, percent);
     }
 
     #[task(priority = 4, shared = [usb_dev, serial, rtt_serial])]
@@ -597,29 +497,131 @@ mod app {
         rprintln!("data_out");
         cli::data_out(cx, data, len);
     }
-    #[task(priority = 3, capacity = 20, local = [len: usize = 0, data_arr :[u8; DATA_IN_BUFFER_SIZE] = [0; DATA_IN_BUFFER_SIZE]], shared = [rtt_serial])]
-    fn data_in(cx: data_in::Context, data: u8){
+    #[task(priority = 3, capacity = 4, local = [len: usize = 0, data_arr :[u8; DATA_IN_BUFFER_SIZE] = [0; DATA_IN_BUFFER_SIZE]], shared = [rtt_serial])]
+    fn data_in(cx: data_in::Context, packet: [u8; DATA_IN_PACKET_SIZE], count: usize){
         #[cfg(feature = "52833-debug")]
         rprintln!("data_in");
-        cli::data_in(cx, data);
+        cli::data_in(cx, packet, count);
     }
 
-    #[task(priority = 3, shared = [rtt_serial, &time_offset_ticks, &alarm_offset_ticks])]
+    // A packet may hold several lines
+    #[task(priority = 3, capacity = 4, shared = [rtt_serial, rtc, &rtc_overflows, alarms, alarm_stages, time_offset, &snooze_minutes, &snooze_limit, &sunrise_minutes, &settings_timeout_minutes, &alarm_timeout_minutes, &volume, &audio_playing, &amp_off_pending, &audio_gain, loads, thermostat, night_light, format])]
     fn cli_commands(cx: cli_commands::Context, command: CliCommand) {
         #[cfg(feature = "52833-debug")]
         rprintln!("cli_commands");
         cli::cli_commands(cx, command);
     }
 
-    #[task(priority = 1, shared = [&amp_on], local = [i2s, dma_buf, segment_index: u32 = 0, rtt_speaker])]
-    fn play_next_audio_segment(cx: play_next_audio_segment::Context) {
-        speaker::next_segment(cx);
+    // One save may be pending from the knob while the CLI saves
+    #[task(priority = 1, capacity = 2, local = [store], shared = [alarms, alarm_stages, &snooze_minutes, &snooze_limit, &sunrise_minutes, &settings_timeout_minutes, &alarm_timeout_minutes, &volume, thermostat, night_light, format])]
+    fn save_settings(cx: save_settings::Context) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("save_settings");
+        flash::save_settings(cx);
+    }
+
+    #[task(priority = 3, shared = [speaker, &audio_playing, &audio_gain])]
+    fn start_audio(cx: start_audio::Context, sound: u8, looped: bool) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("start_audio");
+        speaker::start(cx, sound, looped);
+    }
+
+    #[task(priority = 3, shared = [speaker, &audio_playing])]
+    fn play_tune(cx: play_tune::Context, text: rtttl::Text) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("play_tune");
+        speaker::play_tune(cx, &text);
+    }
+
+    #[task(priority = 3, shared = [speaker])]
+    fn stop_audio(cx: stop_audio::Context) {
+        #[cfg(feature = "52833-debug")]
+        rprintln!("stop_audio");
+        speaker::stop(cx);
+    }
+
+    // Above the display updates, a late refill is heard as a glitch
+    #[task(binds = I2S, priority = 6, local = [rtt_speaker], shared = [speaker, &audio_playing, &amp_off_pending, &volume])]
+    fn i2s_interrupt(cx: i2s_interrupt::Context) {
+        speaker::refill(cx);
+    }
+
+    // Spawns the task carrying out an action of the state machine
+    fn execute_action(cx: &mut state_machine::Context, action: Action) {
+        match action {
+            Action::SetTime(ticks) => set_time::spawn(ticks).ok(),
+            Action::SetAlarm(slot, alarm) => set_alarm::spawn(slot, alarm).ok(),
+            Action::ArmNextAlarm => arm_next_alarm::spawn().ok(),
+            Action::DisableAlarm => {
+                stop_timer::spawn(Timer::Alarm).ok();
+                stop_timer::spawn(Timer::Sunrise).ok();
+                stop_timer::spawn(Timer::Snooze).ok()
+            }
+            Action::SetSnooze(ticks) => set_snooze::spawn(ticks).ok(),
+            Action::DisableSnooze => stop_timer::spawn(Timer::Snooze).ok(),
+            Action::SetPeriodicUpdate(ticks) => set_periodic_update::spawn(ticks).ok(),
+            Action::DisablePeriodicUpdate => stop_timer::spawn(Timer::PeriodicUpdate).ok(),
+            Action::SetTimeout(ticks) => schedule(cx.local.timeout, ticks, Event::Timer(TimerEvent::Timeout)),
+            Action::DisableTimeout => cancel(cx.local.timeout),
+            Action::SetBlinking(ticks) => schedule(cx.local.blink, ticks, Event::Timer(TimerEvent::Blink)),
+            Action::DisableBlinking => cancel(cx.local.blink),
+            Action::ReadTemperature => read_temperature::spawn().ok(),
+            Action::StartSunrise(minutes) => start_sunrise::spawn(minutes).ok(),
+            Action::LoadPwmSequence(haptic_pattern, haptic_intensity) => load_pwm_sequence::spawn(haptic_pattern, haptic_intensity).ok(),
+            Action::StartPwm => start_pwm::spawn().ok(),
+            Action::StopPwm => stop_pwm::spawn().ok(),
+            Action::StartAudio(sound, gain) => {
+                cx.shared.audio_gain.store(gain, Ordering::Relaxed);
+                start_audio::spawn(sound, true).ok()
+            }
+            // The sound fades out, the I2S interrupt stops once it is silent
+            Action::StopAudio => stop_audio::spawn().ok(),
+            Action::SetVolume(volume) => {
+                // Stored right away, the next turn of the knob starts from here
+                cx.shared.volume.store(volume, Ordering::Relaxed);
+                save_later(cx.local.save)
+            }
+            Action::SetOutput(Load::Amp, 0) => speaker::turn_off_amp_after_audio(cx.shared.audio_playing, cx.shared.amp_off_pending),
+            Action::SetOutput(load, percent) => {
+                if load == Load::Amp {
+                    cx.shared.amp_off_pending.store(false, Ordering::Relaxed);
+                }
+                set_output::spawn(load, percent).ok()
+            }
+            Action::StopThermostat => update_thermostat::spawn(false).ok(),
+            Action::SetNightLight(brightness) => set_night_light::spawn(brightness).ok(),
+            Action::SetNightLightBrightness(brightness) => {
+                cx.shared.night_light.lock(|settings| {
+                    settings.brightness = brightness;
+                });
+                save_later(cx.local.save)
+            }
+            Action::UpdateDisplay(now, section, blink) => update_display::spawn(now, section, blink).ok(),
+            Action::EnableRotary => rotary_encoder_enable_interrupts::spawn().ok(),
+            Action::DisableRotary => rotary_disable_interrupts::spawn().ok(),
+            Action::EnableDisplay => enable_display::spawn().ok(),
+            Action::DisableDisplay => disable_display::spawn().ok(),
+        };
+    }
+
+    // Schedules a timer event on the monotonic, replacing the pending one
+    fn schedule(handle: &mut Option<state_machine::SpawnHandle>, ticks: u32, event: Event) -> Option<()> {
+        cancel(handle);
+        *handle = state_machine::spawn_after(monotonic::duration(ticks), event).ok();
+        handle.as_ref().map(|_| ())
+    }
+
+    fn cancel(handle: &mut Option<state_machine::SpawnHandle>) -> Option<()> {
+        handle.take().and_then(|handle| handle.cancel().ok()).map(|_| ())
     }
 
-    fn disable_alarm_components(cx: &state_machine::Context) {
-        cx.shared.amp_on.store(false, Ordering::Relaxed);
-        turn_off_amp_fan_hum::spawn().ok();
-        stop_pwm::spawn().ok();
-        disable_blinking::spawn().ok();
+    // Saves once the knob is left alone, every save rewrites the flash
+    fn save_later(handle: &mut Option<save_settings::SpawnHandle>) -> Option<()> {
+        let delay = monotonic::duration(time::SAVE_DELAY_TICKS);
+        // Rescheduling fails once the save has run
+        let rescheduled = handle.take().and_then(|handle| handle.reschedule_after(delay).ok());
+        *handle = rescheduled.or_else(|| save_settings::spawn_after(delay).ok());
+        handle.as_ref().map(|_| ())
     }
 }
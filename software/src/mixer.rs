@@ -0,0 +1,346 @@
+// Mixes several voices to the 16 bit stereo the I2S sends, so a beep can play over the
+// ambient sound. Every voice is resampled to the I2S rate and has its own gain, which it
+// fades to linearly: alarms start gently and stopped sounds fade out instead of clicking.
+
+use crate::{volume::UNITY_GAIN, wav::Format};
+
+pub const FORMAT: Format = Format { channels: 2, sample_rate: 32_000, bits_per_sample: 16 };
+pub const DECLICK_MS: u32 = 20; // Shortest fade, a sound starting at full level clicks
+
+const VOICES: usize = 2;
+const ONE: u32 = 1 << 16; // Resampling positions are fixed point with ONE as 1.0
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Voice {
+    Ambient, // Alarm sound or preview
+    Effect,  // Tunes and beeps over it
+}
+
+// A sound the mixer can play
+pub trait Input {
+    fn sample_rate(&self) -> u32;
+    // Next left and right sample, None at the end of a sound that is not looped
+    fn frame(&mut self, looped: bool) -> Option<[i16; 2]>;
+}
+
+struct Channel<I> {
+    input: Option<I>,
+    looped: bool,
+    step: u32,     // Input frames per output frame
+    position: u32, // Between `previous` and `next`
+    previous: [i16; 2],
+    next: [i16; 2],
+    // Linear fade of the gain, UNITY_GAIN is 1.0
+    from: u32,
+    to: u32,
+    elapsed: u32,
+    frames: u32,
+    stopping: bool, // The input is dropped once faded out
+    drained: bool,  // The input ended, `next` is its last frame
+}
+
+impl<I: Input> Channel<I> {
+    fn new() -> Channel<I> {
+        Channel {
+            input: None,
+            looped: false,
+            step: ONE,
+            position: 0,
+            previous: [0; 2],
+            next: [0; 2],
+            from: 0,
+            to: 0,
+            elapsed: 0,
+            frames: 0,
+            stopping: false,
+            drained: false,
+        }
+    }
+
+    fn gain(&self) -> u32 {
+        match self.frames {
+            0 => self.to,
+            frames => {
+                let delta = (self.to as i64 - self.from as i64) * self.elapsed as i64 / frames as i64;
+                (self.from as i64 + delta) as u32
+            }
+        }
+    }
+
+    fn fade(&mut self, to: u32, frames: u32) {
+        self.from = self.gain();
+        self.to = to;
+        self.elapsed = 0;
+        self.frames = frames;
+    }
+
+    // Next output frame scaled by the fade, None once the voice is over
+    fn frame(&mut self) -> Option<[i32; 2]> {
+        let input = self.input.as_mut()?;
+        while self.position >= ONE {
+            if self.drained {
+                self.input = None;
+                return None;
+            }
+            self.position -= ONE;
+            self.previous = self.next;
+            match input.frame(self.looped) {
+                Some(frame) => self.next = frame,
+                None => self.drained = true,
+            }
+        }
+        let gain = self.gain() as i64;
+        if self.elapsed < self.frames {
+            self.elapsed += 1;
+        } else if self.stopping {
+            self.input = None;
+        }
+        let position = self.position as i64;
+        self.position += self.step;
+        Some([0, 1].map(|i| {
+            let (previous, next) = (self.previous[i] as i64, self.next[i] as i64);
+            let sample = previous + (((next - previous) * position) >> 16);
+            ((sample * gain) >> 16) as i32
+        }))
+    }
+}
+
+pub struct Mixer<I> {
+    sample_rate: u32, // Of the I2S, resampled to
+    channels: [Channel<I>; VOICES],
+}
+
+impl<I: Input> Mixer<I> {
+    pub fn new(sample_rate: u32) -> Mixer<I> {
+        Mixer { sample_rate, channels: [Channel::new(), Channel::new()] }
+    }
+
+    // Replaces the sound of the voice, it fades in from silence to `gain`
+    pub fn play(&mut self, voice: Voice, mut input: I, looped: bool, gain: u32, fade_ms: u32) {
+        let first = input.frame(looped);
+        let second = input.frame(looped);
+        let frames = self.frames(fade_ms);
+        let channel = &mut self.channels[voice as usize];
+        channel.step = ((input.sample_rate() as u64 * ONE as u64) / self.sample_rate as u64) as u32;
+        channel.position = 0;
+        channel.previous = first.unwrap_or_default();
+        channel.next = second.unwrap_or(channel.previous);
+        channel.drained = second.is_none();
+        channel.input = first.map(|_| input);
+        channel.looped = looped;
+        channel.stopping = false;
+        channel.to = 0;
+        channel.frames = 0;
+        channel.fade(gain.min(UNITY_GAIN), frames);
+    }
+
+    // Fades the voice to a new gain, e.g. of the next alarm stage
+    pub fn fade_to(&mut self, voice: Voice, gain: u32, fade_ms: u32) {
+        let frames = self.frames(fade_ms);
+        let channel = &mut self.channels[voice as usize];
+        channel.stopping = false;
+        channel.fade(gain.min(UNITY_GAIN), frames);
+    }
+
+    // Fades the voice out and stops it
+    pub fn fade_out(&mut self, voice: Voice, fade_ms: u32) {
+        let frames = self.frames(fade_ms);
+        let channel = &mut self.channels[voice as usize];
+        channel.stopping = true;
+        channel.fade(0, frames);
+    }
+
+    pub fn is_playing(&self, voice: Voice) -> bool {
+        self.channels[voice as usize].input.is_some()
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.channels.iter().any(|channel| channel.input.is_some())
+    }
+
+    // Fills `words` with the sum of the voices scaled by `gain`, see volume::gain, the left
+    // sample in the lowest bits. Silence once every voice is over.
+    pub fn fill(&mut self, gain: u32, words: &mut [u32]) {
+        let gain = gain.min(UNITY_GAIN) as i64;
+        for word in words.iter_mut() {
+            let mut mix = [0i64; 2];
+            for channel in self.channels.iter_mut() {
+                if let Some(frame) = channel.frame() {
+                    mix[0] += frame[0] as i64;
+                    mix[1] += frame[1] as i64;
+                }
+            }
+            let [left, right] = mix.map(|sample| ((sample * gain) >> 16).clamp(i16::MIN as i64, i16::MAX as i64) as u16 as u32);
+            *word = left | (right << 16);
+        }
+    }
+
+    fn frames(&self, ms: u32) -> u32 {
+        (self.sample_rate as u64 * ms as u64 / 1000) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pcm;
+
+    // A constant level for `len` frames
+    struct Tone {
+        sample_rate: u32,
+        level: i16,
+        len: usize,
+        played: usize,
+    }
+
+    impl Input for Tone {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+            if self.played == self.len {
+                if !looped {
+                    return None;
+                }
+                self.played = 0;
+            }
+            self.played += 1;
+            Some([self.level, -self.level])
+        }
+    }
+
+    // Counts its frames instead, to check the resampling
+    struct Ramp {
+        sample_rate: u32,
+        next: i16,
+    }
+
+    impl Input for Ramp {
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+
+        fn frame(&mut self, _looped: bool) -> Option<[i16; 2]> {
+            self.next += 1;
+            Some([self.next - 1; 2])
+        }
+    }
+
+    enum Test {
+        Tone(Tone),
+        Ramp(Ramp),
+    }
+
+    impl Input for Test {
+        fn sample_rate(&self) -> u32 {
+            match self {
+                Test::Tone(tone) => tone.sample_rate(),
+                Test::Ramp(ramp) => ramp.sample_rate(),
+            }
+        }
+
+        fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+            match self {
+                Test::Tone(tone) => tone.frame(looped),
+                Test::Ramp(ramp) => ramp.frame(looped),
+            }
+        }
+    }
+
+    const RATE: u32 = 1000; // One frame per millisecond
+
+    fn tone(level: i16, len: usize) -> Test {
+        Test::Tone(Tone { sample_rate: RATE, level, len, played: 0 })
+    }
+
+    fn left(words: &[u32]) -> Vec<i16> {
+        words.iter().map(|word| *word as u16 as i16).collect()
+    }
+
+    fn fill(mixer: &mut Mixer<Test>, frames: usize) -> Vec<u32> {
+        let mut words = vec![0; frames];
+        mixer.fill(UNITY_GAIN, &mut words);
+        words
+    }
+
+    #[test]
+    fn mixer_format_has_a_clock() {
+        assert!(pcm::clock(&FORMAT).is_ok());
+    }
+
+    #[test]
+    fn voices_are_summed_in_stereo() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(1000, 100), true, UNITY_GAIN, 0);
+        mixer.play(Voice::Effect, tone(300, 100), false, UNITY_GAIN / 2, 0);
+        let words = fill(&mut mixer, 2);
+        assert_eq!(words[0], (1150u16 as u32) | ((-1150i16 as u16 as u32) << 16));
+    }
+
+    #[test]
+    fn fade_in_is_linear() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(800, 100), true, UNITY_GAIN, 8);
+        let samples = left(&fill(&mut mixer, 10));
+        assert_eq!(samples, [0, 100, 200, 300, 400, 500, 600, 700, 800, 800]);
+    }
+
+    #[test]
+    fn fade_out_stops_the_voice() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(1000, 100), true, UNITY_GAIN, 0);
+        fill(&mut mixer, 5);
+        mixer.fade_out(Voice::Ambient, 4);
+        assert_eq!(left(&fill(&mut mixer, 4)), [1000, 750, 500, 250]);
+        assert!(mixer.is_active());
+        assert_eq!(left(&fill(&mut mixer, 2)), [0, 0]);
+        assert!(!mixer.is_active());
+    }
+
+    #[test]
+    fn fade_to_a_new_gain_starts_where_it_is() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(800, 100), true, UNITY_GAIN, 8);
+        fill(&mut mixer, 4);
+        mixer.fade_to(Voice::Ambient, UNITY_GAIN / 8, 3);
+        assert_eq!(left(&fill(&mut mixer, 5)), [400, 300, 200, 100, 100]);
+    }
+
+    #[test]
+    fn effect_ends_while_the_ambient_sound_goes_on() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(1000, 3), true, UNITY_GAIN, 0);
+        mixer.play(Voice::Effect, tone(100, 3), false, UNITY_GAIN, 0);
+        assert_eq!(left(&fill(&mut mixer, 5)), [1100, 1100, 1100, 1000, 1000]);
+        assert!(!mixer.is_playing(Voice::Effect));
+        assert!(mixer.is_playing(Voice::Ambient));
+    }
+
+    #[test]
+    fn voices_are_resampled_to_the_output_rate() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, Test::Ramp(Ramp { sample_rate: RATE / 2, next: 0 }), true, UNITY_GAIN, 0);
+        mixer.play(Voice::Effect, Test::Ramp(Ramp { sample_rate: RATE * 2, next: 0 }), true, UNITY_GAIN, 0);
+        // Half speed interpolates between the frames, double speed skips every other one
+        let words = fill(&mut mixer, 5);
+        assert_eq!(left(&words), [0, 2, 5, 7, 10]); // 0 + 0, 0.5 + 2, 1 + 4, 1.5 + 6, 2 + 8 rounded down
+    }
+
+    #[test]
+    fn mix_is_clipped() {
+        let mut mixer = Mixer::new(RATE);
+        mixer.play(Voice::Ambient, tone(30_000, 100), true, UNITY_GAIN, 0);
+        mixer.play(Voice::Effect, tone(30_000, 100), true, UNITY_GAIN, 0);
+        assert_eq!(left(&fill(&mut mixer, 1)), [i16::MAX]);
+    }
+
+    #[test]
+    fn idle_mixer_is_silent() {
+        let mut mixer = Mixer::<Test>::new(RATE);
+        let mut words = [1u32; 4];
+        mixer.fill(UNITY_GAIN, &mut words);
+        assert_eq!(words, [0; 4]);
+        assert!(!mixer.is_active());
+    }
+}
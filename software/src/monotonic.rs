@@ -0,0 +1,70 @@
+// RTIC monotonic on RTC2, running from the LFCLK so scheduled tasks keep running while the
+// CPU sleeps in wfi. The 24 bit counter is extended to 64 bits the same way as RTC1.
+
+use {
+    crate::{counter, time::TICKS_PER_SECOND},
+    fugit::{TimerDurationU64, TimerInstantU64},
+    nrf52833_hal::pac::RTC2,
+    rtic_monotonic::Monotonic,
+};
+
+pub const MONO_HZ: u32 = 32_768; // No prescaler, full LFCLK resolution
+
+pub struct MonoRtc {
+    rtc: RTC2,
+    overflows: u32,
+}
+
+impl MonoRtc {
+    pub(crate) fn new(rtc: RTC2) -> MonoRtc {
+        rtc.prescaler.write(|w| unsafe { w.prescaler().bits(0) });
+        MonoRtc { rtc, overflows: 0 }
+    }
+}
+
+// Duration of a number of clock ticks, as used by the state machine
+pub(crate) fn duration(ticks: u32) -> TimerDurationU64<MONO_HZ> {
+    TimerDurationU64::from_ticks(ticks as u64 * (MONO_HZ / TICKS_PER_SECOND) as u64)
+}
+
+impl Monotonic for MonoRtc {
+    type Instant = TimerInstantU64<MONO_HZ>;
+    type Duration = TimerDurationU64<MONO_HZ>;
+
+    // The interrupt also counts the overflows, so it has to stay enabled
+    const DISABLE_INTERRUPT_ON_EMPTY_QUEUE: bool = false;
+
+    fn now(&mut self) -> Self::Instant {
+        // The counter has to be read before the overflow event
+        let counter = self.rtc.counter.read().bits();
+        let overflow_pending = self.rtc.events_ovrflw.read().bits() != 0;
+        Self::Instant::from_ticks(counter::extend(self.overflows, counter, overflow_pending))
+    }
+
+    fn set_compare(&mut self, instant: Self::Instant) {
+        // Deadlines beyond half the counter range fire early, RTIC then sets the compare again
+        let compare = counter::compare_value(self.now().ticks(), instant.ticks());
+        self.rtc.cc[0].write(|w| unsafe { w.compare().bits(compare) });
+    }
+
+    fn clear_compare_flag(&mut self) {
+        self.rtc.events_compare[0].reset();
+    }
+
+    fn zero() -> Self::Instant {
+        Self::Instant::from_ticks(0)
+    }
+
+    unsafe fn reset(&mut self) {
+        self.rtc.intenset.write(|w| w.compare0().set().ovrflw().set());
+        self.rtc.tasks_clear.write(|w| w.bits(1));
+        self.rtc.tasks_start.write(|w| w.bits(1));
+    }
+
+    fn on_interrupt(&mut self) {
+        if self.rtc.events_ovrflw.read().bits() != 0 {
+            self.rtc.events_ovrflw.reset();
+            self.overflows += 1;
+        }
+    }
+}
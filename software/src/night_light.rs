@@ -0,0 +1,80 @@
+// Night light on the LED outside of the alarms. Holding the knob down or the CLI switch it,
+// the knob dims it while it is on, and it goes off after the auto-off time. The schedule
+// switches it on and off at set times of the day, without the auto-off.
+// Brightness is in percent of the perceived brightness, gamma corrected for the LED.
+
+use {
+    crate::{envelope::GAMMA, time::Window},
+    libm::powf,
+};
+
+pub const MAX_BRIGHTNESS: u8 = 100;
+pub const BRIGHTNESS_STEP: u8 = 5; // Per step of the knob
+pub const MAX_AUTO_OFF_MINUTES: u32 = 240;
+
+pub const DEFAULT_SETTINGS: Settings = Settings {
+    brightness: 20,
+    auto_off_minutes: 30,
+    schedule: Schedule::DISABLED,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    pub brightness: u8,        // Switched on by hand
+    pub auto_off_minutes: u32, // 0 keeps it on
+    pub schedule: Schedule,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Schedule {
+    pub window: Window, // A disabled window disables the schedule
+    pub brightness: u8,
+}
+
+impl Schedule {
+    pub const DISABLED: Schedule = Schedule { window: Window::DISABLED, brightness: 0 };
+}
+
+// Brightness after turning the knob by `steps`, it does not go off
+pub fn step(brightness: u8, steps: isize) -> u8 {
+    let brightness = brightness as isize + steps * BRIGHTNESS_STEP as isize;
+    brightness.clamp(1, MAX_BRIGHTNESS as isize) as u8
+}
+
+// Duty cycle of the LED for a brightness, out of `max_duty`
+pub fn duty(brightness: u8, max_duty: u16) -> u16 {
+    let brightness = brightness.min(MAX_BRIGHTNESS) as f32 / MAX_BRIGHTNESS as f32;
+    (max_duty as f32 * powf(brightness, GAMMA) + 0.5) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::time_to_ticks;
+
+    #[test]
+    fn knob_steps_stop_at_both_ends() {
+        assert_eq!(step(20, 2), 30);
+        assert_eq!(step(20, -3), 5);
+        assert_eq!(step(5, -1), 1);
+        assert_eq!(step(98, 1), MAX_BRIGHTNESS);
+    }
+
+    #[test]
+    fn duty_is_gamma_corrected() {
+        assert_eq!(duty(0, 10_000), 0);
+        assert_eq!(duty(100, 10_000), 10_000);
+        assert_eq!(duty(50, 10_000), 2_176); // 0.5^2.2
+        assert_eq!(duty(5, 10_000), 14);
+        assert_eq!(duty(120, 10_000), 10_000);
+    }
+
+    #[test]
+    fn schedule_spans_midnight() {
+        let window = Window { start: time_to_ticks(22, 0), end: time_to_ticks(6, 0) };
+        assert!(window.contains(time_to_ticks(23, 30)) && window.contains(time_to_ticks(5, 59)));
+        assert!(!window.contains(time_to_ticks(6, 0)) && !window.contains(time_to_ticks(12, 0)));
+        let disabled = Schedule::DISABLED.window;
+        assert!(!disabled.enabled() && !disabled.contains(0));
+    }
+}
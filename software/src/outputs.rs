@@ -0,0 +1,128 @@
+// Loads switched by the alarm stages and the CLI, each a logical channel with its own duty
+// cycle. The board maps every load to a driver, a GPIO or a PWM channel. Loads mapped to the
+// same driver are ganged, it then follows the strongest of them. A GPIO is on at any duty.
+// A load may have a floor, e.g. the fan speed the thermostat asks for, that switching it
+// off from an alarm stage or the CLI does not go below.
+
+pub const LOADS: usize = 3;
+pub const MAX_DUTY: u8 = 100; // Percent
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Load {
+    Amp,
+    Fan,
+    Humidifier,
+}
+
+pub const LOAD_NAMES: [(Load, &[u8]); LOADS] = [(Load::Amp, b"amp"), (Load::Fan, b"fan"), (Load::Humidifier, b"humidifier")];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Driver {
+    Gpio(usize), // Index of the pin on the board
+    Pwm(usize),  // Channel
+}
+
+// Driver of every load, in the order of Load
+pub type Board = [Driver; LOADS];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Outputs {
+    board: Board,
+    duty: [u8; LOADS],  // Percent, 0 is off
+    floor: [u8; LOADS], // Percent, the duty does not go below it
+}
+
+impl Outputs {
+    pub const fn new(board: Board) -> Outputs {
+        Outputs { board, duty: [0; LOADS], floor: [0; LOADS] }
+    }
+
+    // Returns the driver to update, with its new duty cycle
+    pub fn set(&mut self, load: Load, percent: u8) -> (Driver, u8) {
+        self.duty[load as usize] = percent.min(MAX_DUTY);
+        let driver = self.board[load as usize];
+        (driver, self.driver_duty(driver))
+    }
+
+    // Returns the driver to update like set
+    pub fn set_floor(&mut self, load: Load, percent: u8) -> (Driver, u8) {
+        self.floor[load as usize] = percent.min(MAX_DUTY);
+        let driver = self.board[load as usize];
+        (driver, self.driver_duty(driver))
+    }
+
+    pub fn duty(&self, load: Load) -> u8 {
+        self.duty[load as usize].max(self.floor[load as usize])
+    }
+
+    // Strongest of the loads on the driver
+    pub fn driver_duty(&self, driver: Driver) -> u8 {
+        self.board
+            .iter()
+            .zip(LOAD_NAMES)
+            .filter(|(board_driver, _)| **board_driver == driver)
+            .map(|(_, (load, _))| self.duty(load))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// Load by its name, for the CLI
+pub fn find(name: &[u8]) -> Option<Load> {
+    LOAD_NAMES.iter().find(|(_, load_name)| *load_name == name).map(|(load, _)| *load)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEPARATE: Board = [Driver::Gpio(0), Driver::Pwm(2), Driver::Pwm(3)];
+    const GANGED: Board = [Driver::Gpio(0); LOADS];
+
+    #[test]
+    fn loads_are_independent() {
+        let mut outputs = Outputs::new(SEPARATE);
+        assert_eq!(outputs.set(Load::Fan, 40), (Driver::Pwm(2), 40));
+        assert_eq!(outputs.set(Load::Humidifier, 100), (Driver::Pwm(3), 100));
+        assert_eq!(outputs.duty(Load::Amp), 0);
+        assert_eq!(outputs.set(Load::Fan, 0), (Driver::Pwm(2), 0));
+        assert_eq!(outputs.duty(Load::Humidifier), 100);
+    }
+
+    #[test]
+    fn ganged_loads_follow_the_strongest() {
+        let mut outputs = Outputs::new(GANGED);
+        assert_eq!(outputs.set(Load::Fan, 30), (Driver::Gpio(0), 30));
+        assert_eq!(outputs.set(Load::Amp, 100), (Driver::Gpio(0), 100));
+        assert_eq!(outputs.set(Load::Amp, 0), (Driver::Gpio(0), 30));
+        assert_eq!(outputs.set(Load::Fan, 0), (Driver::Gpio(0), 0));
+    }
+
+    #[test]
+    fn duty_is_clamped() {
+        let mut outputs = Outputs::new(SEPARATE);
+        assert_eq!(outputs.set(Load::Fan, 250), (Driver::Pwm(2), MAX_DUTY));
+    }
+
+    #[test]
+    fn floor_holds_the_load_on() {
+        let mut outputs = Outputs::new(SEPARATE);
+        assert_eq!(outputs.set_floor(Load::Fan, 30), (Driver::Pwm(2), 30));
+        assert_eq!(outputs.set(Load::Fan, 80), (Driver::Pwm(2), 80));
+        assert_eq!(outputs.set(Load::Fan, 0), (Driver::Pwm(2), 30));
+        assert_eq!(outputs.set_floor(Load::Fan, 0), (Driver::Pwm(2), 0));
+    }
+
+    #[test]
+    fn unused_driver_is_off() {
+        assert_eq!(Outputs::new(SEPARATE).driver_duty(Driver::Gpio(5)), 0);
+    }
+
+    #[test]
+    fn loads_are_found_by_name() {
+        for (load, name) in LOAD_NAMES {
+            assert_eq!(find(name), Some(load));
+        }
+        assert_eq!(find(b"heater"), None);
+    }
+}
@@ -0,0 +1,244 @@
+// Reads WAV samples as 16 bit frames for the mixer, and finds the I2S clock setting for
+// a sample rate. The sample rate is MCK / RATIO, with MCK derived from 32 MHz.
+
+use crate::{
+    adpcm, mixer,
+    wav::{Encoding, Error, Format, Wav, MAX_ADPCM_BLOCK_ALIGN},
+};
+
+pub const MCK_SOURCE_HZ: u32 = 32_000_000;
+pub const MCK_DIVIDERS: [u32; 13] = [8, 10, 11, 15, 16, 21, 23, 30, 31, 32, 42, 63, 125];
+pub const RATIOS: [u32; 9] = [32, 48, 64, 96, 128, 192, 256, 384, 512];
+
+// Maximum deviation from the sample rate of the file, 1% is about a sixth of a semitone
+const MAX_RATE_ERROR_PERMILLE: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Clock {
+    pub mck_divider: u32,
+    pub ratio: u32,
+}
+
+impl Clock {
+    pub fn sample_rate(&self) -> u32 {
+        MCK_SOURCE_HZ / self.mck_divider / self.ratio
+    }
+}
+
+// Closest clock setting to the sample rate, within MAX_RATE_ERROR_PERMILLE
+pub fn clock(format: &Format) -> Result<Clock, Error> {
+    MCK_DIVIDERS
+        .iter()
+        .flat_map(|mck_divider| RATIOS.iter().map(move |ratio| Clock { mck_divider: *mck_divider, ratio: *ratio }))
+        // A 24 bit frame needs a multiple of 48 bit clocks, 16 bits at least 32
+        .filter(|clock| match format.bits_per_sample {
+            24 => clock.ratio % 48 == 0,
+            16 => clock.ratio >= 32,
+            _ => true,
+        })
+        .min_by_key(|clock| clock.sample_rate().abs_diff(format.sample_rate))
+        .filter(|clock| clock.sample_rate().abs_diff(format.sample_rate) * 1000 <= format.sample_rate * MAX_RATE_ERROR_PERMILLE)
+        .ok_or(Error::UnsupportedSampleRate(format.sample_rate))
+}
+
+// Bytes of one frame, a sample of every channel
+pub fn frame_size(format: &Format) -> usize {
+    (format.channels * format.bits_per_sample / 8) as usize
+}
+
+// Converts one frame to 16 bit left and right samples, mono is played on both
+pub fn frame(format: &Format, bytes: &[u8]) -> [i16; 2] {
+    let width = (format.bits_per_sample / 8) as usize;
+    let sample = |bytes: &[u8]| match width {
+        // WAV stores 8 bit samples unsigned
+        1 => ((bytes[0] ^ 0x80) as i8 as i16) << 8,
+        2 => i16::from_le_bytes([bytes[0], bytes[1]]),
+        _ => i16::from_le_bytes([bytes[1], bytes[2]]),
+    };
+    match format.channels {
+        1 => [sample(bytes); 2],
+        _ => [sample(bytes), sample(&bytes[width..])],
+    }
+}
+
+// Largest decoded ADPCM block, mono has the most samples per block
+const DECODED_SIZE: usize = adpcm::samples_per_block(MAX_ADPCM_BLOCK_ALIGN as usize, 1) * 2;
+
+// Reads a clip frame by frame, an ADPCM clip is decoded one block at a time
+pub struct Reader<'a> {
+    wav: Wav<'a>,
+    offset: usize,               // Next byte of the WAV data
+    decoded: [u8; DECODED_SIZE], // PCM of the current ADPCM block
+    start: usize,                // Next byte of `decoded`
+    end: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(wav: Wav<'a>) -> Reader<'a> {
+        Reader { wav, offset: 0, decoded: [0; DECODED_SIZE], start: 0, end: 0 }
+    }
+
+    pub fn format(&self) -> Format {
+        self.wav.format
+    }
+
+    pub fn rewind(&mut self) {
+        self.offset = 0;
+        self.start = 0;
+        self.end = 0;
+    }
+
+    // Next frame, a looped clip starts over at the end. None at the end of a clip played
+    // once, or of an empty one.
+    pub fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+        let format = self.wav.format;
+        let size = frame_size(&format);
+        let mut rewound = false;
+        loop {
+            if let Some(bytes) = self.pcm().get(..size) {
+                let frame = frame(&format, bytes);
+                self.consume(size);
+                return Some(frame);
+            }
+            if !looped || rewound {
+                return None;
+            }
+            self.rewind();
+            rewound = true;
+        }
+    }
+
+    // PCM bytes up to the end of the data or of the decoded block, ADPCM blocks hold
+    // whole frames
+    fn pcm(&mut self) -> &[u8] {
+        match self.wav.encoding {
+            Encoding::Pcm => &self.wav.data[self.offset..],
+            Encoding::ImaAdpcm { block_align } => {
+                if self.start == self.end && self.offset < self.wav.data.len() {
+                    let block_end = (self.offset + block_align as usize).min(self.wav.data.len());
+                    let block = &self.wav.data[self.offset..block_end];
+                    self.start = 0;
+                    self.end = adpcm::decode_block(block, self.wav.format.channels as usize, &mut self.decoded);
+                    self.offset = block_end;
+                }
+                &self.decoded[self.start..self.end]
+            }
+        }
+    }
+
+    fn consume(&mut self, used: usize) {
+        match self.wav.encoding {
+            Encoding::Pcm => self.offset += used,
+            Encoding::ImaAdpcm { .. } => self.start += used,
+        }
+    }
+}
+
+impl mixer::Input for Reader<'_> {
+    fn sample_rate(&self) -> u32 {
+        self.wav.format.sample_rate
+    }
+
+    fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+        Reader::frame(self, looped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Format {
+        Format { channels, sample_rate, bits_per_sample }
+    }
+
+    #[test]
+    fn common_sample_rates_have_a_clock() {
+        for rate in [8_000, 11_025, 16_000, 22_050, 32_000, 44_100, 48_000] {
+            let clock = clock(&format(1, rate, 16)).unwrap();
+            assert!(clock.sample_rate().abs_diff(rate) * 100 <= rate, "{} Hz: {:?}", rate, clock);
+        }
+        assert_eq!(clock(&format(2, 8_000, 16)), Ok(Clock { mck_divider: 125, ratio: 32 }));
+    }
+
+    #[test]
+    fn embedded_clip_has_a_clock() {
+        let wav = crate::wav::parse(include_bytes!("../assets/SeaBreeze3.wav")).unwrap();
+        assert_eq!(clock(&wav.format), Ok(Clock { mck_divider: 15, ratio: 48 }));
+    }
+
+    #[test]
+    fn clock_respects_sample_width() {
+        let clock = clock(&format(2, 44_100, 24)).unwrap();
+        assert_eq!(clock.ratio % 48, 0);
+    }
+
+    #[test]
+    fn unreachable_sample_rate_has_no_clock() {
+        assert_eq!(clock(&format(2, 96_000, 16)), Err(Error::UnsupportedSampleRate(96_000)));
+        assert_eq!(clock(&format(2, 400, 16)), Err(Error::UnsupportedSampleRate(400))); // Below 32 MHz / 125 / 512
+    }
+
+    #[test]
+    fn frames_are_16_bit_stereo() {
+        assert_eq!(frame(&format(1, 44_100, 16), &[0x00, 0x10]), [4096, 4096]);
+        assert_eq!(frame(&format(2, 44_100, 16), &[0x00, 0x10, 0x00, 0xF0]), [4096, -4096]);
+    }
+
+    #[test]
+    fn frames_of_8_bit_samples_are_signed() {
+        assert_eq!(frame(&format(2, 8_000, 8), &[0x80, 0xFF]), [0, 0x7F00]);
+        assert_eq!(frame(&format(1, 8_000, 8), &[0x00]), [i16::MIN; 2]);
+    }
+
+    #[test]
+    fn frames_of_24_bit_samples_keep_the_high_bits() {
+        assert_eq!(frame(&format(1, 48_000, 24), &[0xFF, 0x00, 0x80]), [i16::MIN; 2]);
+        assert_eq!(frame(&format(2, 48_000, 24), &[0x00, 0x34, 0x12, 0xFF, 0xFF, 0xFF]), [0x1234, -1]);
+    }
+
+    fn pcm_clip(pcm: &[u8]) -> Wav<'_> {
+        Wav { format: format(1, 44_100, 16), encoding: Encoding::Pcm, data: pcm }
+    }
+
+    fn frames(reader: &mut Reader, looped: bool, count: usize) -> Vec<i16> {
+        (0..count).map_while(|_| reader.frame(looped)).map(|frame| frame[0]).collect()
+    }
+
+    #[test]
+    fn looped_clip_starts_over() {
+        let mut reader = Reader::new(pcm_clip(&[1, 0, 2, 0, 3, 0]));
+        assert_eq!(frames(&mut reader, true, 2), [1, 2]);
+        assert_eq!(frames(&mut reader, true, 4), [3, 1, 2, 3]);
+    }
+
+    #[test]
+    fn clip_played_once_ends() {
+        let mut reader = Reader::new(pcm_clip(&[1, 0, 2, 0]));
+        assert_eq!(frames(&mut reader, false, 4), [1, 2]);
+        assert_eq!(reader.frame(false), None);
+    }
+
+    #[test]
+    fn empty_clip_is_not_looped() {
+        let mut reader = Reader::new(pcm_clip(&[]));
+        assert_eq!(reader.frame(true), None);
+    }
+
+    #[test]
+    fn adpcm_blocks_are_read_in_order() {
+        let wav = crate::wav::parse(include_bytes!("../assets/SeaBreeze3.adpcm.wav")).unwrap();
+        let Encoding::ImaAdpcm { block_align } = wav.encoding else {
+            panic!("{:?}", wav.encoding);
+        };
+        let mut expected = Vec::new();
+        let mut pcm = [0u8; DECODED_SIZE];
+        for block in wav.data.chunks(block_align as usize) {
+            let len = adpcm::decode_block(block, 1, &mut pcm);
+            expected.extend(pcm[..len].chunks_exact(2).map(|bytes| i16::from_le_bytes([bytes[0], bytes[1]])));
+        }
+
+        let mut reader = Reader::new(wav);
+        assert!(frames(&mut reader, false, usize::MAX) == expected);
+    }
+}
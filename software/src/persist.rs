@@ -0,0 +1,334 @@
+// Settings kept in the flash store, restored into the state machine config at boot.
+// Values that fail to decode or are out of range fall back to their defaults.
+
+use {
+    crate::{
+        alarm::{self, Alarm, Stage, ALARM_SLOTS, ALARM_STAGES},
+        format::{self, Format},
+        haptic::{self, PATTERNS},
+        night_light::{self, Schedule},
+        sound::{self, SOUNDS},
+        state_machine::Config,
+        store::{self, Store},
+        thermostat::{self, QuietHours},
+        time::{self, Window, TICKS_PER_DAY},
+        volume,
+    },
+    embedded_storage::nor_flash::NorFlash,
+};
+
+// Increment when the encoding of a key changes, the store then starts out empty
+pub const SCHEMA_VERSION: u16 = 1;
+
+const KEY_ALARMS: u8 = 0x00; // One key per slot
+const KEY_STAGES: u8 = 0x10; // One key per stage
+const KEY_SNOOZE_MINUTES: u8 = 0x20;
+const KEY_SNOOZE_LIMIT: u8 = 0x21;
+const KEY_SUNRISE_MINUTES: u8 = 0x22;
+const KEY_SETTINGS_TIMEOUT_MINUTES: u8 = 0x30;
+const KEY_ALARM_TIMEOUT_MINUTES: u8 = 0x31;
+const KEY_VOLUME: u8 = 0x40;
+const KEY_ALARM_SOUNDS: u8 = 0x50; // One key per slot, kept apart so the alarm encoding stays the same
+const KEY_ALARM_HAPTICS: u8 = 0x60; // One key per slot, like the sounds
+const KEY_THERMOSTAT: u8 = 0x70;
+const KEY_QUIET_HOURS: u8 = 0x71;
+const KEY_NIGHT_LIGHT: u8 = 0x80;
+const KEY_NIGHT_LIGHT_SCHEDULE: u8 = 0x81;
+const KEY_FORMAT: u8 = 0x90;
+
+pub fn load<F: NorFlash>(store: &mut Store<F>) -> Config {
+    let mut config = Config::default();
+    for slot in 0..ALARM_SLOTS {
+        if let Some(alarm) = read::<_, 6>(store, KEY_ALARMS + slot as u8).and_then(decode_alarm) {
+            config.alarms[slot] = alarm;
+        }
+        let sound = read::<_, 1>(store, KEY_ALARM_SOUNDS + slot as u8);
+        if let Some([sound]) = sound.filter(|[sound]| (*sound as usize) < SOUNDS.len()) {
+            config.alarms[slot].sound = sound;
+        }
+        let haptic = read::<_, 1>(store, KEY_ALARM_HAPTICS + slot as u8);
+        if let Some([haptic]) = haptic.filter(|[haptic]| (*haptic as usize) < PATTERNS.len()) {
+            config.alarms[slot].haptic = haptic;
+        }
+    }
+    for index in 0..ALARM_STAGES {
+        if let Some(stage) = read::<_, 6>(store, KEY_STAGES + index as u8).and_then(decode_stage) {
+            config.stages[index] = stage;
+        }
+    }
+    let minutes = |store: &mut Store<F>, key, range: core::ops::RangeInclusive<u32>, default| {
+        read::<_, 4>(store, key)
+            .map(u32::from_le_bytes)
+            .filter(|minutes| range.contains(minutes))
+            .unwrap_or(default)
+    };
+    config.snooze_minutes = minutes(store, KEY_SNOOZE_MINUTES, 1..=alarm::MAX_SNOOZE_MINUTES, config.snooze_minutes);
+    config.snooze_limit = minutes(store, KEY_SNOOZE_LIMIT, 0..=u32::MAX, config.snooze_limit);
+    config.sunrise_minutes = minutes(store, KEY_SUNRISE_MINUTES, 0..=alarm::MAX_SUNRISE_MINUTES, config.sunrise_minutes);
+    config.settings_timeout_minutes = minutes(
+        store,
+        KEY_SETTINGS_TIMEOUT_MINUTES,
+        1..=time::MAX_TIMEOUT_MINUTES,
+        config.settings_timeout_minutes,
+    );
+    config.alarm_timeout_minutes = minutes(
+        store,
+        KEY_ALARM_TIMEOUT_MINUTES,
+        1..=time::MAX_TIMEOUT_MINUTES,
+        config.alarm_timeout_minutes,
+    );
+    if let Some([volume]) = read::<_, 1>(store, KEY_VOLUME).filter(|[volume]| *volume <= volume::MAX_VOLUME) {
+        config.volume = volume;
+    }
+    if let Some(settings) = read::<_, 8>(store, KEY_THERMOSTAT).and_then(decode_thermostat) {
+        config.thermostat = settings;
+    }
+    let quiet = read::<_, 9>(store, KEY_QUIET_HOURS).and_then(decode_window);
+    if let Some((window, speed)) = quiet.filter(|(_, speed)| *speed <= 100) {
+        config.thermostat.quiet = QuietHours { window, speed };
+    }
+    if let Some(settings) = read::<_, 5>(store, KEY_NIGHT_LIGHT).and_then(decode_night_light) {
+        config.night_light = settings;
+    }
+    let schedule = read::<_, 9>(store, KEY_NIGHT_LIGHT_SCHEDULE).and_then(decode_window);
+    if let Some((window, brightness)) = schedule.filter(|(_, brightness)| *brightness <= night_light::MAX_BRIGHTNESS) {
+        config.night_light.schedule = Schedule { window, brightness };
+    }
+    if let Some(format) = read::<_, 3>(store, KEY_FORMAT).and_then(decode_format) {
+        config.format = format;
+    }
+    config
+}
+
+// Writes all settings, the store skips the values that did not change
+pub fn save<F: NorFlash>(store: &mut Store<F>, config: &Config) -> Result<(), store::Error<F::Error>> {
+    for (slot, alarm) in config.alarms.iter().enumerate() {
+        store.write(KEY_ALARMS + slot as u8, &encode_alarm(alarm))?;
+        store.write(KEY_ALARM_SOUNDS + slot as u8, &[alarm.sound])?;
+        store.write(KEY_ALARM_HAPTICS + slot as u8, &[alarm.haptic])?;
+    }
+    for (index, stage) in config.stages.iter().enumerate() {
+        store.write(KEY_STAGES + index as u8, &encode_stage(stage))?;
+    }
+    store.write(KEY_SNOOZE_MINUTES, &config.snooze_minutes.to_le_bytes())?;
+    store.write(KEY_SNOOZE_LIMIT, &config.snooze_limit.to_le_bytes())?;
+    store.write(KEY_SUNRISE_MINUTES, &config.sunrise_minutes.to_le_bytes())?;
+    store.write(KEY_SETTINGS_TIMEOUT_MINUTES, &config.settings_timeout_minutes.to_le_bytes())?;
+    store.write(KEY_ALARM_TIMEOUT_MINUTES, &config.alarm_timeout_minutes.to_le_bytes())?;
+    store.write(KEY_VOLUME, &[config.volume])?;
+    store.write(KEY_THERMOSTAT, &encode_thermostat(&config.thermostat))?;
+    store.write(KEY_QUIET_HOURS, &encode_window(&config.thermostat.quiet.window, config.thermostat.quiet.speed))?;
+    store.write(KEY_NIGHT_LIGHT, &encode_night_light(&config.night_light))?;
+    let schedule = &config.night_light.schedule;
+    store.write(KEY_NIGHT_LIGHT_SCHEDULE, &encode_window(&schedule.window, schedule.brightness))?;
+    store.write(KEY_FORMAT, &encode_format(&config.format))?;
+    Ok(())
+}
+
+// Value of exactly N bytes
+fn read<F: NorFlash, const N: usize>(store: &mut Store<F>, key: u8) -> Option<[u8; N]> {
+    let mut buffer = [0u8; N];
+    match store.read(key, &mut buffer) {
+        Some(len) if len == N => Some(buffer),
+        _ => None,
+    }
+}
+
+// Ticks, enabled, weekdays
+fn encode_alarm(alarm: &Alarm) -> [u8; 6] {
+    let ticks = alarm.ticks.to_le_bytes();
+    [ticks[0], ticks[1], ticks[2], ticks[3], alarm.enabled as u8, alarm.weekdays]
+}
+
+fn decode_alarm(bytes: [u8; 6]) -> Option<Alarm> {
+    let ticks = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    match (ticks < TICKS_PER_DAY, bytes[4], bytes[5] & !alarm::EVERY_DAY) {
+        (true, enabled @ (0 | 1), 0) => Some(Alarm {
+            ticks,
+            enabled: enabled == 1,
+            weekdays: bytes[5],
+            sound: sound::DEFAULT_SOUND,
+            haptic: haptic::DEFAULT_PATTERN,
+        }),
+        _ => None,
+    }
+}
+
+// Actuators, intensity, minutes
+fn encode_stage(stage: &Stage) -> [u8; 6] {
+    let minutes = stage.minutes.to_le_bytes();
+    [stage.actuators, stage.intensity, minutes[0], minutes[1], minutes[2], minutes[3]]
+}
+
+fn decode_stage(bytes: [u8; 6]) -> Option<Stage> {
+    let minutes = u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]);
+    match (bytes[1], minutes) {
+        (0..=100, 0..=alarm::MAX_STAGE_MINUTES) => Some(Stage { actuators: bytes[0], intensity: bytes[1], minutes }),
+        _ => None,
+    }
+}
+
+// Enabled, threshold, span, hysteresis, minimum speed, run and off minutes
+fn encode_thermostat(settings: &thermostat::Settings) -> [u8; 8] {
+    let threshold = settings.threshold.to_le_bytes();
+    [
+        settings.enabled as u8,
+        threshold[0],
+        threshold[1],
+        settings.span,
+        settings.hysteresis,
+        settings.min_speed,
+        settings.min_run_minutes,
+        settings.min_off_minutes,
+    ]
+}
+
+fn decode_thermostat(bytes: [u8; 8]) -> Option<thermostat::Settings> {
+    let threshold = i16::from_le_bytes([bytes[1], bytes[2]]);
+    let valid = bytes[0] <= 1
+        && (0..=thermostat::MAX_TEMPERATURE).contains(&threshold)
+        && bytes[5] <= 100
+        && bytes[6] <= thermostat::MAX_MINUTES
+        && bytes[7] <= thermostat::MAX_MINUTES;
+    valid.then_some(thermostat::Settings {
+        enabled: bytes[0] == 1,
+        threshold,
+        span: bytes[3],
+        hysteresis: bytes[4],
+        min_speed: bytes[5],
+        min_run_minutes: bytes[6],
+        min_off_minutes: bytes[7],
+        quiet: QuietHours::DISABLED,
+    })
+}
+
+// Brightness, auto-off minutes
+fn encode_night_light(settings: &night_light::Settings) -> [u8; 5] {
+    let minutes = settings.auto_off_minutes.to_le_bytes();
+    [settings.brightness, minutes[0], minutes[1], minutes[2], minutes[3]]
+}
+
+fn decode_night_light(bytes: [u8; 5]) -> Option<night_light::Settings> {
+    let auto_off_minutes = u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]);
+    match (bytes[0], auto_off_minutes <= night_light::MAX_AUTO_OFF_MINUTES) {
+        (brightness @ 1..=night_light::MAX_BRIGHTNESS, true) => Some(night_light::Settings {
+            brightness,
+            auto_off_minutes,
+            schedule: Schedule::DISABLED,
+        }),
+        _ => None,
+    }
+}
+
+// Start, end and the level in the window, the fan speed or the brightness
+fn encode_window(window: &Window, level: u8) -> [u8; 9] {
+    let (start, end) = (window.start.to_le_bytes(), window.end.to_le_bytes());
+    [start[0], start[1], start[2], start[3], end[0], end[1], end[2], end[3], level]
+}
+
+// The caller checks the level
+fn decode_window(bytes: [u8; 9]) -> Option<(Window, u8)> {
+    let start = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let end = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    (start < TICKS_PER_DAY && end < TICKS_PER_DAY).then_some((Window { start, end }, bytes[8]))
+}
+
+// 12 hour clock, leading zero, Fahrenheit
+fn encode_format(format: &Format) -> [u8; 3] {
+    [
+        (format.clock == format::Clock::H12) as u8,
+        format.leading_zero as u8,
+        (format.unit == format::Unit::Fahrenheit) as u8,
+    ]
+}
+
+fn decode_format(bytes: [u8; 3]) -> Option<Format> {
+    match bytes {
+        [clock @ (0 | 1), leading_zero @ (0 | 1), unit @ (0 | 1)] => Some(Format {
+            clock: if clock == 1 { format::Clock::H12 } else { format::Clock::H24 },
+            leading_zero: leading_zero == 1,
+            unit: if unit == 1 { format::Unit::Fahrenheit } else { format::Unit::Celsius },
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{store::ram_flash::RamFlash, time::time_to_ticks};
+
+    #[test]
+    fn empty_store_gives_defaults() {
+        let mut store = Store::new(RamFlash::new(), SCHEMA_VERSION).unwrap();
+        let config = load(&mut store);
+        assert_eq!(config.alarms, Config::default().alarms);
+        assert_eq!(config.snooze_minutes, alarm::DEFAULT_SNOOZE_MINUTES);
+        assert_eq!(config.alarm_timeout_minutes, time::DEFAULT_ALARM_TIMEOUT_MINUTES);
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let mut config = Config::default();
+        config.alarms[3] = Alarm::new(time_to_ticks(6, 45), alarm::WEEKDAYS);
+        config.alarms[5].sound = 2;
+        config.alarms[6].haptic = 3;
+        config.stages[1].intensity = 80;
+        config.snooze_minutes = 5;
+        config.sunrise_minutes = 0;
+        config.settings_timeout_minutes = 2;
+        config.volume = 0;
+        config.thermostat.enabled = true;
+        config.thermostat.threshold = 245;
+        config.thermostat.quiet = QuietHours { window: Window { start: time_to_ticks(22, 30), end: time_to_ticks(7, 0) }, speed: 20 };
+        config.night_light.brightness = 35;
+        config.night_light.schedule = Schedule { window: Window { start: time_to_ticks(22, 0), end: time_to_ticks(6, 0) }, brightness: 5 };
+        config.format = Format { clock: format::Clock::H12, leading_zero: false, unit: format::Unit::Fahrenheit };
+
+        let mut store = Store::new(RamFlash::new(), SCHEMA_VERSION).unwrap();
+        save(&mut store, &config).unwrap();
+        let mut store = Store::new(store.free(), SCHEMA_VERSION).unwrap();
+        let loaded = load(&mut store);
+
+        assert_eq!(loaded.alarms, config.alarms);
+        assert_eq!(loaded.stages, config.stages);
+        assert_eq!(loaded.snooze_minutes, 5);
+        assert_eq!(loaded.sunrise_minutes, 0);
+        assert_eq!(loaded.settings_timeout_minutes, 2);
+        assert_eq!(loaded.volume, 0);
+        assert_eq!(loaded.thermostat, config.thermostat);
+        assert_eq!(loaded.night_light, config.night_light);
+        assert_eq!(loaded.format, config.format);
+    }
+
+    #[test]
+    fn invalid_values_fall_back_to_defaults() {
+        let mut store = Store::new(RamFlash::new(), SCHEMA_VERSION).unwrap();
+        store.write(KEY_ALARMS, &[0xFF, 0xFF, 0xFF, 0xFF, 1, 0]).unwrap();
+        store.write(KEY_SNOOZE_MINUTES, &0u32.to_le_bytes()).unwrap();
+        store.write(KEY_SUNRISE_MINUTES, &[1, 2]).unwrap();
+        store.write(KEY_SETTINGS_TIMEOUT_MINUTES, &[2, 0, 0, 0, 0]).unwrap();
+        store.write(KEY_VOLUME, &[volume::MAX_VOLUME + 1]).unwrap();
+        store.write(KEY_ALARM_SOUNDS + 1, &[SOUNDS.len() as u8]).unwrap();
+        store.write(KEY_ALARM_HAPTICS + 2, &[PATTERNS.len() as u8]).unwrap();
+        store.write(KEY_THERMOSTAT, &[1, 0xFF, 0x7F, 40, 10, 30, 5, 3]).unwrap();
+        store.write(KEY_QUIET_HOURS, &[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0]).unwrap();
+        store.write(KEY_NIGHT_LIGHT, &[0, 30, 0, 0, 0]).unwrap();
+        store.write(KEY_NIGHT_LIGHT_SCHEDULE, &[0, 0, 0, 0, 0, 1, 0, 0, night_light::MAX_BRIGHTNESS + 1]).unwrap();
+        store.write(KEY_STAGES + 1, &[alarm::SOUND, 60, 0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+        store.write(KEY_FORMAT, &[1, 2, 0]).unwrap();
+
+        let config = load(&mut store);
+        assert_eq!(config.alarms[0], Alarm::DISABLED);
+        assert_eq!(config.snooze_minutes, alarm::DEFAULT_SNOOZE_MINUTES);
+        assert_eq!(config.sunrise_minutes, alarm::DEFAULT_SUNRISE_MINUTES);
+        assert_eq!(config.settings_timeout_minutes, time::DEFAULT_SETTINGS_TIMEOUT_MINUTES);
+        assert_eq!(config.volume, volume::DEFAULT_VOLUME);
+        assert_eq!(config.alarms[1].sound, sound::DEFAULT_SOUND);
+        assert_eq!(config.alarms[2].haptic, haptic::DEFAULT_PATTERN);
+        assert_eq!(config.thermostat, thermostat::DEFAULT_SETTINGS);
+        assert_eq!(config.night_light, night_light::DEFAULT_SETTINGS);
+        assert_eq!(config.stages[1], alarm::DEFAULT_STAGES[1]);
+        assert_eq!(config.format, format::DEFAULT_FORMAT);
+    }
+}
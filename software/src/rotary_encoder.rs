@@ -15,6 +15,7 @@ use core::fmt::Write;
 
 const ROTARY_ENCODER_THRESHOLD_SEC: f32 = 0.1;
 const LONG_PRESS_THRESHOLD_SEC: f32 = 0.5;
+const HOLD_THRESHOLD_SEC: f32 = 2.0;
 const DEBOUNCE_THRESHOLD_SEC: f32 = 0.1;
 
 pub(crate) fn init(
@@ -94,10 +95,12 @@ pub(crate) fn handle_gpiote_interrupt(mut cx: gpiote_interrupt::Context) {
     if gpiote.channel1().is_event_triggered() {
         gpiote.channel1().reset_events();
 
-        match elapsed_time > LONG_PRESS_THRESHOLD_SEC {
-            true => state_machine::spawn(Event::Encoder(EncoderEvent::LongPressed)).ok(),
-            false => state_machine::spawn(Event::Encoder(EncoderEvent::ShortPressed)).ok(),
+        let event = match elapsed_time {
+            _ if elapsed_time > HOLD_THRESHOLD_SEC => EncoderEvent::Held,
+            _ if elapsed_time > LONG_PRESS_THRESHOLD_SEC => EncoderEvent::LongPressed,
+            _ => EncoderEvent::ShortPressed,
         };
+        state_machine::spawn(Event::Encoder(event)).ok();
     }});
 }
 
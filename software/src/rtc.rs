@@ -1,6 +1,6 @@
 use {
-    crate::{app::*, state_machine::*},
-    core::sync::atomic::Ordering,
+    crate::{alarm, app::*, counter, state_machine::*, timer::*},
+    core::sync::atomic::{AtomicU32, Ordering},
     hal::{pac::RTC1, rtc::*},
     nrf52833_hal as hal,
     rtic::Mutex,
@@ -10,23 +10,58 @@ use {
 use core::fmt::Write;
 
 const RTC_PRESCALER: u32 = 4095; // 8 Hz RTC frequency, max prescaler value
-const MAX_TICKS: u32 = 16_777_216; // 24 bit max value for RTC counter
-pub const TICKS_PER_SECOND: u32 = 8;
-pub const TICKS_PER_MINUTE: u32 = TICKS_PER_SECOND * 60; // Interrupt every second for demonstration purpose, will be 8*60 in production
-pub const TICKS_PER_HOUR: u32 = TICKS_PER_MINUTE * 60;
-pub const TICKS_PER_DAY: u32 = TICKS_PER_HOUR * 24;
-pub const TIMEOUT_SETTINGS_TICKS: u32 = TICKS_PER_MINUTE * 5; // Timeout after 5 minutes
-pub const BLINK_TICKS: u32 = TICKS_PER_SECOND/2; // Blink every 1 seconds
-
-pub(crate) fn init(rtc: RTC1) -> Rtc<hal::pac::RTC1> {
+
+// The tick math is kept in the library so it can be tested on the host
+pub(crate) use crate::time::*;
+
+// Monotonic ticks since boot. Must be called with the RTC locked, so a pending overflow
+// cannot be handled between reading the counter and the overflow count.
+pub(crate) fn now(rtc: &Rtc<RTC1>, overflows: &AtomicU32) -> u64 {
+    // The counter has to be read before the overflow event
+    let counter = rtc.get_counter();
+    let overflow_pending = rtc.is_event_triggered(RtcInterrupt::Overflow);
+    counter::extend(overflows.load(Ordering::Relaxed), counter, overflow_pending)
+}
+
+// Wall clock at monotonic tick `now`, the time offset is in ticks since 2000-01-01 00:00
+pub(crate) fn date_time(time_offset: i64, now: u64) -> DateTime {
+    DateTime::from_ticks(now.wrapping_add_signed(time_offset))
+}
+
+// Compare register and its interrupt, by index
+fn compare(index: usize) -> (RtcCompareReg, RtcInterrupt) {
+    match index {
+        0 => (RtcCompareReg::Compare0, RtcInterrupt::Compare0),
+        1 => (RtcCompareReg::Compare1, RtcInterrupt::Compare1),
+        2 => (RtcCompareReg::Compare2, RtcInterrupt::Compare2),
+        _ => (RtcCompareReg::Compare3, RtcInterrupt::Compare3),
+    }
+}
+
+// Programs the nearest deadlines of the timer queue into the compare registers
+fn program_compares(rtc: &mut Rtc<RTC1>, now: u64, timers: &TimerQueue) {
+    for (index, deadline) in timers.nearest().into_iter().enumerate() {
+        let (compare, interrupt) = compare(index);
+        match deadline {
+            Some(deadline) => {
+                rtc.set_compare(compare, counter::compare_value(now, deadline))
+                    .unwrap();
+                rtc.enable_interrupt(interrupt, None);
+            }
+            None => rtc.disable_interrupt(interrupt, None),
+        }
+    }
+}
+
+pub(crate) fn init(rtc: RTC1) -> (Rtc<hal::pac::RTC1>, TimerQueue) {
     let mut rtc = hal::rtc::Rtc::new(rtc, RTC_PRESCALER).unwrap();
     // Set to interrupt straight away, to initialize the periodic update
-    rtc.set_compare(RtcCompareReg::Compare0, TICKS_PER_SECOND*2)
-        .unwrap();
-    rtc.enable_interrupt(RtcInterrupt::Compare0, None);
+    let mut timers = TimerQueue::new();
+    timers.start(Timer::PeriodicUpdate, (TICKS_PER_SECOND * 2) as u64);
+    program_compares(&mut rtc, 0, &timers);
     rtc.enable_interrupt(RtcInterrupt::Overflow, None);
     rtc.enable_counter();
-    rtc
+    (rtc, timers)
 }
 
 pub(crate) fn handle_interrupt(mut cx: rtc_interrupt::Context) {
@@ -34,149 +69,144 @@ pub(crate) fn handle_interrupt(mut cx: rtc_interrupt::Context) {
     cx.shared.rtt_hw.lock(|rtt_hw| {
         writeln!(rtt_hw, "RTC interrupt").ok();
     });
+    let overflows = cx.shared.rtc_overflows;
     cx.shared.rtc.lock(|rtc| {
-        // Need to check which interrupt has been triggered
-        // multiple interrupts can be triggered at the same time
-
-        // Compare 0: Periodic interrupt every minute
-        if rtc.is_event_triggered(RtcInterrupt::Compare0) {
-            rtc.reset_event(RtcInterrupt::Compare0);
-
-            let counter = rtc.get_counter();
-            state_machine::spawn(Event::Timer(TimerEvent::PeriodicUpdate(counter))).ok();
-        }
-        // Compare 1: Alarm interrupt
-        if rtc.is_event_triggered(RtcInterrupt::Compare1) {
-            rtc.reset_event(RtcInterrupt::Compare1);
-            state_machine::spawn(Event::Timer(TimerEvent::AlarmTriggered)).ok();
-        }
-        // Compare 2: Timeout interrupt
-        if rtc.is_event_triggered(RtcInterrupt::Compare2) {
-            rtc.reset_event(RtcInterrupt::Compare2);
-            state_machine::spawn(Event::Timer(TimerEvent::Timeout)).ok();
-        }
-        // Compare 3: Blink interrupt
-        if rtc.is_event_triggered(RtcInterrupt::Compare3) {
-            rtc.reset_event(RtcInterrupt::Compare3);
-            state_machine::spawn(Event::Timer(TimerEvent::Blink)).ok();
-        }
-        // Overflow: RTC counter has reached its maximum value
+        // Overflow: RTC counter has wrapped, extend the monotonic ticks
         if rtc.is_event_triggered(RtcInterrupt::Overflow) {
+            overflows.fetch_add(1, Ordering::Relaxed);
             rtc.reset_event(RtcInterrupt::Overflow);
-
-            let time_offset_ticks = cx.shared.time_offset_ticks.load(Ordering::Relaxed);
-            // Update the time offset to current time, adjusting for overflow
-            let new_offset = (MAX_TICKS + time_offset_ticks) % TICKS_PER_DAY;
-
-            cx.shared
-                .time_offset_ticks
-                .store(new_offset, Ordering::Relaxed);
         };
-    });
-}
+        // Compare registers only wake us up, the timer queue knows what has expired
+        for index in 0..COMPARE_REGISTERS {
+            rtc.reset_event(compare(index).1);
+        }
 
-pub(crate) fn set_alarm(mut cx: set_alarm::Context, ticks: u32) {
-    let next_interrupt =
-        next_alarm_ticks(cx.shared.time_offset_ticks.load(Ordering::Relaxed), ticks);
-    cx.shared.rtc.lock(|rtc| {
-        rtc.set_compare(RtcCompareReg::Compare1, next_interrupt)
-            .unwrap();
-        rtc.enable_interrupt(RtcInterrupt::Compare1, None);
+        let now = now(rtc, overflows);
+        cx.shared.timers.lock(|timers| {
+            while let Some(timer) = timers.pop_expired(now) {
+                match timer {
+                    Timer::PeriodicUpdate => {
+                        let now = cx.shared.time_offset.lock(|time_offset| date_time(*time_offset, now));
+                        state_machine::spawn(Event::Timer(TimerEvent::PeriodicUpdate(now))).ok();
+                    }
+                    Timer::Alarm => {
+                        let slot = cx.shared.next_alarm_slot.load(Ordering::Relaxed);
+                        state_machine::spawn(Event::Timer(TimerEvent::AlarmTriggered(slot))).ok();
+                    }
+                    Timer::Snooze => {
+                        state_machine::spawn(Event::Timer(TimerEvent::SnoozeOver)).ok();
+                    }
+                    Timer::Sunrise => {
+                        let slot = cx.shared.sunrise_slot.load(Ordering::Relaxed);
+                        state_machine::spawn(Event::Timer(TimerEvent::SunriseTriggered(slot))).ok();
+                    }
+                }
+            }
+            program_compares(rtc, now, timers);
+        });
     });
-    cx.shared.alarm_offset_ticks.store(ticks, Ordering::Relaxed);
 }
 
-pub(crate) fn disable_alarm(mut cx: disable_alarm::Context) {
+pub(crate) fn stop_timer(mut cx: stop_timer::Context, timer: Timer) {
+    let overflows = cx.shared.rtc_overflows;
     cx.shared.rtc.lock(|rtc| {
-        rtc.disable_interrupt(RtcInterrupt::Compare1, None);
+        let now = now(rtc, overflows);
+        cx.shared.timers.lock(|timers| {
+            timers.stop(timer);
+            program_compares(rtc, now, timers);
+        });
     });
 }
 
-pub(crate) fn set_time(mut cx: set_time::Context, ticks: u32) {
-    cx.shared.rtc.lock(|rtc| {
-        rtc.clear_counter();
+pub(crate) fn set_alarm(mut cx: set_alarm::Context, slot: usize, alarm: alarm::Alarm) {
+    cx.shared.alarms.lock(|alarms| {
+        alarms[slot] = alarm;
     });
-    cx.shared.time_offset_ticks.store(ticks, Ordering::Relaxed);
+    arm_next_alarm::spawn().ok();
+    save_settings::spawn().ok();
 }
 
-pub(crate) fn set_timeout(mut cx: set_timeout::Context, ticks: u32) {
-    let counter = cx.shared.rtc.lock(|rtc| rtc.get_counter());
-    let timeout_ticks = counter + ticks;
+// Starts the alarm timer for the next alarm, and with sunrise enabled the sunrise timer ahead of
+// it. The state machine is told, a running sunrise stops if it no longer leads up to the alarm.
+pub(crate) fn arm_next_alarm(mut cx: arm_next_alarm::Context) {
+    let alarms = cx.shared.alarms.lock(|alarms| *alarms);
+    let time_offset = cx.shared.time_offset.lock(|time_offset| *time_offset);
+    let sunrise_ticks = cx.shared.sunrise_minutes.load(Ordering::Relaxed) * TICKS_PER_MINUTE;
+    let overflows = cx.shared.rtc_overflows;
     cx.shared.rtc.lock(|rtc| {
-        rtc.set_compare(RtcCompareReg::Compare2, timeout_ticks)
-            .unwrap();
-        rtc.enable_interrupt(RtcInterrupt::Compare2, None);
+        let now = now(rtc, overflows);
+        let next_alarm = alarm::next_alarm(&alarms, date_time(time_offset, now).week_ticks());
+        cx.shared.timers.lock(|timers| {
+            match next_alarm {
+                Some((slot, ticks_until)) => {
+                    timers.start(Timer::Alarm, now + ticks_until as u64);
+                    cx.shared.next_alarm_slot.store(slot, Ordering::Relaxed);
+                    // No sunrise if it should already have started
+                    match sunrise_ticks > 0 && ticks_until > sunrise_ticks {
+                        true => {
+                            timers.start(Timer::Sunrise, now + (ticks_until - sunrise_ticks) as u64);
+                            cx.shared.sunrise_slot.store(slot, Ordering::Relaxed);
+                        }
+                        false => timers.stop(Timer::Sunrise),
+                    }
+                }
+                None => {
+                    timers.stop(Timer::Alarm);
+                    timers.stop(Timer::Sunrise);
+                }
+            }
+            program_compares(rtc, now, timers);
+        });
+        state_machine::spawn(Event::Timer(TimerEvent::AlarmArmed(next_alarm))).ok();
     });
 }
 
-pub(crate) fn disable_timeout(mut cx: disable_timeout::Context) {
+// Starts the snooze timer, the next alarm and its sunrise stay armed
+pub(crate) fn set_snooze(mut cx: set_snooze::Context, ticks: u32) {
+    let overflows = cx.shared.rtc_overflows;
     cx.shared.rtc.lock(|rtc| {
-        rtc.disable_interrupt(RtcInterrupt::Compare2, None);
+        let now = now(rtc, overflows);
+        cx.shared.timers.lock(|timers| {
+            timers.start(Timer::Snooze, now + ticks as u64);
+            program_compares(rtc, now, timers);
+        });
     });
 }
 
-pub(crate) fn set_blinking(mut cx: set_blinking::Context, interval_ticks: u32) {
-    let counter = cx.shared.rtc.lock(|rtc| rtc.get_counter());
-
-    // Align the next interrupt to the nearest future multiple of interval_ticks
-    let next_interrupt = ((counter / interval_ticks) + 1) * interval_ticks;
-
-    cx.shared.rtc.lock(|rtc| {
-        rtc.set_compare(RtcCompareReg::Compare3, next_interrupt % MAX_TICKS)
-            .unwrap();
-        rtc.enable_interrupt(RtcInterrupt::Compare3, None);
+// Sets the time of day, keeping the date
+pub(crate) fn set_time(mut cx: set_time::Context, ticks: u32) {
+    let overflows = cx.shared.rtc_overflows;
+    let now = cx.shared.rtc.lock(|rtc| now(rtc, overflows));
+    cx.shared.time_offset.lock(|time_offset| {
+        let days = date_time(*time_offset, now).days;
+        let ticks = DateTime::new(days, ticks % TICKS_PER_DAY).to_ticks();
+        *time_offset = ticks as i64 - now as i64;
     });
+    // Alarms are armed from the wall clock, which just changed
+    arm_next_alarm::spawn().ok();
 }
 
-pub(crate) fn disable_blinking(mut cx: disable_blinking::Context) {
-    cx.shared.rtc.lock(|rtc| {
-        rtc.disable_interrupt(RtcInterrupt::Compare3, None);
+// Sets the date, keeping the time of day
+pub(crate) fn set_date(mut cx: set_date::Context, days: u32) {
+    let overflows = cx.shared.rtc_overflows;
+    let now = cx.shared.rtc.lock(|rtc| now(rtc, overflows));
+    cx.shared.time_offset.lock(|time_offset| {
+        let ticks = DateTime::new(days, date_time(*time_offset, now).ticks).to_ticks();
+        *time_offset = ticks as i64 - now as i64;
     });
+    // The weekday may have changed
+    arm_next_alarm::spawn().ok();
 }
 
 pub(crate) fn set_periodic_update(mut cx: set_periodic_update::Context, interval_ticks: u32) {
-    let counter = cx.shared.rtc.lock(|rtc| rtc.get_counter());
-
-    // Align the next interrupt to the nearest future multiple of interval_ticks
-    let next_interrupt = ((counter / interval_ticks) + 1) * interval_ticks;
-
-    cx.shared.rtc.lock(|rtc| {
-        rtc.set_compare(RtcCompareReg::Compare0, next_interrupt % MAX_TICKS)
-            .unwrap();
-        rtc.enable_interrupt(RtcInterrupt::Compare0, None);
-    });
-}
-
-pub(crate) fn disable_periodic_update(mut cx: disable_periodic_update::Context) {
+    let time_offset = cx.shared.time_offset.lock(|time_offset| *time_offset);
+    let overflows = cx.shared.rtc_overflows;
     cx.shared.rtc.lock(|rtc| {
-        rtc.disable_interrupt(RtcInterrupt::Compare0, None);
+        let now = now(rtc, overflows);
+        cx.shared.timers.lock(|timers| {
+            // Align the next update to the wall clock, e.g. to full minutes
+            timers.start(Timer::PeriodicUpdate, counter::next_aligned(now, time_offset, interval_ticks));
+            program_compares(rtc, now, timers);
+        });
     });
 }
-
-pub(crate) fn time_to_ticks(hour: u8, minute: u8) -> u32 {
-    let minutes = (hour as u32) * 60 + (minute as u32);
-    let time_offset_ticks = minutes * TICKS_PER_MINUTE;
-
-    time_offset_ticks
-}
-
-pub(crate) fn ticks_to_time(ticks: u32) -> (u8, u8) {
-    let minutes = ticks / TICKS_PER_MINUTE;
-    let hour = ((minutes / 60) % 24) as u8;
-    let minute = (minutes % 60) as u8;
-
-    (hour as u8, minute as u8)
-}
-
-fn next_alarm_ticks(time_offset_ticks: u32, alarm_offset_ticks: u32) -> u32 {
-    let current_time = (time_offset_ticks) % TICKS_PER_DAY;
-    let next_alarm_ticks = if current_time > alarm_offset_ticks {
-        // Alarm time has already passed today, set it for tomorrow
-        TICKS_PER_DAY - current_time + alarm_offset_ticks
-    } else {
-        alarm_offset_ticks - current_time
-    };
-
-    // Modulo MAX_TICKS, adjusting for RTC counter overflow
-    next_alarm_ticks % MAX_TICKS
-}
@@ -0,0 +1,343 @@
+// RTTTL ringtones, e.g. "beep:d=8,o=6,b=160:a,p,4a.5". The name is followed by the default
+// duration, octave and tempo, then the notes: duration, letter or p for a pause, sharp,
+// octave and a dot for half as long again. Played as sine or square tones, no flash needed.
+
+use {crate::mixer, heapless::Vec};
+
+pub const SAMPLE_RATE: u32 = 16_000;
+pub const MAX_LEN: usize = 240;
+
+const DURATIONS: [u32; 6] = [1, 2, 4, 8, 16, 32];
+const OCTAVES: core::ops::RangeInclusive<u32> = 3..=7;
+const BPMS: core::ops::RangeInclusive<u32> = 25..=900;
+const DEFAULTS: Defaults = Defaults { duration: 4, octave: 6, bpm: 63 };
+
+// Octave 4 from C to B in mHz, higher octaves double it
+const FREQUENCIES: [u32; 12] = [
+    261_626, 277_183, 293_665, 311_127, 329_628, 349_228, 369_994, 391_995, 415_305, 440_000, 466_164, 493_883,
+];
+
+const ONE: i32 = 1 << 15; // Levels are fixed point with ONE as 1.0
+const AMPLITUDE: i32 = ONE / 2;
+const ATTACK_SAMPLES: u32 = 16; // 1 ms, a note starting at full level clicks
+const RELEASE_SAMPLES: u32 = 320; // 20 ms, sets repeated notes apart
+const REPEAT_PAUSE_MS: u32 = 1_000; // Between two rounds of a looped melody
+
+pub type Text = Vec<u8, MAX_LEN>;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Error {
+    MissingSection, // Name, defaults and notes are separated by colons
+    InvalidDefault, // Unknown key or value out of range
+    InvalidNote(usize),
+    NoNotes,
+    TooLong, // Longer than MAX_LEN
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Sine, // A parabola per half wave, close enough and without a table
+    Square,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Note {
+    pub frequency_mhz: u32, // 0 for a pause
+    pub duration_ms: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Defaults {
+    duration: u32,
+    octave: u32,
+    bpm: u32,
+}
+
+// Checks the whole ringtone, returns the defaults and where the notes start
+fn parse(text: &[u8]) -> Result<(Defaults, usize), Error> {
+    let mut sections = text.splitn(3, |byte| *byte == b':');
+    let name = sections.next().ok_or(Error::MissingSection)?;
+    let settings = sections.next().ok_or(Error::MissingSection)?;
+    let notes = sections.next().ok_or(Error::MissingSection)?;
+
+    let mut defaults = DEFAULTS;
+    for setting in settings.split(|byte| *byte == b',').map(trim).filter(|setting| !setting.is_empty()) {
+        let (key, value) = match setting.iter().position(|byte| *byte == b'=') {
+            Some(i) => (trim(&setting[..i]), parse_number(trim(&setting[i + 1..])).ok_or(Error::InvalidDefault)?),
+            None => return Err(Error::InvalidDefault),
+        };
+        match key {
+            b"d" if DURATIONS.contains(&value) => defaults.duration = value,
+            b"o" if OCTAVES.contains(&value) => defaults.octave = value,
+            b"b" if BPMS.contains(&value) => defaults.bpm = value,
+            _ => return Err(Error::InvalidDefault),
+        }
+    }
+    if trim(notes).is_empty() {
+        return Err(Error::NoNotes);
+    }
+    for (i, note) in notes.split(|byte| *byte == b',').enumerate() {
+        parse_note(note, &defaults).ok_or(Error::InvalidNote(i))?;
+    }
+    Ok((defaults, name.len() + settings.len() + 2))
+}
+
+// Checks a ringtone can be played, see Player::new
+pub fn check(text: &[u8]) -> Result<(), Error> {
+    parse(text)?;
+    match text.len() {
+        0..=MAX_LEN => Ok(()),
+        _ => Err(Error::TooLong),
+    }
+}
+
+// The notes of a ringtone in order
+pub fn notes(text: &[u8]) -> Result<impl Iterator<Item = Note> + '_, Error> {
+    let (defaults, start) = parse(text)?;
+    Ok(text[start..].split(|byte| *byte == b',').filter_map(move |note| parse_note(note, &defaults)))
+}
+
+fn parse_note(note: &[u8], defaults: &Defaults) -> Option<Note> {
+    let note = trim(note);
+    let digits = note.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    let duration = match digits {
+        0 => defaults.duration,
+        _ => parse_number(&note[..digits]).filter(|duration| DURATIONS.contains(duration))?,
+    };
+    let (letter, mut rest) = note[digits..].split_first()?;
+    let semitone = match letter.to_ascii_lowercase() {
+        b'c' => Some(0),
+        b'd' => Some(2),
+        b'e' => Some(4),
+        b'f' => Some(5),
+        b'g' => Some(7),
+        b'a' => Some(9),
+        b'b' | b'h' => Some(11),
+        b'p' => None,
+        _ => return None,
+    };
+    let mut take = |byte: u8| match rest.split_first() {
+        Some((first, tail)) if *first == byte => {
+            rest = tail;
+            true
+        }
+        _ => false,
+    };
+    let sharp = take(b'#');
+    let mut dotted = take(b'.');
+    let octave = match rest.split_first() {
+        Some((digit, tail)) if digit.is_ascii_digit() => {
+            rest = tail;
+            (digit - b'0') as u32
+        }
+        _ => defaults.octave,
+    };
+    dotted |= rest.first() == Some(&b'.');
+    if rest.len() > dotted as usize || !OCTAVES.contains(&octave) {
+        return None;
+    }
+
+    let whole_ms = 240_000 / defaults.bpm;
+    let duration_ms = match dotted {
+        true => whole_ms * 3 / duration / 2,
+        false => whole_ms / duration,
+    };
+    let frequency_mhz = match semitone {
+        Some(semitone) => {
+            // B# is the C of the next octave
+            let semitone = semitone + sharp as u32;
+            let octave = octave + semitone / 12;
+            let base = FREQUENCIES[semitone as usize % 12];
+            match octave {
+                4.. => base << (octave - 4),
+                _ => base >> (4 - octave),
+            }
+        }
+        None if sharp => return None,
+        None => 0,
+    };
+    Some(Note { frequency_mhz, duration_ms })
+}
+
+fn parse_number(bytes: &[u8]) -> Option<u32> {
+    core::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+fn trim(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|byte| !byte.is_ascii_whitespace()).unwrap_or(bytes.len());
+    let end = bytes.iter().rposition(|byte| !byte.is_ascii_whitespace()).map_or(start, |i| i + 1);
+    &bytes[start..end]
+}
+
+// Plays a copy of the ringtone, so a melody sent from the CLI can outlive the command
+pub struct Player {
+    text: Text,
+    defaults: Defaults,
+    start: usize,  // First note in `text`
+    offset: usize, // Next note in `text`
+    waveform: Waveform,
+    phase: u32,
+    step: u32, // Phase increment per sample, 0 in a pause
+    note_pos: u32,
+    note_len: u32, // Samples
+}
+
+impl Player {
+    pub fn new(text: &[u8], waveform: Waveform) -> Result<Player, Error> {
+        let (defaults, start) = parse(text)?;
+        Ok(Player {
+            text: Vec::from_slice(text).map_err(|_| Error::TooLong)?,
+            defaults,
+            start,
+            offset: start,
+            waveform,
+            phase: 0,
+            step: 0,
+            note_pos: 0,
+            note_len: 0,
+        })
+    }
+
+    // Next sample, a looped melody starts over after a pause
+    pub fn sample(&mut self, looped: bool) -> Option<i16> {
+        while self.note_pos == self.note_len {
+            self.next_note(looped)?;
+        }
+        let attack = self.note_pos.min(ATTACK_SAMPLES) * ONE as u32 / ATTACK_SAMPLES;
+        let release = (self.note_len - self.note_pos).min(RELEASE_SAMPLES) * ONE as u32 / RELEASE_SAMPLES;
+        let level = attack.min(release) as i32;
+        self.note_pos += 1;
+        if self.step == 0 {
+            return Some(0);
+        }
+        let phase = self.phase;
+        self.phase = self.phase.wrapping_add(self.step);
+        let wave = match self.waveform {
+            Waveform::Sine => {
+                let x = ((phase & 0x7FFF_FFFF) >> 15) as i32; // 0 to 65535 over half a wave
+                ((x * (65_536 - x)) >> 15).min(ONE - 1)
+            }
+            // A square wave sounds much louder at the same level
+            Waveform::Square => ONE / 2,
+        };
+        let sample = ((((wave * AMPLITUDE) >> 15) * level) >> 15) as i16;
+        Some(match phase & 0x8000_0000 {
+            0 => sample,
+            _ => -sample,
+        })
+    }
+
+    fn next_note(&mut self, looped: bool) -> Option<()> {
+        let note = if self.offset > self.text.len() {
+            if !looped {
+                return None;
+            }
+            self.offset = self.start;
+            Note { frequency_mhz: 0, duration_ms: REPEAT_PAUSE_MS }
+        } else {
+            let notes = &self.text[self.offset..];
+            let len = notes.iter().position(|byte| *byte == b',').unwrap_or(notes.len());
+            self.offset += len + 1;
+            // Checked when the player was created
+            parse_note(&notes[..len], &self.defaults)?
+        };
+        self.step = (((note.frequency_mhz as u64) << 32) / (SAMPLE_RATE as u64 * 1000)) as u32;
+        self.note_pos = 0;
+        self.note_len = note.duration_ms * SAMPLE_RATE / 1000;
+        Some(())
+    }
+}
+
+impl mixer::Input for Player {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+        self.sample(looped).map(|sample| [sample; 2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(text: &str) -> Vec<Note, 16> {
+        super::notes(text.as_bytes()).unwrap().collect()
+    }
+
+    fn note(frequency_mhz: u32, duration_ms: u32) -> Note {
+        Note { frequency_mhz, duration_ms }
+    }
+
+    fn samples(player: &mut Player, looped: bool, count: usize) -> std::vec::Vec<i16> {
+        (0..count).map_while(|_| player.sample(looped)).collect()
+    }
+
+    #[test]
+    fn notes_follow_the_defaults() {
+        // A whole note lasts 2 s at 120 bpm
+        let notes = notes("test:d=8,o=5,b=120:c, 4e6,p,g#.,b.4,32h");
+        assert_eq!(
+            notes,
+            [note(523_252, 250), note(1_318_512, 500), note(0, 250), note(830_610, 375), note(493_883, 375), note(987_766, 62)]
+        );
+    }
+
+    #[test]
+    fn missing_defaults_are_taken_from_the_standard() {
+        assert_eq!(notes("x::c,b#"), [note(1_046_504, 952), note(2_093_008, 952)]);
+    }
+
+    #[test]
+    fn invalid_ringtones_are_rejected() {
+        assert_eq!(Player::new(b"no sections", Waveform::Sine).err(), Some(Error::MissingSection));
+        assert_eq!(Player::new(b"x:d=3:c", Waveform::Sine).err(), Some(Error::InvalidDefault));
+        assert_eq!(Player::new(b"x:q=1:c", Waveform::Sine).err(), Some(Error::InvalidDefault));
+        assert_eq!(Player::new(b"x::c,z", Waveform::Sine).err(), Some(Error::InvalidNote(1)));
+        assert_eq!(Player::new(b"x::c,c9", Waveform::Sine).err(), Some(Error::InvalidNote(1)));
+        assert_eq!(Player::new(b"x::p#", Waveform::Sine).err(), Some(Error::InvalidNote(0)));
+        assert_eq!(Player::new(b"x:: ", Waveform::Sine).err(), Some(Error::NoNotes));
+        let long = [&b"x::c"[..], &b",c".repeat(MAX_LEN / 2)].concat();
+        assert_eq!(Player::new(&long, Waveform::Sine).err(), Some(Error::TooLong));
+    }
+
+    #[test]
+    fn tone_has_the_pitch_of_the_note() {
+        // 250 ms of A5 is 220 periods
+        let mut player = Player::new(b"a:d=4,o=5,b=240:a", Waveform::Sine).unwrap();
+        let samples = samples(&mut player, false, usize::MAX);
+        assert_eq!(samples.len(), 4000);
+        let rising = samples.windows(2).filter(|pair| pair[0] < 0 && pair[1] >= 0).count();
+        assert!((219..=221).contains(&rising), "{}", rising);
+        let peak = samples.iter().map(|sample| sample.unsigned_abs()).max().unwrap();
+        assert!((AMPLITUDE as u16 - 200..=AMPLITUDE as u16).contains(&peak), "{}", peak);
+    }
+
+    #[test]
+    fn square_wave_holds_its_level() {
+        let mut player = Player::new(b"a:d=4,o=5,b=240:a", Waveform::Square).unwrap();
+        let samples = samples(&mut player, false, 2000);
+        // Past the attack and before the release
+        let level = ((ONE / 2 * AMPLITUDE) >> 15) as i16;
+        assert!(samples[200..1800].iter().all(|sample| sample.abs() == level));
+    }
+
+    #[test]
+    fn looped_melody_starts_over_after_a_pause() {
+        let mut player = Player::new(b"a:d=4,o=5,b=240:a", Waveform::Sine).unwrap();
+        let samples = samples(&mut player, true, 24_000); // The note, a second of pause and the note again
+        assert_eq!(samples.len(), 24_000);
+        assert!(samples[4000..20_000].iter().all(|sample| *sample == 0));
+        assert!(samples[20_000..22_000].iter().any(|sample| *sample != 0));
+    }
+
+    #[test]
+    fn pause_is_silent() {
+        let mut player = Player::new(b"p:d=4,b=240:p,a", Waveform::Sine).unwrap();
+        let samples = samples(&mut player, false, usize::MAX);
+        assert_eq!(samples.len(), 8000);
+        assert!(samples[..4000].iter().all(|sample| *sample == 0));
+        assert!(samples[4000..].iter().any(|sample| *sample != 0));
+    }
+}
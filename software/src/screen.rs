@@ -0,0 +1,372 @@
+// Layout of the 128x64 screen, drawn onto any monochrome DrawTarget so it can be rendered
+// into a framebuffer on the host. The firmware formats the texts and flushes the display.
+
+use {
+    crate::{
+        alarm::{self, Alarm},
+        night_light,
+        sound::SOUNDS,
+        state_machine::Section,
+        volume,
+    },
+    embedded_graphics::{
+        mono_font::MonoTextStyle,
+        pixelcolor::BinaryColor,
+        prelude::*,
+        primitives::{PrimitiveStyle, Rectangle},
+        text::Text,
+    },
+    profont::*,
+};
+
+pub const SIZE: Size = Size::new(128, 64);
+
+const TIME_DISPLAY_STYLE: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&PROFONT_24_POINT, BinaryColor::On);
+const TEMP_DISPLAY_STYLE: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&PROFONT_14_POINT, BinaryColor::On);
+const DATE_DISPLAY_STYLE: MonoTextStyle<BinaryColor> = MonoTextStyle::new(&PROFONT_9_POINT, BinaryColor::On);
+
+const FONT_SIZE: Point = Point::new(16, 29);
+
+const TIME_POSITION: Point = Point::new(24, 20);
+const HOUR_POSITION: Point = Point::new(TIME_POSITION.x, TIME_POSITION.y);
+const MERIDIEM_POSITION: Point = Point::new(4, TIME_POSITION.y); // Left of the hour in 12 hour mode
+const COLON_POSITION: Point = Point::new(HOUR_POSITION.x + (FONT_SIZE.x * 2), TIME_POSITION.y);
+const MINUTE_POSITION: Point = Point::new(COLON_POSITION.x + FONT_SIZE.x, TIME_POSITION.y);
+const TEMPERATURE_POSITION: Point = Point::new(35, 50);
+const DATE_POSITION: Point = Point::new(22, 63);
+const ALARM_POSITION: Point = Point::new(MINUTE_POSITION.x + (FONT_SIZE.x * 2), TIME_POSITION.y);
+pub const ALARM_STRING: &str = "(«";
+const ALARM_SLOT_POSITION: Point = Point::new(14, TEMPERATURE_POSITION.y);
+const ALARM_WEEKDAYS_POSITION: Point = Point::new(ALARM_SLOT_POSITION.x + 30, TEMPERATURE_POSITION.y);
+const RINGING_SLOT_POSITION: Point = Point::new(ALARM_POSITION.x, TEMPERATURE_POSITION.y);
+const ALARM_OFF_STRING: &str = "off";
+const SNOOZE_POSITION: Point = Point::new(ALARM_POSITION.x, 12);
+pub const SNOOZE_STRING: &str = "Zz";
+const BAR_POSITION: Point = Point::new(14, 38); // Volume and night light brightness
+const BAR_SIZE: Size = Size::new(100, 12);
+
+// Texts of the screen, formatted by the firmware
+#[derive(Clone, Copy, Debug)]
+pub struct Texts<'a> {
+    pub hour: &'a str,
+    pub meridiem: &'a str, // Empty in 24 hour mode
+    pub minute: &'a str,
+    pub temperature: &'a str,
+    pub date: &'a str,
+}
+
+// Draws the screen for a section onto a cleared target. `hidden` is the blink phase that
+// leaves the blinking part out.
+pub fn draw<D>(disp: &mut D, texts: &Texts, section: Section, hidden: bool) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    if hidden {
+        match section {
+            Section::Hour => {
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_temperature(disp, texts.temperature)?;
+                draw_date(disp, texts.date)?;
+            }
+            Section::Minute => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_temperature(disp, texts.temperature)?;
+                draw_date(disp, texts.date)?;
+            }
+            Section::Display | Section::Volume(_) | Section::Brightness(_) => {}
+            Section::AlarmIcon(slot) => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_temperature(disp, texts.temperature)?;
+                draw_alarm_icon(disp)?;
+                draw_alarm_slot(disp, slot, RINGING_SLOT_POSITION)?;
+            }
+            Section::SnoozeIcon(slot) => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_temperature(disp, texts.temperature)?;
+                draw_snooze_icon(disp)?;
+                draw_alarm_slot(disp, slot, RINGING_SLOT_POSITION)?;
+            }
+            Section::AlarmSlot(_, alarm) => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_alarm_weekdays(disp, &alarm)?;
+            }
+            Section::AlarmHour(slot, alarm) => {
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_alarm_slot(disp, slot, ALARM_SLOT_POSITION)?;
+                draw_alarm_weekdays(disp, &alarm)?;
+            }
+            Section::AlarmMinute(slot, alarm) => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_alarm_slot(disp, slot, ALARM_SLOT_POSITION)?;
+                draw_alarm_weekdays(disp, &alarm)?;
+            }
+            Section::AlarmWeekdays(slot, _) | Section::AlarmSound(slot, _) => {
+                draw_hour(disp, texts)?;
+                draw_colon(disp)?;
+                draw_minute(disp, texts.minute)?;
+                draw_alarm_slot(disp, slot, ALARM_SLOT_POSITION)?;
+            }
+        }
+    } else {
+        draw_hour(disp, texts)?;
+        draw_colon(disp)?;
+        draw_minute(disp, texts.minute)?;
+        match section {
+            Section::AlarmSlot(slot, alarm)
+            | Section::AlarmHour(slot, alarm)
+            | Section::AlarmMinute(slot, alarm)
+            | Section::AlarmWeekdays(slot, alarm) => {
+                draw_alarm_slot(disp, slot, ALARM_SLOT_POSITION)?;
+                draw_alarm_weekdays(disp, &alarm)?;
+            }
+            Section::AlarmSound(slot, alarm) => {
+                draw_alarm_slot(disp, slot, ALARM_SLOT_POSITION)?;
+                draw_sound_name(disp, &alarm)?;
+            }
+            Section::AlarmIcon(slot) => {
+                draw_temperature(disp, texts.temperature)?;
+                draw_alarm_icon(disp)?;
+                draw_alarm_slot(disp, slot, RINGING_SLOT_POSITION)?;
+            }
+            Section::SnoozeIcon(slot) => {
+                draw_temperature(disp, texts.temperature)?;
+                draw_snooze_icon(disp)?;
+                draw_alarm_slot(disp, slot, RINGING_SLOT_POSITION)?;
+            }
+            Section::Volume(volume) => draw_bar(disp, volume, volume::MAX_VOLUME)?,
+            Section::Brightness(brightness) => draw_bar(disp, brightness, night_light::MAX_BRIGHTNESS)?,
+            _ => {
+                draw_temperature(disp, texts.temperature)?;
+                draw_date(disp, texts.date)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw_text<D>(disp: &mut D, text: &str, position: Point, style: MonoTextStyle<BinaryColor>) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    Text::new(text, position, style).draw(disp).map(|_| ())
+}
+
+// The meridiem blinks along with the hour it belongs to
+fn draw_hour<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, texts: &Texts) -> Result<(), D::Error> {
+    draw_text(disp, texts.meridiem, MERIDIEM_POSITION, DATE_DISPLAY_STYLE)?;
+    draw_text(disp, texts.hour, HOUR_POSITION, TIME_DISPLAY_STYLE)
+}
+
+fn draw_colon<D: DrawTarget<Color = BinaryColor>>(disp: &mut D) -> Result<(), D::Error> {
+    draw_text(disp, ":", COLON_POSITION, TIME_DISPLAY_STYLE)
+}
+
+fn draw_minute<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, time_str: &str) -> Result<(), D::Error> {
+    draw_text(disp, time_str, MINUTE_POSITION, TIME_DISPLAY_STYLE)
+}
+
+fn draw_temperature<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, temp_str: &str) -> Result<(), D::Error> {
+    draw_text(disp, temp_str, TEMPERATURE_POSITION, TEMP_DISPLAY_STYLE)
+}
+
+fn draw_date<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, date_str: &str) -> Result<(), D::Error> {
+    draw_text(disp, date_str, DATE_POSITION, DATE_DISPLAY_STYLE)
+}
+
+fn draw_alarm_icon<D: DrawTarget<Color = BinaryColor>>(disp: &mut D) -> Result<(), D::Error> {
+    draw_text(disp, ALARM_STRING, ALARM_POSITION, TIME_DISPLAY_STYLE)
+}
+
+fn draw_snooze_icon<D: DrawTarget<Color = BinaryColor>>(disp: &mut D) -> Result<(), D::Error> {
+    draw_text(disp, SNOOZE_STRING, SNOOZE_POSITION, TEMP_DISPLAY_STYLE)
+}
+
+fn draw_alarm_slot<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, slot: usize, position: Point) -> Result<(), D::Error> {
+    let label = [b'A', b'1' + slot as u8];
+    draw_text(disp, core::str::from_utf8(&label).unwrap(), position, TEMP_DISPLAY_STYLE)
+}
+
+fn draw_alarm_weekdays<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, alarm: &Alarm) -> Result<(), D::Error> {
+    let weekdays = alarm::weekdays_to_bytes(alarm.weekdays);
+    let weekdays_str = match alarm.enabled {
+        true => core::str::from_utf8(&weekdays).unwrap(),
+        false => ALARM_OFF_STRING,
+    };
+    draw_text(disp, weekdays_str, ALARM_WEEKDAYS_POSITION, TEMP_DISPLAY_STYLE)
+}
+
+// In the small font, the longest name does not fit in the font of the weekdays
+fn draw_sound_name<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, alarm: &Alarm) -> Result<(), D::Error> {
+    let name = core::str::from_utf8(SOUNDS[alarm.sound as usize].name).unwrap();
+    draw_text(disp, name, ALARM_WEEKDAYS_POSITION, DATE_DISPLAY_STYLE)
+}
+
+// Outline with the part up to the value filled in
+fn draw_bar<D: DrawTarget<Color = BinaryColor>>(disp: &mut D, value: u8, max: u8) -> Result<(), D::Error> {
+    Rectangle::new(BAR_POSITION, BAR_SIZE)
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(disp)?;
+    let width = (BAR_SIZE.width - 4) * value as u32 / max as u32;
+    Rectangle::new(BAR_POSITION + Point::new(2, 2), Size::new(width, BAR_SIZE.height - 4))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(disp)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            format::{self, Clock, Format, Unit},
+            time::TICKS_PER_HOUR,
+        },
+        core::convert::Infallible,
+        std::{fs, path::PathBuf},
+    };
+
+    const WIDTH: usize = SIZE.width as usize;
+    const HEIGHT: usize = SIZE.height as usize;
+
+    // Pixels of the screen in memory, like the buffered mode of the SSD1306 driver
+    struct Framebuffer {
+        pixels: [[bool; WIDTH]; HEIGHT],
+    }
+
+    impl OriginDimensions for Framebuffer {
+        fn size(&self) -> Size {
+            SIZE
+        }
+    }
+
+    impl DrawTarget for Framebuffer {
+        type Color = BinaryColor;
+        type Error = Infallible;
+
+        fn draw_iter<I: IntoIterator<Item = Pixel<BinaryColor>>>(&mut self, pixels: I) -> Result<(), Infallible> {
+            for Pixel(point, color) in pixels {
+                if let (Ok(x @ 0..WIDTH), Ok(y @ 0..HEIGHT)) = (usize::try_from(point.x), usize::try_from(point.y)) {
+                    self.pixels[y][x] = color.is_on();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Framebuffer {
+        // Plain PBM, a row takes two lines to stay within 70 characters
+        fn to_pbm(&self) -> String {
+            let mut pbm = format!("P1\n{} {}\n", WIDTH, HEIGHT);
+            for row in &self.pixels {
+                for half in row.chunks(WIDTH / 2) {
+                    pbm.extend(half.iter().map(|on| if *on { '1' } else { '0' }));
+                    pbm.push('\n');
+                }
+            }
+            pbm
+        }
+    }
+
+    const TEXTS: Texts = Texts { hour: "07", meridiem: "", minute: "30", temperature: "21.5°C", date: "Mon 2024-01-15" };
+    const ALARM: Alarm = Alarm::new(6 * TICKS_PER_HOUR, alarm::WEEKDAYS);
+
+    fn render(texts: &Texts, section: Section, hidden: bool) -> String {
+        let mut framebuffer = Framebuffer { pixels: [[false; WIDTH]; HEIGHT] };
+        draw(&mut framebuffer, texts, section, hidden).unwrap();
+        framebuffer.to_pbm()
+    }
+
+    // Compares against snapshots/<name>.pbm, UPDATE_SNAPSHOTS=1 writes them instead
+    fn assert_snapshot(name: &str, section: Section, hidden: bool) {
+        assert_texts_snapshot(name, &TEXTS, section, hidden);
+    }
+
+    fn assert_texts_snapshot(name: &str, texts: &Texts, section: Section, hidden: bool) {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "snapshots", &format!("{}.pbm", name)].iter().collect();
+        let rendered = render(texts, section, hidden);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &rendered).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|_| panic!("missing snapshot {}", path.display()));
+        assert!(rendered == expected, "{} differs from {}, rendered:\n{}", name, path.display(), rendered);
+    }
+
+    // The visible phase, then the hidden phase of a blinking section
+    fn assert_blink_snapshots(name: &str, section: Section) {
+        assert_snapshot(name, section, false);
+        assert_snapshot(&format!("{}-hidden", name), section, true);
+    }
+
+    #[test]
+    fn idle() {
+        assert_snapshot("idle", Section::Display, false);
+        assert_snapshot("idle-hidden", Section::Display, true);
+    }
+
+    // The texts as the firmware formats them for 19:30 and 21.5 °C
+    #[test]
+    fn twelve_hour_fahrenheit() {
+        let format = Format { clock: Clock::H12, unit: Unit::Fahrenheit, ..format::DEFAULT_FORMAT };
+        let hour = format::hour(19, &format);
+        let temperature = format::temperature(21.5, &format);
+        let texts = Texts { hour: &hour, meridiem: format::meridiem(19, &format), temperature: &temperature, ..TEXTS };
+        assert_eq!(texts.hour, "07");
+        assert_eq!(texts.meridiem, "PM");
+        assert_texts_snapshot("idle-12h-fahrenheit", &texts, Section::Display, false);
+        assert_texts_snapshot("clock-hours-12h-hidden", &texts, Section::Hour, true);
+    }
+
+    #[test]
+    fn alarm() {
+        assert_blink_snapshots("alarm", Section::AlarmIcon(2));
+    }
+
+    #[test]
+    fn snoozed() {
+        assert_blink_snapshots("snoozed", Section::SnoozeIcon(2));
+    }
+
+    #[test]
+    fn clock_settings() {
+        assert_blink_snapshots("clock-hours", Section::Hour);
+        assert_blink_snapshots("clock-minutes", Section::Minute);
+    }
+
+    #[test]
+    fn alarm_settings() {
+        assert_blink_snapshots("alarm-slot", Section::AlarmSlot(1, ALARM));
+        assert_blink_snapshots("alarm-hours", Section::AlarmHour(1, ALARM));
+        assert_blink_snapshots("alarm-minutes", Section::AlarmMinute(1, ALARM));
+        assert_blink_snapshots("alarm-weekdays", Section::AlarmWeekdays(1, ALARM));
+        assert_blink_snapshots("alarm-sound", Section::AlarmSound(1, ALARM));
+    }
+
+    #[test]
+    fn disabled_alarm_shows_off() {
+        assert_snapshot("alarm-slot-off", Section::AlarmSlot(0, Alarm::DISABLED), false);
+    }
+
+    #[test]
+    fn bars() {
+        assert_snapshot("volume", Section::Volume(volume::MAX_VOLUME / 2), false);
+        assert_snapshot("brightness", Section::Brightness(20), false);
+    }
+
+    #[test]
+    fn hidden_phase_leaves_only_the_blinking_part_out() {
+        let count = |pbm: &str| pbm.lines().skip(2).map(|line| line.matches('1').count()).sum::<usize>();
+        let section = Section::AlarmHour(1, ALARM);
+        assert!(count(&render(&TEXTS, section, true)) < count(&render(&TEXTS, section, false)));
+        assert!(count(&render(&TEXTS, section, true)) > 0);
+    }
+}
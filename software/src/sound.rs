@@ -0,0 +1,76 @@
+// Sounds an alarm can play, selected per alarm by index, so new sounds go at the end.
+// The clips are stored as IMA ADPCM, a quarter of the 16 bit PCM size, converted from
+// the WAV files next to them with tools/wav_to_adpcm.py. Surf and the melodies are
+// synthesized instead.
+
+use crate::{rtttl::Waveform, surf};
+
+pub const DEFAULT_SOUND: u8 = 0;
+pub const PREVIEW_GAIN: u32 = 100; // Percent, the volume still applies
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Source {
+    Wav(&'static [u8]),             // RIFF/WAVE file, see wav::parse
+    Surf(surf::Params),             // Endless surf noise, see surf::Surf
+    Rtttl(&'static [u8], Waveform), // Ringtone, see rtttl::Player
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sound {
+    pub name: &'static [u8],
+    pub source: Source,
+}
+
+pub const SOUNDS: [Sound; 8] = [
+    Sound { name: b"sea breeze", source: Source::Wav(include_bytes!("../assets/SeaBreeze3.adpcm.wav")) },
+    Sound { name: b"silent", source: Source::Wav(include_bytes!("../assets/Silent.adpcm.wav")) },
+    Sound { name: b"silent stereo", source: Source::Wav(include_bytes!("../assets/Silent1.adpcm.wav")) },
+    Sound { name: b"sea waves", source: Source::Wav(include_bytes!("../assets/SeaBreeze2.adpcm.wav")) },
+    Sound { name: b"surf", source: Source::Surf(surf::Params { period_ms: 9_000, intensity: 70 }) },
+    Sound { name: b"alarm beeps", source: Source::Rtttl(b"beeps:d=8,o=6,b=160:a,p,a,p,a,p,a,2p", Waveform::Square) },
+    Sound {
+        name: b"westminster",
+        source: Source::Rtttl(b"westminster:d=4,o=5,b=80:e,g#,f#,2b4,e,f#,g#,2e,g#,e,f#,2b4,b4,f#,g#,2e", Waveform::Sine),
+    },
+    Sound {
+        name: b"morning mood",
+        source: Source::Rtttl(
+            b"morning:d=8,o=5,b=100:g,e,d,c,d,e,g,e,d,c,d,e.,16d,e,g,e,g,a,e,a,g,e,d,2c",
+            Waveform::Sine,
+        ),
+    },
+];
+
+// Sound after turning the knob by `steps`, wraps around
+pub fn step(sound: u8, steps: isize) -> u8 {
+    (sound as isize + steps).rem_euclid(SOUNDS.len() as isize) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pcm, rtttl, wav};
+
+    // The mixer resamples every sound, a clip only has to be readable
+    #[test]
+    fn every_sound_can_be_played() {
+        for sound in SOUNDS {
+            match sound.source {
+                Source::Wav(bytes) => {
+                    let mut reader = pcm::Reader::new(wav::parse(bytes).unwrap());
+                    assert!(reader.frame(false).is_some(), "{:?}", sound.name);
+                }
+                Source::Surf(params) => assert!(surf::Surf::new(params, 1).sample(false).is_some()),
+                Source::Rtttl(melody, waveform) => {
+                    assert!(rtttl::Player::new(melody, waveform).is_ok(), "{:?}", sound.name)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn knob_wraps_around() {
+        assert_eq!(step(DEFAULT_SOUND, -1), SOUNDS.len() as u8 - 1);
+        assert_eq!(step(SOUNDS.len() as u8 - 1, 1), 0);
+    }
+}
@@ -1,82 +1,300 @@
 use {
-    crate::app::*,
-    core::sync::atomic::Ordering,
+    crate::{
+        app::*,
+        mixer::{self, Mixer, Voice},
+        outputs::Load,
+        pcm::{self, Reader},
+        rtttl::{self, Player, Waveform},
+        sound::{self, Source},
+        surf::Surf,
+        volume::{self, MAX_VOLUME},
+        wav::{self, Format},
+    },
+    core::sync::atomic::{AtomicBool, Ordering},
     hal::{
-        i2s::{Channels, Format, MckFreq, Pins, Ratio, SampleWidth},
+        i2s::{self, Channels, I2SEvent, MckFreq, Pins, Ratio, SampleWidth},
         pac::I2S,
     },
     nrf52833_hal as hal,
+    rtic::Mutex,
 };
 
 #[cfg(feature = "52833-debug")]
-use core::fmt::Write;
+use {core::fmt::Write, rtt_target::rprintln};
+
+pub const BUFFER_LEN: usize = 1024; // Stereo frames, about 33 ms at the mixer rate
+const FADE_IN_MS: u32 = 2_000; // Alarm sounds start gently, and ease into the next stage
+const FADE_OUT_MS: u32 = 1_000;
 
-const WAV_RAW: &[u8] = include_bytes!("../assets/SeaBreeze3.wav");
-const WAV_HEADER_SIZE: usize = 44;
-const SEGMENT_SIZE: usize = 4;
-pub const BUFFER_LEN: usize = SEGMENT_SIZE / 4;
+// The DMA sends one buffer while the I2S interrupt refills the other. At TXPTRUPD the
+// peripheral has latched the pointer for the next buffer and the previous one is free.
+pub struct Speaker {
+    i2s: hal::i2s::I2S,
+    mixer: Mixer<Stream>,
+    sound: Option<u8>, // On the ambient voice
+    ending: bool,      // Only silence is queued, stop at the next pointer update
+    buffers: &'static mut [[u32; BUFFER_LEN]; 2],
+    latched: usize, // Buffer the DMA is sending
+    playing: bool,
+}
 
-pub(crate) fn init(i2s: I2S, pins: Pins) -> hal::i2s::I2S {
+pub(crate) fn init(i2s: I2S, pins: Pins, buffers: &'static mut [[u32; BUFFER_LEN]; 2]) -> Speaker {
     let i2s = hal::i2s::I2S::new(i2s, pins);
     i2s.set_tx_enabled(true);
-    i2s.set_sample_width(SampleWidth::_16bit);
-    i2s.set_format(Format::I2S);
-    i2s.set_channels(Channels::Stereo);
-    i2s.set_mck_frequency(MckFreq::_32MDiv10);
-    i2s.set_ratio(Ratio::_64x);
-    i2s.enable();
+    i2s.set_format(i2s::Format::I2S);
+    // Every sound is mixed to one format, the I2S never changes
+    let clock = pcm::clock(&mixer::FORMAT).unwrap();
+    configure(&i2s, &mixer::FORMAT, &clock);
+    Speaker {
+        i2s,
+        mixer: Mixer::new(clock.sample_rate()),
+        sound: None,
+        ending: false,
+        buffers,
+        latched: 0,
+        playing: false,
+    }
+}
 
-    i2s
+// Samples of a sound, a clip decoded from flash, synthesized surf or a tune
+#[allow(clippy::large_enum_variant)]
+enum Stream {
+    Clip(Reader<'static>),
+    Surf(Surf),
+    Tune(Player),
 }
 
-pub(crate) fn next_segment(mut cx: play_next_audio_segment::Context) {
-    if !cx.shared.amp_on.load(Ordering::Relaxed) {
-        return;
+// Why a sound cannot be played, only printed with 52833-debug
+#[derive(Debug)]
+#[cfg_attr(not(feature = "52833-debug"), allow(dead_code))]
+enum Error {
+    Wav(wav::Error),
+    Rtttl(rtttl::Error),
+}
+
+impl From<wav::Error> for Error {
+    fn from(error: wav::Error) -> Error {
+        Error::Wav(error)
     }
+}
 
-    let dma_buf = cx.local.dma_buf;
-    let pcm_raw = &WAV_RAW[WAV_HEADER_SIZE..];
-    let num_segments = pcm_raw.len() / SEGMENT_SIZE;
+impl From<rtttl::Error> for Error {
+    fn from(error: rtttl::Error) -> Error {
+        Error::Rtttl(error)
+    }
+}
 
-    let curr_segment = *cx.local.segment_index;
-    let seg_index = *cx.local.segment_index as usize;
+impl Stream {
+    fn open(source: Source) -> Result<Stream, Error> {
+        match source {
+            Source::Wav(bytes) => Ok(Stream::Clip(Reader::new(wav::parse(bytes)?))),
+            // Seeded from the clock, so every alarm hears different waves
+            Source::Surf(params) => Ok(Stream::Surf(Surf::new(params, monotonics::now().ticks() as u32))),
+            Source::Rtttl(melody, waveform) => Ok(Stream::Tune(Player::new(melody, waveform)?)),
+        }
+    }
+}
+
+impl mixer::Input for Stream {
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Stream::Clip(reader) => reader.sample_rate(),
+            Stream::Surf(surf) => surf.sample_rate(),
+            Stream::Tune(player) => player.sample_rate(),
+        }
+    }
+
+    fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+        match self {
+            Stream::Clip(reader) => mixer::Input::frame(reader, looped),
+            Stream::Surf(surf) => surf.frame(looped),
+            Stream::Tune(player) => player.frame(looped),
+        }
+    }
+}
 
-    *cx.local.segment_index = if curr_segment + 1 >= num_segments as u32 {
-        0
-    } else {
-        curr_segment + 1
-    };
+// Configures the I2S for the output of the mixer
+fn configure(i2s: &hal::i2s::I2S, format: &Format, clock: &pcm::Clock) {
+    #[cfg(feature = "52833-debug")]
+    rprintln!("{:?}, played at {} Hz", format, clock.sample_rate());
 
-    //#[cfg(feature = "52833-debug")]
-    //writeln!(cx.local.rtt_speaker, "Playing segment {}", seg_index).ok();
+    i2s.set_sample_width(match format.bits_per_sample {
+        8 => SampleWidth::_8bit,
+        16 => SampleWidth::_16bit,
+        _ => SampleWidth::_24bit,
+    });
+    i2s.set_channels(match format.channels {
+        1 => Channels::Left,
+        _ => Channels::Stereo,
+    });
+    i2s.set_mck_frequency(mck_frequency(clock.mck_divider));
+    i2s.set_ratio(ratio(clock.ratio));
+}
 
-    let start = seg_index * SEGMENT_SIZE;
-    let end = start + SEGMENT_SIZE;
-    let segment = &pcm_raw[start..end];
+fn mck_frequency(divider: u32) -> MckFreq {
+    match divider {
+        8 => MckFreq::_32MDiv8,
+        10 => MckFreq::_32MDiv10,
+        11 => MckFreq::_32MDiv11,
+        15 => MckFreq::_32MDiv15,
+        16 => MckFreq::_32MDiv16,
+        21 => MckFreq::_32MDiv21,
+        23 => MckFreq::_32MDiv23,
+        30 => MckFreq::_32MDiv30,
+        31 => MckFreq::_32MDiv31,
+        32 => MckFreq::_32MDiv32,
+        42 => MckFreq::_32MDiv42,
+        63 => MckFreq::_32MDiv63,
+        _ => MckFreq::_32MDiv125,
+    }
+}
 
-    for (i, chunk) in segment.chunks(4).enumerate().take(BUFFER_LEN) {
-        dma_buf[i] = u32::from_le_bytes(chunk.try_into().unwrap());
+fn ratio(ratio: u32) -> Ratio {
+    match ratio {
+        32 => Ratio::_32x,
+        48 => Ratio::_48x,
+        64 => Ratio::_64x,
+        96 => Ratio::_96x,
+        128 => Ratio::_128x,
+        192 => Ratio::_192x,
+        256 => Ratio::_256x,
+        384 => Ratio::_384x,
+        _ => Ratio::_512x,
     }
+}
 
-    let i2s = cx.local.i2s.take().unwrap();
+// Starts the sound on the ambient voice, or fades a sound already playing to the new gain
+pub(crate) fn start(mut cx: start_audio::Context, sound: u8, looped: bool) {
+    let percent = cx.shared.audio_gain.load(Ordering::Relaxed);
+    let started = cx.shared.speaker.lock(|speaker| speaker.start(sound, looped, percent));
+    if started {
+        cx.shared.audio_playing.store(true, Ordering::Relaxed);
+    }
+}
 
-    unsafe {
-        i2s.set_buffersize(BUFFER_LEN as u32).unwrap();
-        let ptr = dma_buf.as_ptr() as u32;
-        i2s.set_tx_ptr(ptr).unwrap();
+// Plays the ringtone from the CLI once, over the ambient sound
+pub(crate) fn play_tune(mut cx: play_tune::Context, text: &[u8]) {
+    let player = Player::new(text, Waveform::Sine);
+    let started = cx.shared.speaker.lock(|speaker| speaker.play(Voice::Effect, player.map(Stream::Tune), false, sound::PREVIEW_GAIN, mixer::DECLICK_MS));
+    if started {
+        cx.shared.audio_playing.store(true, Ordering::Relaxed);
     }
+}
 
-    i2s.start();
-    let tx_buf: &'static [u32] =
-        unsafe { core::slice::from_raw_parts(dma_buf.as_ptr(), BUFFER_LEN) };
-    let transfer = i2s.tx(tx_buf).expect("I2S TX transfer failed");
+// Fades every voice out, the I2S interrupt stops once they are silent
+pub(crate) fn stop(mut cx: stop_audio::Context) {
+    cx.shared.speaker.lock(|speaker| {
+        speaker.mixer.fade_out(Voice::Ambient, FADE_OUT_MS);
+        speaker.mixer.fade_out(Voice::Effect, FADE_OUT_MS);
+    });
+}
 
-    let (_returned_buffer, new_i2s) = transfer.wait();
+// Switching the amp off while a sound fades out would cut it, the I2S interrupt then
+// switches it off once the audio has stopped
+pub(crate) fn turn_off_amp_after_audio(audio_playing: &AtomicBool, amp_off_pending: &AtomicBool) -> Option<()> {
+    amp_off_pending.store(true, Ordering::Relaxed);
+    if audio_playing.load(Ordering::Relaxed) || !amp_off_pending.swap(false, Ordering::Relaxed) {
+        return Some(());
+    }
+    set_output::spawn(Load::Amp, 0).ok()
+}
 
+// Refills the free buffer, or stops once every voice is over
+pub(crate) fn refill(mut cx: i2s_interrupt::Context) {
+    // Volume changes are heard from the next buffer on
+    let gain = volume::gain(cx.shared.volume.load(Ordering::Relaxed), 100);
+    let playing = cx.shared.speaker.lock(|speaker| speaker.refill(gain));
+    if playing {
+        return;
+    }
+    cx.shared.speaker.lock(|speaker| speaker.stop());
+    cx.shared.audio_playing.store(false, Ordering::Relaxed);
+    // The amp was kept on for the fade out
+    if cx.shared.amp_off_pending.swap(false, Ordering::Relaxed) {
+        set_output::spawn(Load::Amp, 0).ok();
+    }
     #[cfg(feature = "52833-debug")]
-    writeln!(cx.local.rtt_speaker, "Completed segment {}", seg_index).ok();
+    writeln!(cx.local.rtt_speaker, "Audio stopped").ok();
+}
+
+impl Speaker {
+    fn start(&mut self, sound: u8, looped: bool, percent: u32) -> bool {
+        let gain = volume::gain(MAX_VOLUME, percent);
+        let fade_ms = match looped {
+            true => FADE_IN_MS,
+            false => mixer::DECLICK_MS,
+        };
+        if self.mixer.is_playing(Voice::Ambient) && self.sound == Some(sound) {
+            self.mixer.fade_to(Voice::Ambient, gain, fade_ms);
+            return true;
+        }
+        let Some(entry) = sound::SOUNDS.get(sound as usize) else {
+            return false;
+        };
+        // Only a sound that plays counts as the current one, a failed start keeps the last
+        let started = self.play(Voice::Ambient, Stream::open(entry.source), looped, gain, fade_ms);
+        if started {
+            self.sound = Some(sound);
+        }
+        started
+    }
+
+    // A stream that cannot be played is dropped, the voice then stays silent
+    fn play(&mut self, voice: Voice, stream: Result<Stream, impl Into<Error>>, looped: bool, gain: u32, fade_ms: u32) -> bool {
+        let stream = stream.map_err(Into::<Error>::into);
+        #[cfg(feature = "52833-debug")]
+        if let Err(error) = &stream {
+            rprintln!("Cannot play on {:?}: {:?}", voice, error);
+        }
+        let Ok(stream) = stream else {
+            return false;
+        };
+        self.mixer.play(voice, stream, looped, gain, fade_ms);
+        if !self.playing {
+            self.start_dma();
+        }
+        true
+    }
 
-    *cx.local.i2s = Some(new_i2s);
-    cx.local.dma_buf = dma_buf;
-    play_next_audio_segment::spawn().ok();
+    fn start_dma(&mut self) {
+        self.ending = false;
+        self.buffers[0].fill(0);
+        // Buffer 0 is latched at the start, the first TXPTRUPD asks for buffer 1 right away
+        self.latched = 1;
+        unsafe {
+            self.i2s.set_buffersize(BUFFER_LEN as u32).unwrap();
+        }
+        self.i2s.set_tx_ptr(self.buffers[0].as_ptr() as u32).unwrap();
+        self.i2s.reset_event(I2SEvent::TxPtrUpdated);
+        self.i2s.enable_interrupt(I2SEvent::TxPtrUpdated);
+        self.i2s.start();
+        self.playing = true;
+    }
+
+    // Returns false once the last sound has been sent completely
+    fn refill(&mut self, gain: u32) -> bool {
+        self.i2s.reset_event(I2SEvent::TxPtrUpdated);
+        if self.ending {
+            return false;
+        }
+        self.latched ^= 1;
+        let free = self.latched ^ 1;
+        // The buffer being sent holds the end of the last voice, this one only silence
+        self.ending = !self.mixer.is_active();
+        self.mixer.fill(gain, &mut self.buffers[free]);
+        self.i2s.set_tx_ptr(self.buffers[free].as_ptr() as u32).unwrap();
+        true
+    }
+
+    fn stop(&mut self) {
+        self.i2s.disable_interrupt(I2SEvent::TxPtrUpdated);
+        self.i2s.reset_event(I2SEvent::TxPtrUpdated);
+        if self.playing {
+            self.i2s.stop();
+            self.i2s.event_stopped().reset();
+            // Disabling the peripheral stops MCK as well
+            self.i2s.disable();
+            self.playing = false;
+        }
+    }
 }
@@ -1,9 +1,30 @@
 #![allow(dead_code)]
 
+use {
+    crate::{
+        alarm::{self, Alarm, Stage, ALARM_SLOTS, ALARM_STAGES},
+        format::{self, Format},
+        night_light,
+        outputs::{Load, MAX_DUTY},
+        sound,
+        thermostat,
+        time::*,
+        volume,
+    },
+    heapless::Vec,
+};
+
+// Enough for the longest transition, going to backup battery
+pub const MAX_ACTIONS: usize = 16;
+
+pub type Actions = Vec<Action, MAX_ACTIONS>;
+
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum State {
     Idle,
+    Sunrise,
     Alarm,
+    Snoozed,
     Settings(Settings),
     BackupBattery,
 }
@@ -12,8 +33,11 @@ pub enum State {
 pub enum Settings {
     ClockHours,
     ClockMinutes,
+    AlarmSlot,
     AlarmHours,
     AlarmMinutes,
+    AlarmWeekdays,
+    AlarmSound,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -23,12 +47,16 @@ pub enum Event {
     Timer(TimerEvent),
     VBUSDisconnected,
     VBUSConnected,
+    NightLight(u8), // From the CLI, brightness in percent, 0 switches it off
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum TimerEvent {
-    PeriodicUpdate(u32), // Event contains RTC counter value
-    AlarmTriggered,
+    PeriodicUpdate(DateTime), // Event contains the current date and time
+    SunriseTriggered(usize), // Event contains the alarm slot
+    AlarmTriggered(usize),   // Event contains the alarm slot
+    AlarmArmed(Option<(usize, u32)>), // Slot and ticks until it rings, None without an alarm
+    SnoozeOver, // The snoozed alarm rings again
     Timeout, // General timeout used for timing out settings/alarm
     Blink,   // Used for blinking the alarm and settings display
 }
@@ -38,6 +66,486 @@ pub enum EncoderEvent {
     Rotated(isize),
     ShortPressed,
     LongPressed,
+    Held, // Pressed for a few seconds, a long press outside of Idle
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Section {
+    Hour,
+    Minute,
+    Display,
+    AlarmIcon(usize),            // Ringing alarm slot
+    SnoozeIcon(usize),           // Snoozed alarm slot
+    AlarmSlot(usize, Alarm),     // Alarm settings, the slot is blinking
+    AlarmHour(usize, Alarm),     // Alarm settings, the hour is blinking
+    AlarmMinute(usize, Alarm),   // Alarm settings, the minute is blinking
+    AlarmWeekdays(usize, Alarm), // Alarm settings, the weekdays are blinking
+    AlarmSound(usize, Alarm),    // Alarm settings, the sound name is blinking
+    Volume(u8),                  // Volume bar below the time
+    Brightness(u8),              // Night light brightness bar below the time
+}
+
+// Side effects of a transition, executed by the RTIC app
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    SetTime(u32), // Ticks since midnight, the date is kept
+    SetAlarm(usize, Alarm),
+    ArmNextAlarm,
+    DisableAlarm,
+    SetSnooze(u32), // Ticks until the snoozed alarm rings again
+    DisableSnooze,
+    SetPeriodicUpdate(u32),
+    DisablePeriodicUpdate,
+    SetTimeout(u32),
+    DisableTimeout,
+    SetBlinking(u32),
+    DisableBlinking,
+    ReadTemperature,
+    StartSunrise(u32), // Minutes until the LED is at full brightness
+    LoadPwmSequence(u8, u8), // Haptic pattern and intensity in percent
+    StartPwm,
+    StopPwm,
+    StartAudio(u8, u32), // Sound and gain in percent, keeps playing a sound already started
+    StopAudio,
+    SetVolume(u8),
+    SetOutput(Load, u8), // Duty cycle in percent, 0 switches the load off
+    StopThermostat, // Clears the fan speed of the thermostat until the next temperature reading
+    SetNightLight(u8), // Brightness in percent, 0 switches it off
+    SetNightLightBrightness(u8), // Stored as the brightness it is switched on at
+    UpdateDisplay(DateTime, Section, bool), // Time to show, section and whether it blinks
+    EnableRotary,
+    DisableRotary,
+    EnableDisplay,
+    DisableDisplay,
+}
+
+// User settings the transitions depend on, copied from the shared resources for every event
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub alarms: [Alarm; ALARM_SLOTS],
+    pub stages: [Stage; ALARM_STAGES],
+    pub snooze_minutes: u32,
+    pub snooze_limit: u32, // Number of snoozes before a press dismisses the alarm
+    pub sunrise_minutes: u32,
+    pub settings_timeout_minutes: u32,
+    pub alarm_timeout_minutes: u32,
+    pub volume: u8,
+    pub thermostat: thermostat::Settings,
+    pub night_light: night_light::Settings,
+    pub format: Format,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            alarms: [Alarm::DISABLED; ALARM_SLOTS],
+            stages: alarm::DEFAULT_STAGES,
+            snooze_minutes: alarm::DEFAULT_SNOOZE_MINUTES,
+            snooze_limit: alarm::DEFAULT_SNOOZE_LIMIT,
+            sunrise_minutes: alarm::DEFAULT_SUNRISE_MINUTES,
+            settings_timeout_minutes: DEFAULT_SETTINGS_TIMEOUT_MINUTES,
+            alarm_timeout_minutes: DEFAULT_ALARM_TIMEOUT_MINUTES,
+            volume: volume::DEFAULT_VOLUME,
+            thermostat: thermostat::DEFAULT_SETTINGS,
+            night_light: night_light::DEFAULT_SETTINGS,
+            format: format::DEFAULT_FORMAT,
+        }
+    }
+}
+
+// The clock logic, the state plus what is remembered between events
+#[derive(Clone, Copy, Debug)]
+pub struct Clock {
+    pub state: State,
+    pub now: DateTime,
+    temp_ticks: u32, // Time of day being edited
+    temp_slot: usize,
+    temp_alarm: Alarm,
+    ringing_slot: usize,
+    sunrise_slot: usize,
+    snooze_count: u32,
+    alarm_minutes: u32,
+    alarm_stage: usize,
+    light: u8, // Night light brightness, 0 is off
+    light_minutes: u32,
+    light_auto_off: bool, // Switched on by hand rather than by the schedule
+    light_scheduled: bool, // Within the schedule at the last update
+}
+
+impl Clock {
+    pub const fn new() -> Clock {
+        Clock {
+            state: State::Idle,
+            now: DateTime::new(0, 0),
+            temp_ticks: 0,
+            temp_slot: 0,
+            temp_alarm: Alarm::DISABLED,
+            ringing_slot: 0,
+            sunrise_slot: 0,
+            snooze_count: 0,
+            alarm_minutes: 0,
+            alarm_stage: 0,
+            light: 0,
+            light_minutes: 0,
+            light_auto_off: false,
+            light_scheduled: false,
+        }
+    }
+
+    // Handles one event, returns the new state and the actions to execute
+    pub fn next(&mut self, event: Event, config: &Config) -> (State, Actions) {
+        let state = self.state;
+        let mut actions = Actions::new();
+
+        // Short press snoozes until the snooze limit is reached, after that it dismisses.
+        // Holding the knob only toggles the night light when idle. A sunrise that no longer
+        // leads up to the armed alarm is as good as without an alarm.
+        let event = match (state, event) {
+            (State::Sunrise, Event::Timer(TimerEvent::AlarmArmed(Some((slot, ticks)))))
+                if slot != self.sunrise_slot || ticks > config.sunrise_minutes * TICKS_PER_MINUTE =>
+            {
+                Event::Timer(TimerEvent::AlarmArmed(None))
+            }
+            (State::Alarm, Event::Encoder(EncoderEvent::ShortPressed))
+                if self.snooze_count >= config.snooze_limit =>
+            {
+                Event::Encoder(EncoderEvent::LongPressed)
+            }
+            (State::Idle, Event::Encoder(EncoderEvent::Held)) => event,
+            (_, Event::Encoder(EncoderEvent::Held)) => Event::Encoder(EncoderEvent::LongPressed),
+            _ => event,
+        };
+        self.state = state.next(event);
+
+        match event {
+            Event::Timer(TimerEvent::PeriodicUpdate(now)) => {
+                self.now = now;
+                push(&mut actions, Action::ReadTemperature);
+                push(&mut actions, Action::SetPeriodicUpdate(TICKS_PER_MINUTE));
+                self.update_night_light(&mut actions, state, config);
+
+                match state {
+                    State::Idle | State::Sunrise => {
+                        push(&mut actions, Action::UpdateDisplay(now, Section::Display, false));
+                    }
+                    State::Snoozed => {
+                        push(&mut actions, Action::UpdateDisplay(now, Section::SnoozeIcon(self.ringing_slot), false));
+                    }
+                    State::Alarm => {
+                        // Escalate once the current stage has run its time
+                        self.alarm_minutes += 1;
+                        let stage = alarm::current_stage(&config.stages, self.alarm_minutes);
+                        if stage != self.alarm_stage {
+                            self.alarm_stage = stage;
+                            apply_alarm_stage(&mut actions, &config.stages[stage], &config.alarms[self.ringing_slot]);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Timer(TimerEvent::SunriseTriggered(slot)) => {
+                if let State::Idle | State::Sunrise = state {
+                    // The sunrise takes over the LED from the night light
+                    self.sunrise_slot = slot;
+                    self.light = 0;
+                    push(&mut actions, Action::StartSunrise(config.sunrise_minutes));
+                }
+            }
+            // The alarm was changed or removed during the sunrise
+            Event::Timer(TimerEvent::AlarmArmed(None)) if state == State::Sunrise => {
+                push(&mut actions, Action::StopPwm);
+            }
+            Event::Timer(TimerEvent::AlarmTriggered(slot)) => {
+                // Arm the next alarm in the table, possibly this slot on another day
+                push(&mut actions, Action::ArmNextAlarm);
+                match state {
+                    // Another alarm during the snooze takes its place
+                    State::Idle | State::Sunrise | State::Snoozed => {
+                        if state == State::Snoozed {
+                            push(&mut actions, Action::DisableSnooze);
+                        }
+                        self.ringing_slot = slot;
+                        self.snooze_count = 0;
+                        self.light = 0;
+                        self.start_alarm(&mut actions, config);
+                        push(&mut actions, Action::UpdateDisplay(self.now, Section::AlarmIcon(slot), false));
+                    }
+                    _ => {}
+                }
+            }
+            Event::Timer(TimerEvent::SnoozeOver) if state == State::Snoozed => {
+                self.start_alarm(&mut actions, config);
+                push(&mut actions, Action::UpdateDisplay(self.now, Section::AlarmIcon(self.ringing_slot), false));
+            }
+            Event::Timer(TimerEvent::Timeout) => match state {
+                State::Settings(settings) => {
+                    if settings == Settings::AlarmSound {
+                        stop_preview(&mut actions);
+                    }
+                    push(&mut actions, Action::DisableBlinking);
+                    push(&mut actions, Action::ArmNextAlarm);
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                }
+                State::Alarm => {
+                    disable_alarm_components(&mut actions);
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                }
+                // The volume bar is hidden again
+                State::Idle => push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false)),
+                _ => {}
+            },
+            Event::Timer(TimerEvent::Blink) => {
+                let section = match state {
+                    State::Alarm => Some((self.now, Section::AlarmIcon(self.ringing_slot))),
+                    State::Settings(settings) => Some((self.temp(), match settings {
+                        Settings::ClockHours => Section::Hour,
+                        Settings::ClockMinutes => Section::Minute,
+                        Settings::AlarmSlot => Section::AlarmSlot(self.temp_slot, self.temp_alarm),
+                        Settings::AlarmHours => Section::AlarmHour(self.temp_slot, self.temp_alarm),
+                        Settings::AlarmMinutes => Section::AlarmMinute(self.temp_slot, self.temp_alarm),
+                        Settings::AlarmWeekdays => Section::AlarmWeekdays(self.temp_slot, self.temp_alarm),
+                        Settings::AlarmSound => Section::AlarmSound(self.temp_slot, self.temp_alarm),
+                    })),
+                    _ => None,
+                };
+                if let Some((ticks, section)) = section {
+                    push(&mut actions, Action::UpdateDisplay(ticks, section, true));
+                    push(&mut actions, Action::SetBlinking(BLINK_TICKS));
+                }
+            }
+            Event::Encoder(EncoderEvent::ShortPressed) => match state {
+                // The alarm still rings at its time
+                State::Sunrise => push(&mut actions, Action::StopPwm),
+                State::Idle => {
+                    let alarm = config.alarms[self.temp_slot];
+                    self.temp_alarm = alarm;
+                    self.temp_ticks = alarm.ticks;
+
+                    push(&mut actions, Action::DisableAlarm);
+                    push(&mut actions, Action::SetTimeout(config.settings_timeout_minutes * TICKS_PER_MINUTE));
+                    push(&mut actions, Action::SetBlinking(BLINK_TICKS));
+                    push(&mut actions, Action::UpdateDisplay(self.temp(), Section::AlarmSlot(self.temp_slot, alarm), false));
+                }
+                State::Alarm => {
+                    self.snooze_count += 1;
+                    disable_alarm_components(&mut actions);
+                    push(&mut actions, Action::SetSnooze(config.snooze_minutes * TICKS_PER_MINUTE));
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::SnoozeIcon(self.ringing_slot), false));
+                }
+                State::Settings(settings) => match settings {
+                    Settings::ClockMinutes => {
+                        self.now = self.temp();
+                        push(&mut actions, Action::DisableBlinking);
+                        push(&mut actions, Action::SetTime(self.temp_ticks));
+                        push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                        push(&mut actions, Action::SetPeriodicUpdate(TICKS_PER_MINUTE));
+                    }
+                    Settings::AlarmMinutes => {
+                        self.temp_alarm.ticks = self.temp_ticks;
+                    }
+                    Settings::AlarmWeekdays => {
+                        // Play the sound of the alarm while it is being chosen
+                        push(&mut actions, Action::SetOutput(Load::Amp, MAX_DUTY));
+                        push(&mut actions, Action::StartAudio(self.temp_alarm.sound, sound::PREVIEW_GAIN));
+                    }
+                    Settings::AlarmSound => {
+                        stop_preview(&mut actions);
+                        push(&mut actions, Action::SetAlarm(self.temp_slot, self.temp_alarm));
+                        push(&mut actions, Action::DisableBlinking);
+                        push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            },
+            Event::Encoder(EncoderEvent::LongPressed) => match state {
+                State::Sunrise => push(&mut actions, Action::StopPwm),
+                State::Idle => {
+                    self.temp_ticks = self.now.ticks;
+
+                    push(&mut actions, Action::DisablePeriodicUpdate);
+                    push(&mut actions, Action::DisableAlarm);
+                    push(&mut actions, Action::SetBlinking(BLINK_TICKS));
+                    push(&mut actions, Action::SetTimeout(config.settings_timeout_minutes * TICKS_PER_MINUTE));
+                }
+                State::Alarm => {
+                    disable_alarm_components(&mut actions);
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                }
+                State::Snoozed => {
+                    push(&mut actions, Action::DisableSnooze);
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+                }
+                _ => {}
+            },
+            Event::Encoder(EncoderEvent::Held) => match self.light {
+                0 => self.switch_light(&mut actions, config.night_light.brightness, true),
+                _ => self.switch_light(&mut actions, 0, false),
+            },
+            Event::NightLight(brightness) if state == State::Idle => {
+                self.switch_light(&mut actions, brightness.min(night_light::MAX_BRIGHTNESS), true);
+            }
+            Event::Encoder(EncoderEvent::Rotated(direction)) => {
+                // The knob dims the night light while it is on
+                if state == State::Idle && self.light > 0 {
+                    let brightness = night_light::step(self.light, direction);
+                    self.light = brightness;
+                    self.light_minutes = 0;
+                    push(&mut actions, Action::SetNightLight(brightness));
+                    push(&mut actions, Action::SetNightLightBrightness(brightness));
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Brightness(brightness), false));
+                    push(&mut actions, Action::SetTimeout(VOLUME_BAR_TICKS));
+                } else if state == State::Idle {
+                    let volume = volume::step(config.volume, direction);
+                    push(&mut actions, Action::SetVolume(volume));
+                    push(&mut actions, Action::UpdateDisplay(self.now, Section::Volume(volume), false));
+                    push(&mut actions, Action::SetTimeout(VOLUME_BAR_TICKS));
+                }
+                if let State::Settings(settings) = state {
+                    match settings {
+                        Settings::ClockHours | Settings::AlarmHours => {
+                            let diff = direction * TICKS_PER_HOUR as isize;
+                            self.temp_ticks = add_ticks_within_day(self.temp_ticks, diff);
+                        }
+                        Settings::ClockMinutes | Settings::AlarmMinutes => {
+                            let diff = direction * TICKS_PER_MINUTE as isize;
+                            self.temp_ticks = add_ticks_within_day(self.temp_ticks, diff);
+                        }
+                        Settings::AlarmSlot => {
+                            let slot = (self.temp_slot as isize + direction).rem_euclid(ALARM_SLOTS as isize) as usize;
+                            let alarm = config.alarms[slot];
+                            self.temp_slot = slot;
+                            self.temp_alarm = alarm;
+                            self.temp_ticks = alarm.ticks;
+                        }
+                        Settings::AlarmWeekdays => {
+                            let presets = alarm::WEEKDAY_PRESETS.len() as isize;
+                            let index = (self.temp_alarm.preset_index() as isize + direction).rem_euclid(presets);
+                            self.temp_alarm = self.temp_alarm.with_preset(index as usize);
+                        }
+                        Settings::AlarmSound => {
+                            self.temp_alarm.sound = sound::step(self.temp_alarm.sound, direction);
+                            push(&mut actions, Action::StartAudio(self.temp_alarm.sound, sound::PREVIEW_GAIN));
+                        }
+                    }
+                }
+            }
+            // Just in case, only act on an actual change of the power source
+            Event::VBUSConnected if state == State::BackupBattery => {
+                push(&mut actions, Action::ArmNextAlarm);
+                push(&mut actions, Action::SetPeriodicUpdate(TICKS_PER_MINUTE));
+                push(&mut actions, Action::EnableRotary);
+                push(&mut actions, Action::EnableDisplay);
+                push(&mut actions, Action::UpdateDisplay(self.now, Section::Display, false));
+            }
+            Event::VBUSDisconnected if state != State::BackupBattery => {
+                push(&mut actions, Action::DisableRotary);
+                push(&mut actions, Action::DisableAlarm);
+                push(&mut actions, Action::DisablePeriodicUpdate);
+                push(&mut actions, Action::DisableTimeout);
+                push(&mut actions, Action::DisableDisplay);
+                disable_alarm_components(&mut actions);
+                // Readings stop without VBUS, the fan would keep the thermostat speed
+                push(&mut actions, Action::StopThermostat);
+                self.light = 0;
+            }
+            _ => {}
+        }
+
+        (self.state, actions)
+    }
+
+    // The time being edited on today's date
+    fn temp(&self) -> DateTime {
+        DateTime::new(self.now.days, self.temp_ticks)
+    }
+
+    // Follows the schedule and switches off a light switched on by hand after a while, the
+    // schedule only switches the light when idle
+    fn update_night_light(&mut self, actions: &mut Actions, state: State, config: &Config) {
+        let schedule = config.night_light.schedule;
+        let scheduled = schedule.window.contains(self.now.ticks);
+        if scheduled != self.light_scheduled {
+            self.light_scheduled = scheduled;
+            if state == State::Idle {
+                let brightness = match scheduled {
+                    true => schedule.brightness,
+                    false => 0,
+                };
+                self.switch_light(actions, brightness, false);
+            }
+        } else if self.light > 0 && self.light_auto_off {
+            self.light_minutes += 1;
+            let minutes = config.night_light.auto_off_minutes;
+            if minutes > 0 && self.light_minutes >= minutes {
+                self.switch_light(actions, 0, false);
+            }
+        }
+    }
+
+    fn switch_light(&mut self, actions: &mut Actions, brightness: u8, auto_off: bool) {
+        self.light = brightness;
+        self.light_minutes = 0;
+        self.light_auto_off = auto_off;
+        push(actions, Action::SetNightLight(brightness));
+    }
+
+    // Starts ringing from the first stage
+    fn start_alarm(&mut self, actions: &mut Actions, config: &Config) {
+        let stage = alarm::current_stage(&config.stages, 0);
+        self.alarm_minutes = 0;
+        self.alarm_stage = stage;
+        apply_alarm_stage(actions, &config.stages[stage], &config.alarms[self.ringing_slot]);
+        push(actions, Action::SetTimeout(config.alarm_timeout_minutes * TICKS_PER_MINUTE));
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+fn apply_alarm_stage(actions: &mut Actions, stage: &Stage, alarm: &Alarm) {
+    // The fan blows as hard as the stage is intense, the amp and the humidifier are just on
+    let duty = |actuator, percent| match stage.has(actuator) {
+        true => percent,
+        false => 0,
+    };
+    push(actions, Action::SetOutput(Load::Amp, duty(alarm::SOUND, MAX_DUTY)));
+    push(actions, Action::SetOutput(Load::Fan, duty(alarm::FAN, stage.intensity)));
+    push(actions, Action::SetOutput(Load::Humidifier, duty(alarm::HUMIDIFIER, MAX_DUTY)));
+    // The sunrise replaces the alarm sequence, load it before starting
+    let haptic_intensity = match stage.has(alarm::HAPTIC) {
+        true => stage.intensity,
+        false => 0,
+    };
+    push(actions, Action::LoadPwmSequence(alarm.haptic, haptic_intensity));
+    push(actions, Action::StartPwm);
+    match stage.has(alarm::SOUND) {
+        true => push(actions, Action::StartAudio(alarm.sound, stage.intensity as u32)),
+        false => push(actions, Action::StopAudio),
+    }
+}
+
+fn disable_alarm_components(actions: &mut Actions) {
+    push(actions, Action::StopAudio);
+    push(actions, Action::SetOutput(Load::Amp, 0));
+    push(actions, Action::SetOutput(Load::Fan, 0));
+    push(actions, Action::SetOutput(Load::Humidifier, 0));
+    push(actions, Action::StopPwm);
+    push(actions, Action::DisableBlinking);
+    push(actions, Action::DisableTimeout);
+}
+
+fn stop_preview(actions: &mut Actions) {
+    push(actions, Action::StopAudio);
+    push(actions, Action::SetOutput(Load::Amp, 0));
+}
+
+fn push(actions: &mut Actions, action: Action) {
+    // MAX_ACTIONS covers every transition, running out is a bug
+    actions.push(action).unwrap();
 }
 
 pub trait StateMachine {
@@ -48,38 +556,62 @@ impl StateMachine for State {
     fn next(&self, event: Event) -> State {
         match self {
             State::Idle => match event {
-                Event::Encoder(EncoderEvent::ShortPressed) => State::Settings(Settings::AlarmHours),
+                Event::Encoder(EncoderEvent::ShortPressed) => State::Settings(Settings::AlarmSlot),
                 Event::Encoder(EncoderEvent::LongPressed) => State::Settings(Settings::ClockHours),
                 Event::VBUSDisconnected => State::BackupBattery,
-                Event::Timer(TimerEvent::AlarmTriggered) => State::Alarm,
+                Event::Timer(TimerEvent::SunriseTriggered(_)) => State::Sunrise,
+                Event::Timer(TimerEvent::AlarmTriggered(_)) => State::Alarm,
                 _ => State::Idle,
             },
 
+            State::Sunrise => match event {
+                Event::Encoder(EncoderEvent::ShortPressed | EncoderEvent::LongPressed) => State::Idle,
+                Event::VBUSDisconnected => State::BackupBattery,
+                Event::Timer(TimerEvent::AlarmArmed(None)) => State::Idle,
+                Event::Timer(TimerEvent::AlarmTriggered(_)) => State::Alarm,
+                _ => State::Sunrise,
+            },
+
             State::Alarm => match event {
                 Event::Encoder(encoder_event) => match encoder_event {
-                    EncoderEvent::ShortPressed => State::Idle,
+                    EncoderEvent::ShortPressed => State::Snoozed,
                     EncoderEvent::LongPressed => State::Idle,
                     _ => State::Alarm,
                 },
                 Event::Timer(TimerEvent::PeriodicUpdate(_)) => State::Alarm,
                 Event::Timer(TimerEvent::Timeout) => State::Idle,
+                Event::VBUSDisconnected => State::BackupBattery,
                 _ => State::Alarm,
             },
 
+            State::Snoozed => match event {
+                Event::Encoder(EncoderEvent::LongPressed) => State::Idle,
+                Event::Timer(TimerEvent::SnoozeOver | TimerEvent::AlarmTriggered(_)) => State::Alarm,
+                Event::VBUSDisconnected => State::BackupBattery,
+                _ => State::Snoozed,
+            },
+
             State::Settings(settings) => match event {
                 Event::Encoder(EncoderEvent::Rotated(_)) => match settings {
                     Settings::ClockHours => State::Settings(Settings::ClockHours),
                     Settings::ClockMinutes => State::Settings(Settings::ClockMinutes),
+                    Settings::AlarmSlot => State::Settings(Settings::AlarmSlot),
                     Settings::AlarmHours => State::Settings(Settings::AlarmHours),
                     Settings::AlarmMinutes => State::Settings(Settings::AlarmMinutes),
+                    Settings::AlarmWeekdays => State::Settings(Settings::AlarmWeekdays),
+                    Settings::AlarmSound => State::Settings(Settings::AlarmSound),
                 },
                 Event::Encoder(EncoderEvent::ShortPressed) => match settings {
                     Settings::ClockHours => State::Settings(Settings::ClockMinutes),
                     Settings::ClockMinutes => State::Idle,
+                    Settings::AlarmSlot => State::Settings(Settings::AlarmHours),
                     Settings::AlarmHours => State::Settings(Settings::AlarmMinutes),
-                    Settings::AlarmMinutes => State::Idle,
+                    Settings::AlarmMinutes => State::Settings(Settings::AlarmWeekdays),
+                    Settings::AlarmWeekdays => State::Settings(Settings::AlarmSound),
+                    Settings::AlarmSound => State::Idle,
                 },
                 Event::Timer(TimerEvent::Timeout) => State::Idle,
+                Event::VBUSDisconnected => State::BackupBattery,
                 _ => State::Settings(*settings),
             },
 
@@ -89,4 +621,509 @@ impl StateMachine for State {
             },
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::haptic;
+
+    const SHORT: Event = Event::Encoder(EncoderEvent::ShortPressed);
+    const LONG: Event = Event::Encoder(EncoderEvent::LongPressed);
+
+    fn rotate(steps: isize) -> Event {
+        Event::Encoder(EncoderEvent::Rotated(steps))
+    }
+
+    fn minute(ticks: u32) -> Event {
+        Event::Timer(TimerEvent::PeriodicUpdate(DateTime::new(0, ticks)))
+    }
+
+    fn ringing(config: &Config) -> Clock {
+        let mut clock = Clock::new();
+        clock.next(Event::Timer(TimerEvent::AlarmTriggered(0)), config);
+        clock
+    }
+
+    #[test]
+    fn periodic_update_refreshes_display() {
+        let mut clock = Clock::new();
+        let (state, actions) = clock.next(minute(time_to_ticks(7, 15)), &Config::default());
+
+        assert_eq!(state, State::Idle);
+        assert_eq!(clock.now, DateTime::new(0, time_to_ticks(7, 15)));
+        assert!(actions.contains(&Action::SetPeriodicUpdate(TICKS_PER_MINUTE)));
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, time_to_ticks(7, 15)), Section::Display, false)));
+    }
+
+    #[test]
+    fn set_clock_with_encoder() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        clock.next(minute(time_to_ticks(6, 20)), &config);
+
+        assert_eq!(clock.next(LONG, &config).0, State::Settings(Settings::ClockHours));
+        clock.next(rotate(-7), &config); // Wraps around midnight
+        assert_eq!(clock.next(SHORT, &config).0, State::Settings(Settings::ClockMinutes));
+        clock.next(rotate(30), &config);
+        let (state, actions) = clock.next(SHORT, &config);
+
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::SetTime(time_to_ticks(23, 50))));
+        assert_eq!(clock.now, DateTime::new(0, time_to_ticks(23, 50)));
+    }
+
+    #[test]
+    fn set_alarm_with_encoder() {
+        let mut config = Config::default();
+        config.alarms[1] = Alarm::new(time_to_ticks(6, 30), alarm::WEEKDAYS);
+        let mut clock = Clock::new();
+
+        let (state, actions) = clock.next(SHORT, &config);
+        assert_eq!(state, State::Settings(Settings::AlarmSlot));
+        assert!(actions.contains(&Action::DisableAlarm));
+        clock.next(rotate(1), &config);
+        clock.next(SHORT, &config);
+        clock.next(rotate(1), &config);
+        clock.next(SHORT, &config);
+        clock.next(rotate(-31), &config);
+        clock.next(SHORT, &config);
+        clock.next(rotate(1), &config); // WEEKDAYS -> WEEKENDS
+        clock.next(SHORT, &config);
+        clock.next(rotate(1), &config);
+        let (state, actions) = clock.next(SHORT, &config);
+
+        assert_eq!(state, State::Idle);
+        let expected = Alarm { sound: 1, ..Alarm::new(time_to_ticks(6, 59), alarm::WEEKENDS) };
+        assert!(actions.contains(&Action::SetAlarm(1, expected)));
+    }
+
+    #[test]
+    fn alarm_settings_time_out() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        clock.next(SHORT, &config);
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::Timeout), &config);
+
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::ArmNextAlarm));
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetAlarm(..))));
+    }
+
+    #[test]
+    fn blink_shows_the_edited_section() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        clock.next(LONG, &config);
+        let (_, actions) = clock.next(Event::Timer(TimerEvent::Blink), &config);
+
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::Hour, true)));
+        assert!(actions.contains(&Action::SetBlinking(BLINK_TICKS)));
+    }
+
+    #[test]
+    fn sunrise_then_alarm() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::SunriseTriggered(2)), &config);
+        assert_eq!(state, State::Sunrise);
+        assert!(actions.contains(&Action::StartSunrise(config.sunrise_minutes)));
+
+        // The alarm is armed again, e.g. for another alarm that is not due yet
+        let armed = Event::Timer(TimerEvent::AlarmArmed(Some((2, 5 * TICKS_PER_MINUTE))));
+        assert_eq!(clock.next(armed, &config).0, State::Sunrise);
+
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::AlarmTriggered(2)), &config);
+        assert_eq!(state, State::Alarm);
+        assert!(actions.contains(&Action::SetTimeout(DEFAULT_ALARM_TIMEOUT_MINUTES * TICKS_PER_MINUTE)));
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::AlarmIcon(2), false)));
+    }
+
+    fn sunrise(config: &Config) -> Clock {
+        let mut clock = Clock::new();
+        clock.next(Event::Timer(TimerEvent::SunriseTriggered(2)), config);
+        clock
+    }
+
+    #[test]
+    fn press_stops_the_sunrise() {
+        let config = Config::default();
+        for press in [SHORT, LONG, Event::Encoder(EncoderEvent::Held)] {
+            let (state, actions) = sunrise(&config).next(press, &config);
+            assert_eq!(state, State::Idle);
+            assert!(actions.contains(&Action::StopPwm));
+        }
+    }
+
+    #[test]
+    fn sunrise_stops_when_the_alarm_changes() {
+        let config = Config::default();
+        let removed = Event::Timer(TimerEvent::AlarmArmed(None));
+        let later = Event::Timer(TimerEvent::AlarmArmed(Some((2, (config.sunrise_minutes + 1) * TICKS_PER_MINUTE))));
+        let other = Event::Timer(TimerEvent::AlarmArmed(Some((3, TICKS_PER_MINUTE))));
+        for armed in [removed, later, other] {
+            let (state, actions) = sunrise(&config).next(armed, &config);
+            assert_eq!(state, State::Idle);
+            assert!(actions.contains(&Action::StopPwm));
+        }
+    }
+
+    #[test]
+    fn vbus_disconnect_during_sunrise() {
+        let config = Config::default();
+        let mut clock = sunrise(&config);
+        let (state, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+        assert!(actions.contains(&Action::StopPwm));
+        assert!(actions.contains(&Action::DisableAlarm));
+
+        let (state, actions) = clock.next(Event::VBUSConnected, &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::EnableDisplay));
+    }
+
+    #[test]
+    fn alarm_starts_with_first_stage() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        let (_, actions) = clock.next(Event::Timer(TimerEvent::AlarmTriggered(0)), &config);
+
+        assert!(actions.contains(&Action::LoadPwmSequence(haptic::DEFAULT_PATTERN, 30)));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, 0)));
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, 0)));
+        assert!(actions.contains(&Action::StopAudio));
+    }
+
+    #[test]
+    fn alarm_escalates_through_stages() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+
+        let (_, actions) = clock.next(minute(0), &config);
+        assert!(actions.contains(&Action::StartAudio(sound::DEFAULT_SOUND, 60)));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, MAX_DUTY)));
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, 0)));
+
+        let (_, actions) = clock.next(minute(0), &config);
+        assert!(!actions.iter().any(|action| matches!(action, Action::StartAudio(..))));
+
+        let (state, actions) = clock.next(minute(0), &config);
+        assert_eq!(state, State::Alarm);
+        assert!(actions.contains(&Action::StartAudio(sound::DEFAULT_SOUND, 100)));
+        assert!(actions.contains(&Action::LoadPwmSequence(haptic::DEFAULT_PATTERN, 100)));
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, 100)));
+        assert!(actions.contains(&Action::SetOutput(Load::Humidifier, MAX_DUTY)));
+    }
+
+    #[test]
+    fn fan_runs_at_the_stage_intensity() {
+        let mut config = Config::default();
+        config.stages[0].actuators = alarm::FAN;
+        let (_, actions) = Clock::new().next(Event::Timer(TimerEvent::AlarmTriggered(0)), &config);
+
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, config.stages[0].intensity)));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, 0)));
+        assert!(actions.contains(&Action::SetOutput(Load::Humidifier, 0)));
+    }
+
+    #[test]
+    fn alarm_plays_the_sound_of_its_slot() {
+        let mut config = Config::default();
+        config.alarms[0].sound = 2;
+        config.stages[0].actuators = alarm::SOUND;
+        let (_, actions) = Clock::new().next(Event::Timer(TimerEvent::AlarmTriggered(0)), &config);
+
+        assert!(actions.contains(&Action::StartAudio(2, config.stages[0].intensity as u32)));
+    }
+
+    #[test]
+    fn alarm_plays_the_haptic_pattern_of_its_slot() {
+        let mut config = Config::default();
+        config.alarms[1].haptic = 2;
+        let (_, actions) = Clock::new().next(Event::Timer(TimerEvent::AlarmTriggered(1)), &config);
+
+        assert!(actions.contains(&Action::LoadPwmSequence(2, config.stages[0].intensity)));
+        assert!(actions.contains(&Action::StartPwm));
+    }
+
+    #[test]
+    fn sound_menu_previews_the_choice() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        for _ in 0..4 {
+            clock.next(SHORT, &config); // Slot, hours, minutes, weekdays
+        }
+        let (state, actions) = clock.next(SHORT, &config);
+        assert_eq!(state, State::Settings(Settings::AlarmSound));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, MAX_DUTY)));
+        assert!(actions.contains(&Action::StartAudio(sound::DEFAULT_SOUND, sound::PREVIEW_GAIN)));
+
+        let (_, actions) = clock.next(rotate(-1), &config);
+        let last = sound::SOUNDS.len() as u8 - 1;
+        assert!(actions.contains(&Action::StartAudio(last, sound::PREVIEW_GAIN)));
+
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::Timeout), &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::StopAudio));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, 0)));
+    }
+
+    #[test]
+    fn snooze_until_limit_then_dismiss() {
+        let config = Config {
+            snooze_limit: 2,
+            ..Default::default()
+        };
+        let mut clock = ringing(&config);
+
+        for _ in 0..2 {
+            let (state, actions) = clock.next(SHORT, &config);
+            assert_eq!(state, State::Snoozed);
+            assert!(actions.contains(&Action::SetSnooze(config.snooze_minutes * TICKS_PER_MINUTE)));
+            assert!(actions.contains(&Action::StopPwm));
+
+            let (state, _) = clock.next(Event::Timer(TimerEvent::SnoozeOver), &config);
+            assert_eq!(state, State::Alarm);
+        }
+
+        let (state, actions) = clock.next(SHORT, &config);
+        assert_eq!(state, State::Idle);
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetSnooze(..))));
+    }
+
+    #[test]
+    fn snooze_survives_rearm() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+        clock.next(SHORT, &config);
+
+        // e.g. an alarm set from the CLI during the snooze
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::AlarmArmed(Some((3, TICKS_PER_HOUR)))), &config);
+        assert_eq!(state, State::Snoozed);
+        assert!(actions.is_empty());
+
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::SnoozeOver), &config);
+        assert_eq!(state, State::Alarm);
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::AlarmIcon(0), false)));
+    }
+
+    #[test]
+    fn second_alarm_inside_the_snooze_rings() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+        clock.next(SHORT, &config);
+
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::AlarmTriggered(1)), &config);
+        assert_eq!(state, State::Alarm);
+        assert!(actions.contains(&Action::DisableSnooze));
+        assert!(actions.contains(&Action::ArmNextAlarm));
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::AlarmIcon(1), false)));
+    }
+
+    #[test]
+    fn snooze_count_resets_on_new_alarm() {
+        let config = Config {
+            snooze_limit: 1,
+            ..Default::default()
+        };
+        let mut clock = ringing(&config);
+        clock.next(SHORT, &config);
+        clock.next(LONG, &config);
+
+        clock.next(Event::Timer(TimerEvent::AlarmTriggered(1)), &config);
+        assert_eq!(clock.next(SHORT, &config).0, State::Snoozed);
+    }
+
+    #[test]
+    fn alarm_times_out() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::Timeout), &config);
+
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::StopAudio));
+        assert!(actions.contains(&Action::SetOutput(Load::Amp, 0)));
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, 0)));
+        assert!(actions.contains(&Action::SetOutput(Load::Humidifier, 0)));
+    }
+
+    #[test]
+    fn knob_sets_volume_when_idle() {
+        let config = Config { volume: 19, ..Default::default() };
+        let mut clock = Clock::new();
+
+        let (state, actions) = clock.next(rotate(3), &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::SetVolume(volume::MAX_VOLUME)));
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::Volume(volume::MAX_VOLUME), false)));
+        assert!(actions.contains(&Action::SetTimeout(VOLUME_BAR_TICKS)));
+
+        let (_, actions) = clock.next(Event::Timer(TimerEvent::Timeout), &config);
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::Display, false)));
+
+        // Turning the knob while setting the clock does not touch the volume
+        clock.next(LONG, &config);
+        let (_, actions) = clock.next(rotate(1), &config);
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetVolume(_))));
+    }
+
+    #[test]
+    fn holding_the_knob_toggles_the_night_light() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        let held = Event::Encoder(EncoderEvent::Held);
+
+        let (state, actions) = clock.next(held, &config);
+        assert_eq!(state, State::Idle);
+        assert_eq!(actions, [Action::SetNightLight(20)]);
+
+        // The knob dims the light rather than setting the volume
+        let (_, actions) = clock.next(rotate(2), &config);
+        assert!(actions.contains(&Action::SetNightLight(30)));
+        assert!(actions.contains(&Action::SetNightLightBrightness(30)));
+        assert!(actions.contains(&Action::UpdateDisplay(DateTime::new(0, 0), Section::Brightness(30), false)));
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetVolume(_))));
+
+        let (_, actions) = clock.next(held, &config);
+        assert_eq!(actions, [Action::SetNightLight(0)]);
+        let (_, actions) = clock.next(rotate(1), &config);
+        assert!(actions.iter().any(|action| matches!(action, Action::SetVolume(_))));
+    }
+
+    #[test]
+    fn night_light_goes_off_after_a_while() {
+        let config = Config {
+            night_light: night_light::Settings { auto_off_minutes: 2, ..night_light::DEFAULT_SETTINGS },
+            ..Default::default()
+        };
+        let mut clock = Clock::new();
+        clock.next(Event::NightLight(60), &config);
+        let (_, actions) = clock.next(minute(time_to_ticks(12, 1)), &config);
+        assert!(!actions.contains(&Action::SetNightLight(0)));
+        let (_, actions) = clock.next(minute(time_to_ticks(12, 2)), &config);
+        assert!(actions.contains(&Action::SetNightLight(0)));
+    }
+
+    #[test]
+    fn schedule_switches_the_night_light() {
+        let window = Window { start: time_to_ticks(22, 0), end: time_to_ticks(6, 0) };
+        let schedule = night_light::Schedule { window, brightness: 5 };
+        let config = Config {
+            night_light: night_light::Settings { auto_off_minutes: 1, schedule, ..night_light::DEFAULT_SETTINGS },
+            ..Default::default()
+        };
+        let mut clock = Clock::new();
+        let light = |actions: Actions| actions.into_iter().find(|action| matches!(action, Action::SetNightLight(_)));
+
+        assert_eq!(light(clock.next(minute(time_to_ticks(21, 59)), &config).1), None);
+        assert_eq!(light(clock.next(minute(time_to_ticks(22, 0)), &config).1), Some(Action::SetNightLight(5)));
+        // No auto-off while scheduled
+        assert_eq!(light(clock.next(minute(time_to_ticks(22, 1)), &config).1), None);
+        assert_eq!(light(clock.next(minute(time_to_ticks(22, 2)), &config).1), None);
+        assert_eq!(light(clock.next(minute(time_to_ticks(6, 0)), &config).1), Some(Action::SetNightLight(0)));
+    }
+
+    #[test]
+    fn holding_the_knob_dismisses_the_alarm() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+        let (state, actions) = clock.next(Event::Encoder(EncoderEvent::Held), &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::StopPwm));
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetNightLight(_))));
+    }
+
+    #[test]
+    fn backup_battery_round_trip() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+
+        let (state, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+        assert!(actions.contains(&Action::DisableDisplay));
+        assert!(actions.contains(&Action::DisableRotary));
+
+        // Alarms are ignored without VBUS
+        let (state, actions) = clock.next(Event::Timer(TimerEvent::AlarmTriggered(0)), &config);
+        assert_eq!(state, State::BackupBattery);
+        assert_eq!(actions.as_slice(), &[Action::ArmNextAlarm]);
+
+        let (state, actions) = clock.next(Event::VBUSConnected, &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::EnableDisplay));
+    }
+
+    #[test]
+    fn vbus_disconnect_stops_the_thermostat() {
+        let config = Config { thermostat: thermostat::Settings { enabled: true, ..thermostat::DEFAULT_SETTINGS }, ..Config::default() };
+        let mut clock = Clock::new();
+        let (_, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert!(actions.contains(&Action::StopThermostat));
+        assert!(actions.contains(&Action::SetOutput(Load::Fan, 0)));
+    }
+
+    #[test]
+    fn vbus_disconnect_while_snoozed() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+        clock.next(SHORT, &config);
+
+        let (state, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+        assert!(actions.contains(&Action::DisableAlarm));
+
+        // The alarm table is armed again, the snooze is gone
+        let (state, actions) = clock.next(Event::VBUSConnected, &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::ArmNextAlarm));
+        assert!(actions.contains(&Action::EnableRotary));
+    }
+
+    #[test]
+    fn vbus_disconnect_while_ringing() {
+        let config = Config::default();
+        let mut clock = ringing(&config);
+
+        let (state, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+        assert!(actions.contains(&Action::StopAudio));
+        assert!(actions.contains(&Action::DisableAlarm));
+
+        // The knob is off, nothing snoozes the silenced alarm
+        assert_eq!(clock.next(SHORT, &config).0, State::BackupBattery);
+        assert_eq!(clock.next(Event::VBUSConnected, &config).0, State::Idle);
+    }
+
+    #[test]
+    fn vbus_disconnect_in_settings() {
+        let config = Config::default();
+        let mut clock = Clock::new();
+        clock.next(SHORT, &config);
+        clock.next(SHORT, &config);
+        clock.next(SHORT, &config);
+        let (state, _) = clock.next(SHORT, &config);
+        assert_eq!(state, State::Settings(Settings::AlarmWeekdays));
+        // Previewing the sound
+        let (state, _) = clock.next(SHORT, &config);
+        assert_eq!(state, State::Settings(Settings::AlarmSound));
+
+        let (state, actions) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+        assert!(actions.contains(&Action::StopAudio));
+        assert!(actions.contains(&Action::DisableBlinking));
+        assert!(actions.contains(&Action::DisableTimeout));
+        assert!(!actions.iter().any(|action| matches!(action, Action::SetAlarm(..))));
+
+        // A stale blink or timeout does nothing on the battery
+        assert_eq!(clock.next(Event::Timer(TimerEvent::Timeout), &config).0, State::BackupBattery);
+        let (state, actions) = clock.next(Event::VBUSConnected, &config);
+        assert_eq!(state, State::Idle);
+        assert!(actions.contains(&Action::ArmNextAlarm));
+
+        // Setting the clock is left the same way
+        let mut clock = Clock::new();
+        clock.next(LONG, &config);
+        let (state, _) = clock.next(Event::VBUSDisconnected, &config);
+        assert_eq!(state, State::BackupBattery);
+    }
+}
@@ -0,0 +1,440 @@
+// Key/value store for the settings in a reserved flash region. Records are appended to a log
+// in the active page, so changing a value does not erase any flash. When the page is full the
+// latest value of every key is copied to the other page, which then becomes the active page.
+// Alternating the pages spreads the erases evenly over both of them.
+//
+// Page layout: magic, schema version and sequence number, followed by the records. Each
+// record is a header word (key, length, CRC-16 of key, length and value) and the value padded
+// to whole words. The header word is written first, so a record interrupted by a reset still
+// has its length and is skipped for failing the CRC.
+
+use embedded_storage::nor_flash::NorFlash;
+
+pub const PAGE_SIZE: u32 = 4096;
+pub const PAGES: u32 = 2;
+pub const MAX_VALUE_SIZE: usize = 64;
+
+const MAGIC: u32 = 0x5342_4B56; // "SBKV"
+const PAGE_HEADER_SIZE: u32 = 8;
+const WORD_SIZE: u32 = 4;
+const ERASED: u32 = 0xFFFF_FFFF;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Error<E> {
+    Flash(E),
+    TooLarge, // Value longer than MAX_VALUE_SIZE
+    Full,     // Latest values of all keys do not fit in a page
+}
+
+impl<E> From<E> for Error<E> {
+    fn from(error: E) -> Error<E> {
+        Error::Flash(error)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Record {
+    key: u8,
+    len: usize,
+    offset: u32, // Offset of the value in the flash
+}
+
+pub struct Store<F: NorFlash> {
+    flash: F,
+    schema: u16,
+    page: u32,     // Active page
+    sequence: u16, // Incremented on every compaction, the newest page wins
+    end: u32,      // Offset of the next record in the active page
+}
+
+impl<F: NorFlash> Store<F> {
+    // Mounts the store. Values written with a different schema version are discarded.
+    pub fn new(flash: F, schema: u16) -> Result<Store<F>, Error<F::Error>> {
+        let mut store = Store { flash, schema, page: 0, sequence: 0, end: PAGE_HEADER_SIZE };
+        let headers = [store.page_header(0)?, store.page_header(1)?];
+        // Both pages are valid if a reset hit a compaction before the old page was erased
+        let newest = match headers {
+            [Some(first), Some(second)] if (second.wrapping_sub(first) as i16) > 0 => Some((1, second)),
+            [Some(first), _] => Some((0, first)),
+            [None, Some(second)] => Some((1, second)),
+            [None, None] => None,
+        };
+        match newest {
+            Some((page, sequence)) => {
+                store.page = page;
+                store.sequence = sequence;
+                store.end = store.log_end()?;
+            }
+            None => {
+                store.flash.erase(0, PAGE_SIZE)?;
+                store.write_page_header(0, 0)?;
+            }
+        }
+        Ok(store)
+    }
+
+    // Copies the latest value of `key` into `buffer`, returns the length of the value. A value
+    // longer than the buffer is cut short, the caller tells from the length.
+    pub fn read(&mut self, key: u8, buffer: &mut [u8]) -> Option<usize> {
+        let record = self.find(key).ok()??;
+        let len = record.len.min(buffer.len());
+        self.flash.read(record.offset, &mut buffer[..len]).ok()?;
+        Some(record.len)
+    }
+
+    pub fn write(&mut self, key: u8, value: &[u8]) -> Result<(), Error<F::Error>> {
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(Error::TooLarge);
+        }
+        // Rewriting an unchanged value would only wear the flash
+        let mut current = [0u8; MAX_VALUE_SIZE];
+        if self.read(key, &mut current) == Some(value.len()) && &current[..value.len()] == value {
+            return Ok(());
+        }
+        if self.end + record_size(value.len()) > PAGE_SIZE {
+            self.compact()?;
+            if self.end + record_size(value.len()) > PAGE_SIZE {
+                return Err(Error::Full);
+            }
+        }
+        let offset = self.page * PAGE_SIZE + self.end;
+        self.append(offset, key, value)?;
+        self.end += record_size(value.len());
+        Ok(())
+    }
+
+    pub fn free(self) -> F {
+        self.flash
+    }
+
+    // Moves the latest values to the other page and erases the active one
+    fn compact(&mut self) -> Result<(), Error<F::Error>> {
+        let page = 1 - self.page;
+        let sequence = self.sequence.wrapping_add(1);
+        self.flash.erase(page * PAGE_SIZE, (page + 1) * PAGE_SIZE)?;
+
+        let mut end = PAGE_HEADER_SIZE;
+        let mut offset = PAGE_HEADER_SIZE;
+        while let Some((record, next)) = self.record_at(offset)? {
+            offset = next;
+            if self.is_latest(&record, next)? {
+                let mut value = [0u8; MAX_VALUE_SIZE];
+                self.flash.read(record.offset, &mut value[..record.len])?;
+                self.append(page * PAGE_SIZE + end, record.key, &value[..record.len])?;
+                end += record_size(record.len);
+            }
+        }
+        // The header makes the new page valid, only then the old one can go
+        self.write_page_header(page, sequence)?;
+        self.flash.erase(self.page * PAGE_SIZE, (self.page + 1) * PAGE_SIZE)?;
+
+        self.page = page;
+        self.sequence = sequence;
+        self.end = end;
+        Ok(())
+    }
+
+    fn append(&mut self, offset: u32, key: u8, value: &[u8]) -> Result<(), Error<F::Error>> {
+        let header = record_header(key, value);
+        self.flash.write(offset, &header.to_le_bytes())?;
+        let mut words = [0u8; MAX_VALUE_SIZE];
+        words[..value.len()].copy_from_slice(value);
+        self.flash.write(offset + WORD_SIZE, &words[..padded_len(value.len())])?;
+        Ok(())
+    }
+
+    fn find(&mut self, key: u8) -> Result<Option<Record>, Error<F::Error>> {
+        let mut found = None;
+        let mut offset = PAGE_HEADER_SIZE;
+        while let Some((record, next)) = self.record_at(offset)? {
+            if record.key == key {
+                found = Some(record);
+            }
+            offset = next;
+        }
+        Ok(found)
+    }
+
+    // Whether no record after `offset` in the active page has the same key
+    fn is_latest(&mut self, record: &Record, offset: u32) -> Result<bool, Error<F::Error>> {
+        let mut offset = offset;
+        while let Some((later, next)) = self.record_at(offset)? {
+            if later.key == record.key {
+                return Ok(false);
+            }
+            offset = next;
+        }
+        Ok(true)
+    }
+
+    // Offset after the last record, a damaged header makes the page count as full
+    fn log_end(&mut self) -> Result<u32, Error<F::Error>> {
+        let mut offset = PAGE_HEADER_SIZE;
+        while offset + WORD_SIZE <= PAGE_SIZE {
+            let header = self.word(self.page * PAGE_SIZE + offset)?;
+            let len = (header >> 8) as u8 as usize;
+            if header == ERASED {
+                return Ok(offset);
+            }
+            if len > MAX_VALUE_SIZE {
+                break;
+            }
+            offset += record_size(len);
+        }
+        Ok(PAGE_SIZE)
+    }
+
+    // Next valid record at or after `offset` in the active page and the offset following it.
+    // Records failing their CRC are skipped.
+    fn record_at(&mut self, offset: u32) -> Result<Option<(Record, u32)>, Error<F::Error>> {
+        let mut offset = offset;
+        while offset + WORD_SIZE <= PAGE_SIZE {
+            let header = self.word(self.page * PAGE_SIZE + offset)?;
+            let key = header as u8;
+            let len = (header >> 8) as u8 as usize;
+            if header == ERASED || len > MAX_VALUE_SIZE || offset + record_size(len) > PAGE_SIZE {
+                return Ok(None);
+            }
+            let record = Record { key, len, offset: self.page * PAGE_SIZE + offset + WORD_SIZE };
+            let next = offset + record_size(len);
+            let mut value = [0u8; MAX_VALUE_SIZE];
+            self.flash.read(record.offset, &mut value[..len])?;
+            if record_header(key, &value[..len]) == header {
+                return Ok(Some((record, next)));
+            }
+            offset = next;
+        }
+        Ok(None)
+    }
+
+    // Sequence number of a valid page of this schema
+    fn page_header(&mut self, page: u32) -> Result<Option<u16>, Error<F::Error>> {
+        let magic = self.word(page * PAGE_SIZE)?;
+        let version = self.word(page * PAGE_SIZE + WORD_SIZE)?;
+        match magic == MAGIC && (version >> 16) as u16 == self.schema {
+            true => Ok(Some(version as u16)),
+            false => Ok(None),
+        }
+    }
+
+    fn write_page_header(&mut self, page: u32, sequence: u16) -> Result<(), Error<F::Error>> {
+        let mut header = [0u8; PAGE_HEADER_SIZE as usize];
+        header[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        header[4..].copy_from_slice(&((self.schema as u32) << 16 | sequence as u32).to_le_bytes());
+        self.flash.write(page * PAGE_SIZE, &header)?;
+        Ok(())
+    }
+
+    fn word(&mut self, offset: u32) -> Result<u32, Error<F::Error>> {
+        let mut bytes = [0u8; WORD_SIZE as usize];
+        self.flash.read(offset, &mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    len.div_ceil(WORD_SIZE as usize) * WORD_SIZE as usize
+}
+
+fn record_size(len: usize) -> u32 {
+    WORD_SIZE + padded_len(len) as u32
+}
+
+fn record_header(key: u8, value: &[u8]) -> u32 {
+    let crc = crc16(&[key, value.len() as u8]);
+    let crc = crc16_update(crc, value);
+    key as u32 | (value.len() as u32) << 8 | (crc as u32) << 16
+}
+
+// CRC-16/CCITT-FALSE
+pub fn crc16(bytes: &[u8]) -> u16 {
+    crc16_update(0xFFFF, bytes)
+}
+
+fn crc16_update(crc: u16, bytes: &[u8]) -> u16 {
+    bytes.iter().fold(crc, |crc, byte| {
+        (0..8).fold(crc ^ (*byte as u16) << 8, |crc, _| match crc & 0x8000 {
+            0 => crc << 1,
+            _ => crc << 1 ^ 0x1021,
+        })
+    })
+}
+
+// Flash in RAM with the NOR flash rules, writes can only clear bits
+#[cfg(test)]
+pub(crate) mod ram_flash {
+    use super::{PAGES, PAGE_SIZE};
+    use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+    pub struct RamFlash {
+        pub bytes: [u8; (PAGE_SIZE * PAGES) as usize],
+        pub erases: [u32; PAGES as usize],
+    }
+
+    impl RamFlash {
+        pub fn new() -> RamFlash {
+            RamFlash { bytes: [0xFF; (PAGE_SIZE * PAGES) as usize], erases: [0; PAGES as usize] }
+        }
+    }
+
+    impl ErrorType for RamFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.bytes.len()
+        }
+    }
+
+    impl NorFlash for RamFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = PAGE_SIZE as usize;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            for page in from / PAGE_SIZE..to / PAGE_SIZE {
+                self.erases[page as usize] += 1;
+            }
+            self.bytes[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            assert!(offset.is_multiple_of(4) && bytes.len().is_multiple_of(4), "unaligned write");
+            for (target, byte) in self.bytes[offset as usize..].iter_mut().zip(bytes) {
+                assert!(*target & byte == *byte, "write to flash that is not erased");
+                *target = *byte;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ram_flash::RamFlash;
+    use super::*;
+
+    fn read(store: &mut Store<RamFlash>, key: u8) -> Option<u32> {
+        let mut buffer = [0u8; 4];
+        store.read(key, &mut buffer).filter(|len| *len == 4).map(|_| u32::from_le_bytes(buffer))
+    }
+
+    #[test]
+    fn crc_matches_reference() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn latest_value_wins() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        assert_eq!(read(&mut store, 3), None);
+        store.write(3, &7u32.to_le_bytes()).unwrap();
+        store.write(4, &8u32.to_le_bytes()).unwrap();
+        store.write(3, &9u32.to_le_bytes()).unwrap();
+
+        assert_eq!(read(&mut store, 3), Some(9));
+        assert_eq!(read(&mut store, 4), Some(8));
+    }
+
+    #[test]
+    fn values_survive_remount() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, b"hello").unwrap();
+        let mut store = Store::new(store.free(), 1).unwrap();
+        store.write(2, b"world").unwrap();
+
+        let mut buffer = [0u8; 8];
+        assert_eq!(store.read(1, &mut buffer), Some(5));
+        assert_eq!(&buffer[..5], b"hello");
+        assert_eq!(store.read(2, &mut buffer), Some(5));
+        assert_eq!(&buffer[..5], b"world");
+    }
+
+    #[test]
+    fn unchanged_value_is_not_rewritten() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, &5u32.to_le_bytes()).unwrap();
+        let end = store.end;
+        store.write(1, &5u32.to_le_bytes()).unwrap();
+        assert_eq!(store.end, end);
+    }
+
+    #[test]
+    fn compaction_alternates_pages() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        for value in 0..2000u32 {
+            store.write((value % 5) as u8, &value.to_le_bytes()).unwrap();
+        }
+        for key in 0..5u32 {
+            assert_eq!(read(&mut store, key as u8), Some(1995 + key));
+        }
+        let erases = store.free().erases;
+        assert!(erases[0] > 1 && erases[0].abs_diff(erases[1]) <= 1, "{:?}", erases);
+    }
+
+    #[test]
+    fn corrupt_record_is_skipped() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, &5u32.to_le_bytes()).unwrap();
+        store.write(1, &6u32.to_le_bytes()).unwrap();
+        store.write(2, &7u32.to_le_bytes()).unwrap();
+        let mut flash = store.free();
+        // Flip a bit in the value of the second record
+        flash.bytes[(PAGE_HEADER_SIZE + 8 + 4) as usize] ^= 1;
+
+        let mut store = Store::new(flash, 1).unwrap();
+        assert_eq!(read(&mut store, 1), Some(5));
+        assert_eq!(read(&mut store, 2), Some(7));
+    }
+
+    #[test]
+    fn other_schema_is_discarded() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, &5u32.to_le_bytes()).unwrap();
+        let mut store = Store::new(store.free(), 2).unwrap();
+        assert_eq!(read(&mut store, 1), None);
+    }
+
+    #[test]
+    fn interrupted_compaction_keeps_newest_page() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, &5u32.to_le_bytes()).unwrap();
+        let mut flash = store.free();
+        // Copy of the page with a newer sequence number, as left before the old page is erased
+        let (old, new) = flash.bytes.split_at_mut(PAGE_SIZE as usize);
+        new.copy_from_slice(old);
+        new[4] = 1;
+        new[PAGE_HEADER_SIZE as usize + 4] = 6;
+        new[PAGE_HEADER_SIZE as usize..PAGE_HEADER_SIZE as usize + 4]
+            .copy_from_slice(&record_header(1, &6u32.to_le_bytes()).to_le_bytes());
+
+        let mut store = Store::new(flash, 1).unwrap();
+        assert_eq!(read(&mut store, 1), Some(6));
+    }
+
+    #[test]
+    fn longer_value_reports_its_length() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        store.write(1, b"too long").unwrap();
+
+        let mut buffer = [0u8; 4];
+        assert_eq!(store.read(1, &mut buffer), Some(8));
+        assert_eq!(&buffer, b"too ");
+        assert_eq!(read(&mut store, 1), None);
+    }
+
+    #[test]
+    fn oversized_value_is_rejected() {
+        let mut store = Store::new(RamFlash::new(), 1).unwrap();
+        assert_eq!(store.write(1, &[0; MAX_VALUE_SIZE + 1]), Err(Error::TooLarge));
+    }
+}
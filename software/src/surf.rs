@@ -0,0 +1,162 @@
+// Endless surf noise synthesized on the fly, so the ambient sound never loops and takes no
+// flash. A deep rumble of brown noise and a hiss of low-passed white noise are shaped by
+// waves that build up, break and wash out, each with a randomised length and height.
+
+use crate::mixer;
+
+pub const SAMPLE_RATE: u32 = 22_050;
+pub const MIN_PERIOD_MS: u32 = 1_000;
+
+const ONE: i32 = 1 << 15; // Levels are fixed point with ONE as 1.0
+const FLOOR: i32 = ONE / 5; // The wash between two waves
+const ATTACK_PERCENT: u32 = 30; // Part of a wave that builds up, the rest washes out
+const PERIOD_SPREAD_PERCENT: u32 = 25; // Wave lengths vary by this much around the period
+const MIN_HEIGHT_PERCENT: u32 = 60; // Lowest wave relative to the highest
+
+// Low-pass coefficients of the hiss, out of 65536: about 250 Hz in the wash, 2.4 kHz as
+// a wave breaks
+const CALM_HISS: i32 = 4_000;
+const BREAKING_HISS: i32 = 32_000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Params {
+    pub period_ms: u32, // Average time from one wave to the next
+    pub intensity: u8,  // Percent, 0 is a steady wash, 100 the highest breakers
+}
+
+pub struct Surf {
+    params: Params,
+    random: u32, // Xorshift state, never 0
+    rumble: i32, // Brown noise, leaky integral of the white noise
+    hiss: i32,   // White noise through a one pole low-pass
+    wave_len: u32,
+    wave_pos: u32,
+    height: i32, // Level at the crest of the current wave
+}
+
+impl Surf {
+    pub fn new(params: Params, seed: u32) -> Surf {
+        let mut surf = Surf { params, random: seed | 1, rumble: 0, hiss: 0, wave_len: 0, wave_pos: 0, height: 0 };
+        surf.next_wave();
+        surf
+    }
+
+    // Next sample, unless looped only one wave is played
+    pub fn sample(&mut self, looped: bool) -> Option<i16> {
+        if self.wave_pos == self.wave_len {
+            if !looped {
+                return None;
+            }
+            self.next_wave();
+        }
+        Some(self.synthesize())
+    }
+
+    fn synthesize(&mut self) -> i16 {
+        let level = self.level();
+        self.wave_pos += 1;
+
+        let white = (self.next_random() as i32) >> 17;
+        self.rumble += (white >> 3) - (self.rumble >> 6);
+        let cutoff = CALM_HISS + ((BREAKING_HISS - CALM_HISS) * (level - FLOOR).max(0) / (ONE - FLOOR));
+        self.hiss += ((white - self.hiss) * cutoff) >> 16;
+
+        let noise = self.rumble + 2 * self.hiss;
+        ((noise as i64 * level as i64) >> 15).clamp(i16::MIN as i64, i16::MAX as i64) as i16
+    }
+
+    // Level at the current position of the wave, it builds up slowly and breaks at the crest
+    fn level(&self) -> i32 {
+        let attack = self.wave_len * ATTACK_PERCENT / 100;
+        let shape = if self.wave_pos < attack {
+            let rise = (self.wave_pos as i64 * ONE as i64 / attack as i64) as i32;
+            (rise * rise) >> 15
+        } else {
+            ((self.wave_len - self.wave_pos) as i64 * ONE as i64 / (self.wave_len - attack) as i64) as i32
+        };
+        FLOOR + (((self.height - FLOOR) * shape) >> 15)
+    }
+
+    fn next_wave(&mut self) {
+        let period = self.params.period_ms.max(MIN_PERIOD_MS) as u64 * SAMPLE_RATE as u64 / 1000;
+        let spread = 100 - PERIOD_SPREAD_PERCENT + self.next_random() % (2 * PERIOD_SPREAD_PERCENT + 1);
+        self.wave_len = (period * spread as u64 / 100) as u32;
+        self.wave_pos = 0;
+        let height = MIN_HEIGHT_PERCENT + self.next_random() % (101 - MIN_HEIGHT_PERCENT);
+        let intensity = self.params.intensity.min(100) as i32;
+        self.height = FLOOR + (ONE - 1 - FLOOR) * intensity / 100 * height as i32 / 100;
+    }
+
+    fn next_random(&mut self) -> u32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random
+    }
+}
+
+impl mixer::Input for Surf {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn frame(&mut self, looped: bool) -> Option<[i16; 2]> {
+        self.sample(looped).map(|sample| [sample; 2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAVES: Params = Params { period_ms: 4_000, intensity: 100 };
+
+    fn samples(surf: &mut Surf, looped: bool, count: usize) -> Vec<i16> {
+        (0..count).map_while(|_| surf.sample(looped)).collect()
+    }
+
+    // Loudness of every `window_ms` of `seconds` of surf
+    fn loudness(params: Params, seconds: u32, window_ms: u32) -> Vec<f64> {
+        let samples = samples(&mut Surf::new(params, 1), true, (SAMPLE_RATE * seconds) as usize);
+        let window = (SAMPLE_RATE * window_ms / 1000) as usize;
+        samples
+            .chunks_exact(window)
+            .map(|chunk| (chunk.iter().map(|sample| (*sample as f64).powi(2)).sum::<f64>() / window as f64).sqrt())
+            .collect()
+    }
+
+    #[test]
+    fn surf_never_repeats() {
+        let mut surf = Surf::new(WAVES, 7);
+        let first = samples(&mut surf, true, 2048);
+        let second = samples(&mut surf, true, 2048);
+        assert_eq!(second.len(), 2048);
+        assert_ne!(first, second);
+        assert!(first.iter().any(|sample| *sample != 0));
+    }
+
+    #[test]
+    fn waves_rise_and_fall() {
+        let loudness = loudness(WAVES, 20, 200);
+        let loudest = loudness.iter().cloned().fold(0.0, f64::max);
+        let quietest = loudness.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(loudest > 4.0 * quietest, "{} / {}", loudest, quietest);
+    }
+
+    #[test]
+    fn calm_sea_is_a_steady_wash() {
+        let loudness = loudness(Params { intensity: 0, ..WAVES }, 20, 1000);
+        let loudest = loudness.iter().cloned().fold(0.0, f64::max);
+        let quietest = loudness.iter().cloned().fold(f64::MAX, f64::min);
+        assert!(loudest < 2.0 * quietest, "{} / {}", loudest, quietest);
+    }
+
+    #[test]
+    fn preview_plays_one_wave() {
+        let mut surf = Surf::new(WAVES, 3);
+        let played = samples(&mut surf, false, usize::MAX).len();
+        assert_eq!(surf.sample(false), None);
+        let seconds = played as f64 / SAMPLE_RATE as f64;
+        assert!((3.0..=5.0).contains(&seconds), "{}", seconds);
+    }
+}
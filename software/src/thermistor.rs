@@ -38,6 +38,8 @@ pub(crate) fn read(mut cx: read_temperature::Context) {
             *temperature = temp;
         });
     }
+    // Once a minute, the thermostat counts its run and off times in readings
+    update_thermostat::spawn(true).ok();
 }
 
 fn calculate_temperature(adc_value: i16) -> f32 {
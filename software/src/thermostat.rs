@@ -0,0 +1,170 @@
+// Comfort mode, the fan speeds up with the temperature above a threshold. It starts at the
+// threshold and stops once the temperature has fallen `hysteresis` below it, but only after
+// running and resting a minimum time. In quiet hours the speed is capped, 0 keeps it off.
+// Updated after every temperature reading, once a minute.
+
+use {
+    crate::{outputs::MAX_DUTY, time::{Window, TICKS_PER_DAY}},
+    libm::roundf,
+};
+
+pub const MAX_TEMPERATURE: i16 = 400; // Tenths of a degree, the thermistor reads up to 40 C
+pub const MAX_MINUTES: u8 = 60;
+
+pub const DEFAULT_SETTINGS: Settings = Settings {
+    enabled: false,
+    threshold: 260,
+    span: 40,
+    hysteresis: 10,
+    min_speed: 30,
+    min_run_minutes: 5,
+    min_off_minutes: 3,
+    quiet: QuietHours::DISABLED,
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    pub enabled: bool,
+    pub threshold: i16, // Tenths of a degree Celsius the fan starts at
+    pub span: u8,       // Tenths of a degree above the threshold for full speed
+    pub hysteresis: u8, // Tenths of a degree below the threshold the fan stops at
+    pub min_speed: u8,  // Percent, the slowest the fan turns reliably
+    pub min_run_minutes: u8,
+    pub min_off_minutes: u8,
+    pub quiet: QuietHours,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QuietHours {
+    pub window: Window, // A disabled window disables the quiet hours
+    pub speed: u8,      // Percent the fan is capped at
+}
+
+impl QuietHours {
+    pub const DISABLED: QuietHours = QuietHours { window: Window::DISABLED, speed: 0 };
+}
+
+impl Settings {
+    // Speed for a temperature in tenths of a degree, before the quiet hours
+    fn speed(&self, temperature: i16) -> u8 {
+        let above = (temperature - self.threshold).max(0) as u32;
+        let range = (MAX_DUTY - self.min_speed.min(MAX_DUTY)) as u32;
+        let speed = self.min_speed as u32 + range * above / (self.span as u32).max(1);
+        speed.min(MAX_DUTY as u32) as u8
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Thermostat {
+    running: bool,
+    minutes: u32, // Since the fan last started or stopped
+}
+
+impl Thermostat {
+    // Free to start right away after boot
+    pub const fn new() -> Thermostat {
+        Thermostat { running: false, minutes: u32::MAX }
+    }
+
+    // Called once a minute with the temperature in degrees Celsius and the time of day in
+    // ticks, returns the fan speed in percent
+    pub fn update(&mut self, settings: &Settings, temperature: f32, ticks: u32) -> u8 {
+        let temperature = roundf(temperature * 10.0) as i16;
+        self.minutes = self.minutes.saturating_add(1);
+        let running = match self.running {
+            _ if !settings.enabled => false,
+            false => temperature >= settings.threshold && self.minutes >= settings.min_off_minutes as u32,
+            true => {
+                temperature > settings.threshold - settings.hysteresis as i16
+                    || self.minutes < settings.min_run_minutes as u32
+            }
+        };
+        if running != self.running {
+            self.running = running;
+            self.minutes = 0;
+        }
+        match running {
+            false => 0,
+            true if settings.quiet.window.contains(ticks % TICKS_PER_DAY) => {
+                settings.speed(temperature).min(settings.quiet.speed)
+            }
+            true => settings.speed(temperature),
+        }
+    }
+}
+
+impl Default for Thermostat {
+    fn default() -> Thermostat {
+        Thermostat::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::time_to_ticks;
+
+    const NOON: u32 = TICKS_PER_DAY / 2;
+
+    fn enabled() -> Settings {
+        Settings { enabled: true, ..DEFAULT_SETTINGS }
+    }
+
+    // Fan speed for every minute at the given temperatures
+    fn run(settings: &Settings, temperatures: &[f32]) -> Vec<u8> {
+        let mut thermostat = Thermostat::new();
+        temperatures.iter().map(|temperature| thermostat.update(settings, *temperature, NOON)).collect()
+    }
+
+    #[test]
+    fn disabled_keeps_the_fan_off() {
+        assert_eq!(run(&DEFAULT_SETTINGS, &[35.0, 35.0]), [0, 0]);
+    }
+
+    #[test]
+    fn speed_scales_above_the_threshold() {
+        // 26.0 C starts at 30%, 30.0 C and above is full speed
+        assert_eq!(run(&enabled(), &[25.9, 26.0, 28.0, 30.0, 39.0]), [0, 30, 65, 100, 100]);
+    }
+
+    #[test]
+    fn hysteresis_keeps_it_running_below_the_threshold() {
+        let settings = Settings { min_run_minutes: 0, ..enabled() };
+        assert_eq!(run(&settings, &[26.0, 25.5, 25.1, 25.0, 25.5]), [30, 30, 30, 0, 0]);
+    }
+
+    #[test]
+    fn runs_and_rests_a_minimum_time() {
+        let settings = Settings { min_run_minutes: 3, min_off_minutes: 2, ..enabled() };
+        let temperatures = [27.0, 20.0, 20.0, 20.0, 27.0, 27.0, 27.0];
+        assert_eq!(run(&settings, &temperatures), [47, 30, 30, 0, 0, 47, 47]);
+    }
+
+    #[test]
+    fn switching_off_stops_at_once() {
+        let mut thermostat = Thermostat::new();
+        assert_eq!(thermostat.update(&enabled(), 28.0, NOON), 65);
+        assert_eq!(thermostat.update(&DEFAULT_SETTINGS, 28.0, NOON), 0);
+    }
+
+    #[test]
+    fn quiet_hours_cap_the_speed() {
+        let quiet = QuietHours { window: Window { start: time_to_ticks(22, 0), end: time_to_ticks(7, 0) }, speed: 40 };
+        let settings = Settings { quiet, ..enabled() };
+        let mut thermostat = Thermostat::new();
+        assert_eq!(thermostat.update(&settings, 30.0, NOON), 100);
+        assert_eq!(thermostat.update(&settings, 30.0, time_to_ticks(23, 0)), 40);
+        assert_eq!(thermostat.update(&settings, 30.0, time_to_ticks(6, 59)), 40);
+        assert_eq!(thermostat.update(&settings, 30.0, time_to_ticks(7, 0)), 100);
+        let silent = Settings { quiet: QuietHours { speed: 0, ..quiet }, ..settings };
+        assert_eq!(thermostat.update(&silent, 30.0, time_to_ticks(2, 0)), 0);
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let window = Window { start: time_to_ticks(13, 0), end: time_to_ticks(15, 0) };
+        assert!(!window.contains(NOON) && window.contains(time_to_ticks(14, 0)));
+        let disabled = QuietHours::DISABLED.window;
+        assert!(!disabled.enabled() && !disabled.contains(0));
+    }
+}
@@ -0,0 +1,239 @@
+pub const TICKS_PER_SECOND: u32 = 8;
+pub const TICKS_PER_MINUTE: u32 = TICKS_PER_SECOND * 60; // Interrupt every second for demonstration purpose, will be 8*60 in production
+pub const TICKS_PER_HOUR: u32 = TICKS_PER_MINUTE * 60;
+pub const TICKS_PER_DAY: u32 = TICKS_PER_HOUR * 24;
+pub const TICKS_PER_WEEK: u32 = TICKS_PER_DAY * 7; // Time is kept as ticks from Monday 00:00
+pub const DEFAULT_SETTINGS_TIMEOUT_MINUTES: u32 = 5; // Settings are left after 5 minutes
+pub const DEFAULT_ALARM_TIMEOUT_MINUTES: u32 = 30; // Alarm gives up after 30 minutes
+pub const MAX_TIMEOUT_MINUTES: u32 = 240;
+pub const BLINK_TICKS: u32 = TICKS_PER_SECOND/2; // Blink every 1 seconds
+pub const VOLUME_BAR_TICKS: u32 = TICKS_PER_SECOND * 3; // Volume bar shown after the last turn
+pub const SAVE_DELAY_TICKS: u32 = TICKS_PER_SECOND * 5; // Knob settings saved after the last turn
+pub const WEEKDAY_NAMES: [&[u8; 3]; 7] = [b"Mon", b"Tue", b"Wed", b"Thu", b"Fri", b"Sat", b"Sun"];
+
+// Days are counted from 2000-01-01, which was a Saturday
+pub const EPOCH_YEAR: u16 = 2000;
+pub const MAX_YEAR: u16 = 2099;
+const EPOCH_WEEKDAY: u32 = 5;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8, // 1 is January
+    pub day: u8,   // 1 is the first day of the month
+}
+
+impl Date {
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Date> {
+        match (year, month) {
+            (EPOCH_YEAR..=MAX_YEAR, 1..=12) if day >= 1 && day <= days_in_month(year, month) => {
+                Some(Date { year, month, day })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_days(days: u32) -> Date {
+        let mut days = days;
+        let mut year = EPOCH_YEAR;
+        while days >= days_in_year(year) {
+            days -= days_in_year(year);
+            year += 1;
+        }
+        let mut month = 1;
+        while days >= days_in_month(year, month) as u32 {
+            days -= days_in_month(year, month) as u32;
+            month += 1;
+        }
+        Date {
+            year,
+            month,
+            day: days as u8 + 1,
+        }
+    }
+
+    pub fn to_days(&self) -> u32 {
+        let years: u32 = (EPOCH_YEAR..self.year).map(days_in_year).sum();
+        let months: u32 = (1..self.month).map(|month| days_in_month(self.year, month) as u32).sum();
+        years + months + self.day as u32 - 1
+    }
+}
+
+// Date and time of day, the days counter keeps counting across midnight
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DateTime {
+    pub days: u32,  // Days since 2000-01-01
+    pub ticks: u32, // Ticks since midnight
+}
+
+impl DateTime {
+    pub const fn new(days: u32, ticks: u32) -> DateTime {
+        DateTime { days, ticks }
+    }
+
+    // From ticks since 2000-01-01 00:00
+    pub fn from_ticks(ticks: u64) -> DateTime {
+        DateTime {
+            days: (ticks / TICKS_PER_DAY as u64) as u32,
+            ticks: (ticks % TICKS_PER_DAY as u64) as u32,
+        }
+    }
+
+    // Ticks since 2000-01-01 00:00
+    pub fn to_ticks(&self) -> u64 {
+        self.days as u64 * TICKS_PER_DAY as u64 + self.ticks as u64
+    }
+
+    pub fn date(&self) -> Date {
+        Date::from_days(self.days)
+    }
+
+    // 0 is Monday, 6 is Sunday
+    pub fn weekday(&self) -> u8 {
+        ((self.days + EPOCH_WEEKDAY) % 7) as u8
+    }
+
+    // Ticks from Monday 00:00, the time base of the alarms
+    pub fn week_ticks(&self) -> u32 {
+        self.weekday() as u32 * TICKS_PER_DAY + self.ticks
+    }
+}
+
+// Part of the day from `start` up to `end`, which may be past midnight
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Window {
+    pub start: u32, // Ticks from 00:00, equal to `end` disables the window
+    pub end: u32,
+}
+
+impl Window {
+    pub const DISABLED: Window = Window { start: 0, end: 0 };
+
+    pub fn enabled(&self) -> bool {
+        self.start != self.end
+    }
+
+    // Never while disabled
+    pub fn contains(&self, ticks: u32) -> bool {
+        match self.start <= self.end {
+            true => (self.start..self.end).contains(&ticks),
+            false => ticks >= self.start || ticks < self.end,
+        }
+    }
+}
+
+pub fn is_leap_year(year: u16) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+pub fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn days_in_year(year: u16) -> u32 {
+    match is_leap_year(year) {
+        true => 366,
+        false => 365,
+    }
+}
+
+pub fn time_to_ticks(hour: u8, minute: u8) -> u32 {
+    let minutes = (hour as u32) * 60 + (minute as u32);
+    minutes * TICKS_PER_MINUTE
+}
+
+pub fn ticks_to_time(ticks: u32) -> (u8, u8) {
+    let minutes = ticks / TICKS_PER_MINUTE;
+    let hour = ((minutes / 60) % 24) as u8;
+    let minute = (minutes % 60) as u8;
+
+    (hour, minute)
+}
+
+// 0 is Monday, 6 is Sunday
+pub fn ticks_to_weekday(ticks: u32) -> u8 {
+    ((ticks / TICKS_PER_DAY) % 7) as u8
+}
+
+// Moves the time of day by `diff` ticks, wrapping around midnight without changing the weekday
+pub fn add_ticks_within_day(ticks: u32, diff: isize) -> u32 {
+    let day_start = ticks - ticks % TICKS_PER_DAY;
+    let time_of_day = (ticks % TICKS_PER_DAY) as isize;
+    day_start + (time_of_day + diff).rem_euclid(TICKS_PER_DAY as isize) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leap_years() {
+        assert!(is_leap_year(2000));
+        assert!(is_leap_year(2024));
+        assert!(!is_leap_year(2023));
+        assert!(!is_leap_year(2100));
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(2023, 2), 28);
+    }
+
+    #[test]
+    fn date_round_trip() {
+        let mut days = 0;
+        for year in EPOCH_YEAR..=MAX_YEAR {
+            for month in 1..=12 {
+                for day in 1..=days_in_month(year, month) {
+                    let date = Date::new(year, month, day).unwrap();
+                    assert_eq!(date.to_days(), days);
+                    assert_eq!(Date::from_days(days), date);
+                    days += 1;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_dates() {
+        assert_eq!(Date::new(2023, 2, 29), None);
+        assert_eq!(Date::new(2024, 4, 31), None);
+        assert_eq!(Date::new(2024, 13, 1), None);
+        assert_eq!(Date::new(2024, 1, 0), None);
+        assert_eq!(Date::new(1999, 12, 31), None);
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(DateTime::new(0, 0).weekday(), 5); // Saturday 2000-01-01
+        let monday = Date::new(2024, 1, 1).unwrap().to_days();
+        assert_eq!(DateTime::new(monday, 0).weekday(), 0);
+        let leap_day = Date::new(2024, 2, 29).unwrap().to_days();
+        assert_eq!(DateTime::new(leap_day, 0).weekday(), 3);
+    }
+
+    #[test]
+    fn ticks_roll_over_midnight() {
+        let new_years_eve = Date::new(2023, 12, 31).unwrap().to_days();
+        let now = DateTime::new(new_years_eve, time_to_ticks(23, 59));
+        let later = DateTime::from_ticks(now.to_ticks() + TICKS_PER_MINUTE as u64);
+        assert_eq!(later.date(), Date::new(2024, 1, 1).unwrap());
+        assert_eq!(later.ticks, 0);
+    }
+
+    #[test]
+    fn ticks_round_trip() {
+        let now = DateTime::new(Date::new(2099, 12, 31).unwrap().to_days(), time_to_ticks(23, 59));
+        assert_eq!(DateTime::from_ticks(now.to_ticks()), now);
+    }
+
+    #[test]
+    fn week_ticks() {
+        let wednesday = Date::new(2024, 1, 3).unwrap().to_days();
+        let now = DateTime::new(wednesday, time_to_ticks(6, 30));
+        assert_eq!(now.week_ticks(), 2 * TICKS_PER_DAY + time_to_ticks(6, 30));
+        assert_eq!(ticks_to_weekday(now.week_ticks()), 2);
+    }
+}
@@ -0,0 +1,156 @@
+// Software timers sharing the RTC1 compare registers. Deadlines are kept sorted in monotonic
+// ticks, the nearest ones are programmed into the compare registers. These are the timers
+// following the wall clock, short delays like blinking and timeouts use the RTIC monotonic.
+
+use heapless::Vec;
+
+pub const COMPARE_REGISTERS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timer {
+    PeriodicUpdate,
+    Alarm,   // Next alarm
+    Sunrise, // Start of the sunrise before the next alarm
+    Snooze,  // End of a snooze, next to the alarms still armed
+}
+
+// Every timer is in the queue at most once
+const TIMERS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Deadline {
+    pub ticks: u64, // Monotonic ticks
+    pub timer: Timer,
+}
+
+pub struct TimerQueue {
+    deadlines: Vec<Deadline, TIMERS>, // Sorted, nearest first
+}
+
+impl TimerQueue {
+    pub const fn new() -> TimerQueue {
+        TimerQueue { deadlines: Vec::new() }
+    }
+
+    // Starts the timer, a running timer is moved to the new deadline
+    pub fn start(&mut self, timer: Timer, ticks: u64) {
+        self.stop(timer);
+        let index = self
+            .deadlines
+            .iter()
+            .position(|deadline| deadline.ticks > ticks)
+            .unwrap_or(self.deadlines.len());
+        // Cannot be full, the timer was just removed
+        self.deadlines.insert(index, Deadline { ticks, timer }).ok();
+    }
+
+    pub fn stop(&mut self, timer: Timer) {
+        self.deadlines.retain(|deadline| deadline.timer != timer);
+    }
+
+    pub fn is_running(&self, timer: Timer) -> bool {
+        self.deadlines.iter().any(|deadline| deadline.timer == timer)
+    }
+
+    // Removes and returns the nearest timer that has expired at `now`
+    pub fn pop_expired(&mut self, now: u64) -> Option<Timer> {
+        match self.deadlines.first() {
+            Some(deadline) if deadline.ticks <= now => Some(self.deadlines.remove(0).timer),
+            _ => None,
+        }
+    }
+
+    // Deadline for each compare register, None disables the register
+    pub fn nearest(&self) -> [Option<u64>; COMPARE_REGISTERS] {
+        let mut nearest = [None; COMPARE_REGISTERS];
+        for (compare, deadline) in nearest.iter_mut().zip(self.deadlines.iter()) {
+            *compare = Some(deadline.ticks);
+        }
+        nearest
+    }
+}
+
+impl Default for TimerQueue {
+    fn default() -> TimerQueue {
+        TimerQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_in_deadline_order() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::Alarm, 300);
+        timers.start(Timer::PeriodicUpdate, 100);
+
+        assert_eq!(timers.nearest(), [Some(100), Some(300), None, None]);
+        assert_eq!(timers.pop_expired(99), None);
+        assert_eq!(timers.pop_expired(250), Some(Timer::PeriodicUpdate));
+        assert_eq!(timers.pop_expired(250), None);
+        assert_eq!(timers.pop_expired(300), Some(Timer::Alarm));
+        assert_eq!(timers.nearest(), [None; COMPARE_REGISTERS]);
+    }
+
+    #[test]
+    fn restart_moves_deadline() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::PeriodicUpdate, 100);
+        timers.start(Timer::Alarm, 200);
+        timers.start(Timer::PeriodicUpdate, 300);
+
+        assert_eq!(timers.nearest(), [Some(200), Some(300), None, None]);
+        assert_eq!(timers.pop_expired(1000), Some(Timer::Alarm));
+        assert_eq!(timers.pop_expired(1000), Some(Timer::PeriodicUpdate));
+        assert_eq!(timers.pop_expired(1000), None);
+    }
+
+    #[test]
+    fn stop_removes_timer() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::Alarm, 100);
+        timers.start(Timer::PeriodicUpdate, 50);
+        timers.stop(Timer::Alarm);
+        timers.stop(Timer::Alarm); // Not running
+
+        assert!(!timers.is_running(Timer::Alarm));
+        assert!(timers.is_running(Timer::PeriodicUpdate));
+        assert_eq!(timers.pop_expired(1000), Some(Timer::PeriodicUpdate));
+        assert_eq!(timers.pop_expired(1000), None);
+    }
+
+    #[test]
+    fn snooze_keeps_the_sunrise() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::PeriodicUpdate, 10);
+        timers.start(Timer::Sunrise, 200);
+        timers.start(Timer::Alarm, 300);
+        timers.start(Timer::Snooze, 100);
+
+        assert_eq!(timers.nearest(), [Some(10), Some(100), Some(200), Some(300)]);
+        assert!(timers.is_running(Timer::Sunrise) && timers.is_running(Timer::Alarm));
+    }
+
+    #[test]
+    fn rearm_keeps_the_snooze() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::Alarm, 500);
+        timers.start(Timer::Snooze, 200);
+        timers.start(Timer::Alarm, 100);
+
+        assert_eq!(timers.pop_expired(1000), Some(Timer::Alarm));
+        assert_eq!(timers.pop_expired(1000), Some(Timer::Snooze));
+    }
+
+    #[test]
+    fn equal_deadlines_keep_start_order() {
+        let mut timers = TimerQueue::new();
+        timers.start(Timer::Alarm, 100);
+        timers.start(Timer::PeriodicUpdate, 100);
+
+        assert_eq!(timers.pop_expired(100), Some(Timer::Alarm));
+        assert_eq!(timers.pop_expired(100), Some(Timer::PeriodicUpdate));
+    }
+}
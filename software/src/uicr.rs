@@ -6,7 +6,8 @@ use {
 const RESET_PIN: u8 = 18;
 const RESET_PORT: bool = false; // Port 0
 
-pub(crate) fn init(uicr: UICR, nvmc: NVMC) {
+// Returns the NVMC for the settings store
+pub(crate) fn init(uicr: UICR, nvmc: NVMC) -> NVMC {
     // Check if UICR is set correctly
     let check_uicr_set = uicr.nfcpins.read().protect().is_disabled()
         | uicr.pselreset[0].read().connect().is_connected()
@@ -36,4 +37,5 @@ pub(crate) fn init(uicr: UICR, nvmc: NVMC) {
         // Changes to UICR require a reset to take effect
         cortex_m::peripheral::SCB::sys_reset();
     }
+    nvmc
 }
@@ -0,0 +1,50 @@
+// Speaker volume in steps of 2 dB, 0 mutes and MAX_VOLUME plays the clip unchanged.
+// Gains are fixed point with UNITY_GAIN as 1.0, applied to every sample by the mixer.
+
+pub const MAX_VOLUME: u8 = 20;
+pub const DEFAULT_VOLUME: u8 = 14;
+pub const UNITY_GAIN: u32 = 1 << 16;
+
+// 65536 * 10^(-(20 - volume) * 2 / 20)
+const GAINS: [u32; MAX_VOLUME as usize + 1] = [
+    0, 825, 1039, 1308, 1646, 2072, 2609, 3285, 4135, 5206, 6554, 8250, 10387, 13076, 16462, 20724, 26090, 32846,
+    41350, 52057, 65536,
+];
+
+// Gain for a volume step and an intensity in percent, e.g. of the alarm stage
+pub fn gain(volume: u8, percent: u32) -> u32 {
+    GAINS[volume.min(MAX_VOLUME) as usize] * percent.min(100) / 100
+}
+
+// Volume after turning the knob by `steps`, stops at both ends
+pub fn step(volume: u8, steps: isize) -> u8 {
+    (volume as isize + steps).clamp(0, MAX_VOLUME as isize) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ends_of_the_range() {
+        assert_eq!(gain(0, 100), 0);
+        assert_eq!(gain(MAX_VOLUME, 100), UNITY_GAIN);
+        assert_eq!(gain(MAX_VOLUME, 50), UNITY_GAIN / 2);
+        assert_eq!(gain(MAX_VOLUME + 5, 100), UNITY_GAIN);
+    }
+
+    #[test]
+    fn steps_are_2_db() {
+        for volume in 2..=MAX_VOLUME {
+            let ratio = gain(volume, 100) as f64 / gain(volume - 1, 100) as f64;
+            assert!((20.0 * ratio.log10() - 2.0).abs() < 0.01, "{}: {}", volume, ratio);
+        }
+    }
+
+    #[test]
+    fn knob_stops_at_the_ends() {
+        assert_eq!(step(DEFAULT_VOLUME, 2), DEFAULT_VOLUME + 2);
+        assert_eq!(step(1, -3), 0);
+        assert_eq!(step(MAX_VOLUME - 1, 4), MAX_VOLUME);
+    }
+}
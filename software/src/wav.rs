@@ -0,0 +1,258 @@
+// RIFF/WAVE parser for the embedded sound clips. Walks the chunks, so LIST and other
+// metadata chunks are skipped, and checks the format can be played by the I2S peripheral.
+// IMA ADPCM clips are described by the 16 bit PCM they decode to.
+
+use crate::adpcm;
+
+const RIFF_HEADER_SIZE: usize = 12;
+const CHUNK_HEADER_SIZE: usize = 8;
+const FORMAT_PCM: u16 = 1;
+const FORMAT_IMA_ADPCM: u16 = 0x11;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// Largest ADPCM block decoded at once, 512 bytes per channel of stereo
+pub const MAX_ADPCM_BLOCK_ALIGN: u16 = 1024;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Error {
+    NotWave,                    // No RIFF header of type WAVE
+    Truncated,                  // A chunk runs past the end of the file
+    MissingFormat,              // No fmt chunk before the data chunk
+    MissingData,                // No data chunk
+    UnsupportedEncoding(u16),   // Only uncompressed PCM and IMA ADPCM can be played
+    UnsupportedChannels(u16),   // Mono or stereo only
+    UnsupportedBitDepth(u16),   // 8, 16 or 24 bits per sample
+    InvalidBlockAlign(u16),     // Frame or ADPCM block size does not match the format
+    UnsupportedSampleRate(u32), // No I2S clock close enough, see pcm::clock
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Encoding {
+    Pcm,
+    ImaAdpcm { block_align: u16 }, // Blocks of this many bytes, see adpcm::decode_block
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Wav<'a> {
+    pub format: Format,
+    pub encoding: Encoding,
+    pub data: &'a [u8], // Interleaved PCM samples, little endian, or ADPCM blocks
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Wav<'_>, Error> {
+    if bytes.len() < RIFF_HEADER_SIZE || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(Error::NotWave);
+    }
+    let mut format = None;
+    let mut offset = RIFF_HEADER_SIZE;
+    while offset + CHUNK_HEADER_SIZE <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32_at(bytes, offset + 4) as usize;
+        let start = offset + CHUNK_HEADER_SIZE;
+        let body = bytes.get(start..start.saturating_add(size)).ok_or(Error::Truncated)?;
+        match id {
+            b"fmt " => format = Some(parse_format(body)?),
+            b"data" => {
+                let (format, encoding) = format.ok_or(Error::MissingFormat)?;
+                let data = match encoding {
+                    // Drop a partial frame at the end
+                    Encoding::Pcm => {
+                        let frame = (format.channels * format.bits_per_sample / 8) as usize;
+                        &body[..body.len() - body.len() % frame]
+                    }
+                    // The last block may be shorter
+                    Encoding::ImaAdpcm { .. } => body,
+                };
+                return Ok(Wav { format, encoding, data });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even size
+        offset = start + size + size % 2;
+    }
+    Err(Error::MissingData)
+}
+
+fn parse_format(body: &[u8]) -> Result<(Format, Encoding), Error> {
+    if body.len() < 16 {
+        return Err(Error::Truncated);
+    }
+    let encoding = match u16_at(body, 0) {
+        // The sub format follows the extension, its first two bytes are the encoding
+        FORMAT_EXTENSIBLE if body.len() >= 26 => u16_at(body, 24),
+        encoding => encoding,
+    };
+    let format = Format {
+        channels: u16_at(body, 2),
+        sample_rate: u32_at(body, 4),
+        bits_per_sample: u16_at(body, 14),
+    };
+    let block_align = u16_at(body, 12);
+    match format {
+        Format { channels: 1 | 2, bits_per_sample: 4, .. } if encoding == FORMAT_IMA_ADPCM => {
+            parse_adpcm_format(body, format, block_align)
+        }
+        _ if encoding != FORMAT_PCM => Err(Error::UnsupportedEncoding(encoding)),
+        Format { channels: 1 | 2, bits_per_sample: 8 | 16 | 24, .. }
+            if block_align == format.channels * format.bits_per_sample / 8 =>
+        {
+            Ok((format, Encoding::Pcm))
+        }
+        Format { channels: 1 | 2, bits_per_sample: 8 | 16 | 24, .. } => Err(Error::InvalidBlockAlign(block_align)),
+        Format { channels: 1 | 2, bits_per_sample, .. } => Err(Error::UnsupportedBitDepth(bits_per_sample)),
+        Format { channels, .. } => Err(Error::UnsupportedChannels(channels)),
+    }
+}
+
+// Blocks hold a header and whole groups of 8 samples per channel, the samples per block
+// follow the extension size
+fn parse_adpcm_format(body: &[u8], format: Format, block_align: u16) -> Result<(Format, Encoding), Error> {
+    if body.len() < 20 {
+        return Err(Error::Truncated);
+    }
+    let channels = format.channels as usize;
+    let header = adpcm::HEADER_SIZE * channels;
+    let valid = block_align <= MAX_ADPCM_BLOCK_ALIGN
+        && block_align as usize > header
+        && (block_align as usize - header).is_multiple_of(4 * channels)
+        && u16_at(body, 18) as usize == adpcm::samples_per_block(block_align as usize, channels);
+    match valid {
+        true => Ok((Format { bits_per_sample: 16, ..format }, Encoding::ImaAdpcm { block_align })),
+        false => Err(Error::InvalidBlockAlign(block_align)),
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn fmt(encoding: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut body = Vec::new();
+        body.extend_from_slice(&encoding.to_le_bytes());
+        body.extend_from_slice(&channels.to_le_bytes());
+        body.extend_from_slice(&sample_rate.to_le_bytes());
+        body.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        body.extend_from_slice(&block_align.to_le_bytes());
+        body.extend_from_slice(&bits.to_le_bytes());
+        chunk(b"fmt ", &body)
+    }
+
+    fn riff(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut riff = b"RIFF".to_vec();
+        riff.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        riff.extend_from_slice(b"WAVE");
+        riff.extend_from_slice(&body);
+        riff
+    }
+
+    #[test]
+    fn embedded_clip() {
+        let wav = parse(include_bytes!("../assets/SeaBreeze3.wav")).unwrap();
+        assert_eq!(wav.format, Format { channels: 1, sample_rate: 44_100, bits_per_sample: 16 });
+        assert_eq!(wav.encoding, Encoding::Pcm);
+        assert_eq!(wav.data.len(), 176_400);
+    }
+
+    #[test]
+    fn adpcm_clip_is_described_by_its_decoded_format() {
+        let wav = parse(include_bytes!("../assets/SeaBreeze3.adpcm.wav")).unwrap();
+        assert_eq!(wav.format, Format { channels: 1, sample_rate: 44_100, bits_per_sample: 16 });
+        assert_eq!(wav.encoding, Encoding::ImaAdpcm { block_align: 512 });
+    }
+
+    #[test]
+    fn adpcm_block_must_match_samples_per_block() {
+        let mut body = fmt(FORMAT_IMA_ADPCM, 2, 22_050, 4)[8..].to_vec();
+        body[12..14].copy_from_slice(&1024u16.to_le_bytes());
+        body.extend_from_slice(&[2, 0, 0xF9, 0x03]); // Extension size, 1017 samples per block
+        let data = chunk(b"data", &[0; 8]);
+        assert!(parse(&riff(&[chunk(b"fmt ", &body), data.clone()])).is_ok());
+
+        body[18] = 0xF8;
+        assert_eq!(parse(&riff(&[chunk(b"fmt ", &body), data.clone()])), Err(Error::InvalidBlockAlign(1024)));
+        body[18] = 0xF9;
+        body[12..14].copy_from_slice(&2048u16.to_le_bytes());
+        assert_eq!(parse(&riff(&[chunk(b"fmt ", &body), data])), Err(Error::InvalidBlockAlign(2048)));
+    }
+
+    #[test]
+    fn skips_metadata_chunks() {
+        let bytes = riff(&[
+            chunk(b"LIST", b"INFOISFT\x05\x00\x00\x00Lavf\x00"),
+            fmt(FORMAT_PCM, 2, 48_000, 24),
+            chunk(b"fact", &[0; 4]),
+            chunk(b"data", &[1, 2, 3, 4, 5, 6]),
+        ]);
+        let wav = parse(&bytes).unwrap();
+        assert_eq!(wav.format, Format { channels: 2, sample_rate: 48_000, bits_per_sample: 24 });
+        assert_eq!(wav.data, &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn odd_sized_chunk_is_padded() {
+        let bytes = riff(&[chunk(b"junk", &[0; 3]), fmt(FORMAT_PCM, 1, 8_000, 8), chunk(b"data", &[7; 5])]);
+        assert_eq!(parse(&bytes).unwrap().data, &[7; 5]);
+    }
+
+    #[test]
+    fn extensible_pcm_is_accepted() {
+        let mut body = fmt(FORMAT_EXTENSIBLE, 2, 44_100, 16)[8..].to_vec();
+        body.extend_from_slice(&[22, 0, 16, 0, 3, 0, 0, 0]); // Size, valid bits, channel mask
+        body.extend_from_slice(&FORMAT_PCM.to_le_bytes());
+        body.extend_from_slice(&[0; 14]);
+        let bytes = riff(&[chunk(b"fmt ", &body), chunk(b"data", &[0; 8])]);
+        assert_eq!(parse(&bytes).unwrap().format.channels, 2);
+    }
+
+    #[test]
+    fn partial_frame_is_dropped() {
+        let bytes = riff(&[fmt(FORMAT_PCM, 2, 44_100, 16), chunk(b"data", &[0; 10])]);
+        assert_eq!(parse(&bytes).unwrap().data.len(), 8);
+    }
+
+    #[test]
+    fn unsupported_files_are_rejected() {
+        let data = chunk(b"data", &[0; 4]);
+        assert_eq!(parse(b"RIFX\0\0\0\0WAVE"), Err(Error::NotWave));
+        assert_eq!(parse(&riff(&[fmt(2, 1, 44_100, 4), data.clone()])), Err(Error::UnsupportedEncoding(2)));
+        assert_eq!(parse(&riff(&[fmt(FORMAT_PCM, 6, 44_100, 16), data.clone()])), Err(Error::UnsupportedChannels(6)));
+        assert_eq!(parse(&riff(&[fmt(FORMAT_PCM, 1, 44_100, 32), data.clone()])), Err(Error::UnsupportedBitDepth(32)));
+        assert_eq!(parse(&riff(std::slice::from_ref(&data))), Err(Error::MissingFormat));
+        assert_eq!(parse(&riff(&[fmt(FORMAT_PCM, 1, 44_100, 16)])), Err(Error::MissingData));
+
+        let mut bytes = riff(&[fmt(FORMAT_PCM, 1, 44_100, 16), data]);
+        bytes.truncate(bytes.len() - 1);
+        assert_eq!(parse(&bytes), Err(Error::Truncated));
+
+        let mut bytes = riff(&[fmt(FORMAT_PCM, 2, 44_100, 16), chunk(b"data", &[0; 4])]);
+        bytes[RIFF_HEADER_SIZE + CHUNK_HEADER_SIZE + 12] = 3; // Block align
+        assert_eq!(parse(&bytes), Err(Error::InvalidBlockAlign(3)));
+    }
+}
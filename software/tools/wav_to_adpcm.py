@@ -0,0 +1,91 @@
+#!/usr/bin/env python3
+"""Encodes a 16 bit PCM WAV file as IMA ADPCM (WAVE_FORMAT_IMA_ADPCM, 4:1).
+
+Usage: wav_to_adpcm.py input.wav output.wav [block bytes per channel, default 512]
+
+The firmware decodes the blocks on the fly, see src/adpcm.rs.
+"""
+
+import struct
+import sys
+import wave
+
+STEPS = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66, 73, 80, 88, 97,
+    107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449, 494, 544, 598, 658, 724, 796,
+    876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272, 2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871,
+    5358, 5894, 6484, 7132, 7845, 8630, 9493, 10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623,
+    27086, 29794, 32767,
+]
+INDEX_ADJUST = [-1, -1, -1, -1, 2, 4, 6, 8]
+
+
+class Channel:
+    def __init__(self):
+        self.predictor = 0
+        self.index = 0
+
+    def encode(self, sample):
+        step = STEPS[self.index]
+        diff = sample - self.predictor
+        nibble = 8 if diff < 0 else 0
+        diff = abs(diff)
+        delta = step >> 3
+        for bit in (4, 2, 1):
+            if diff >= step:
+                nibble |= bit
+                diff -= step
+                delta += step
+            step >>= 1
+        self.predictor += -delta if nibble & 8 else delta
+        self.predictor = max(-32768, min(32767, self.predictor))
+        self.index = max(0, min(88, self.index + INDEX_ADJUST[nibble & 7]))
+        return nibble
+
+
+def encode(frames, channels, block_align):
+    """frames is a list of tuples of one sample per channel"""
+    samples_per_block = (block_align - 4 * channels) * 2 // channels + 1
+    states = [Channel() for _ in range(channels)]
+    out = bytearray()
+    for start in range(0, len(frames), samples_per_block):
+        block = frames[start:start + samples_per_block]
+        # The header holds the first sample uncompressed
+        for channel, state in enumerate(states):
+            state.predictor = block[0][channel]
+            out += struct.pack('<hBB', state.predictor, state.index, 0)
+        # Groups of 8 samples per channel, 4 bytes each, low nibble first
+        rest = block[1:]
+        for group in range(0, len(rest), 8):
+            chunk = rest[group:group + 8]
+            chunk += [chunk[-1]] * (8 - len(chunk))
+            for channel, state in enumerate(states):
+                nibbles = [state.encode(frame[channel]) for frame in chunk]
+                out += bytes(nibbles[i] | nibbles[i + 1] << 4 for i in range(0, 8, 2))
+    return out, samples_per_block
+
+
+def main():
+    source, target = sys.argv[1], sys.argv[2]
+    block_bytes = int(sys.argv[3]) if len(sys.argv) > 3 else 512
+    with wave.open(source) as wav:
+        assert wav.getsampwidth() == 2, 'only 16 bit PCM can be encoded'
+        channels, rate = wav.getnchannels(), wav.getframerate()
+        raw = wav.readframes(wav.getnframes())
+    samples = struct.unpack('<%dh' % (len(raw) // 2), raw)
+    frames = [samples[i:i + channels] for i in range(0, len(samples), channels)]
+
+    block_align = block_bytes * channels
+    data, samples_per_block = encode(frames, channels, block_align)
+    fmt = struct.pack('<HHIIHHHH', 0x11, channels, rate, rate * block_align // samples_per_block, block_align, 4, 2,
+                      samples_per_block)
+    fact = struct.pack('<I', len(frames))
+    chunks = b'fmt ' + struct.pack('<I', len(fmt)) + fmt
+    chunks += b'fact' + struct.pack('<I', len(fact)) + fact
+    chunks += b'data' + struct.pack('<I', len(data)) + data + b'\0' * (len(data) % 2)
+    with open(target, 'wb') as out:
+        out.write(b'RIFF' + struct.pack('<I', len(chunks) + 4) + b'WAVE' + chunks)
+
+
+if __name__ == '__main__':
+    main()